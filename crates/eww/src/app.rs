@@ -3,16 +3,20 @@ use crate::{
     EwwPaths,
 };
 use anyhow::*;
+use codespan_reporting::diagnostic::Diagnostic;
 use debug_stub_derive::*;
 use eww_shared_util::VarName;
 use gdk::WindowExt;
 use gtk::{ContainerExt, CssProviderExt, GtkWindowExt, StyleContextExt, WidgetExt};
 use itertools::Itertools;
-use simplexpr::dynval::DynVal;
-use std::collections::{HashMap, HashSet};
+use simplexpr::{dynval::DynVal, SimplExpr};
+use std::collections::{BTreeMap, HashMap};
 use tokio::sync::mpsc::UnboundedSender;
 use yuck::{
-    config::window_geometry::{AnchorPoint, WindowGeometry},
+    config::{
+        script_var_definition::{ScriptVarDefinition, VarSource},
+        window_geometry::{AnchorPoint, WindowGeometry},
+    },
     value::Coords,
 };
 
@@ -25,16 +29,19 @@ pub enum DaemonCommand {
     UpdateCss(String),
     OpenMany {
         windows: Vec<String>,
+        args: Vec<(VarName, DynVal)>,
         should_toggle: bool,
         sender: DaemonResponseSender,
     },
     OpenWindow {
         window_name: String,
+        instance_id: Option<String>,
         pos: Option<Coords>,
         size: Option<Coords>,
         anchor: Option<AnchorPoint>,
         screen: Option<i32>,
         should_toggle: bool,
+        args: Vec<(VarName, DynVal)>,
         sender: DaemonResponseSender,
     },
     CloseWindows {
@@ -45,15 +52,60 @@ pub enum DaemonCommand {
     CloseAll,
     PrintState {
         all: bool,
+        instance_id: Option<String>,
+        sender: DaemonResponseSender,
+    },
+    GetVar {
+        names: Vec<VarName>,
+        expr: Option<SimplExpr>,
         sender: DaemonResponseSender,
     },
+    /// Keep sending the values of the given variables to the sender whenever they change
+    ListenVars {
+        names: Vec<VarName>,
+        sender: DaemonResponseSender,
+    },
+    /// Drop the subscriptions of clients that disconnected
+    RemoveClosedSubscriptions,
     PrintDebug(DaemonResponseSender),
     PrintWindows(DaemonResponseSender),
 }
 
+/// Everything needed to open an instance of a window.
+/// This is kept around such that the instance can be reopened when reloading the configuration.
+#[derive(Debug, Clone)]
+pub struct WindowArguments {
+    /// Name of the window definition
+    pub window_name: String,
+    /// Id of this instance of the window. Defaults to the name of the window.
+    pub instance_id: String,
+    pub args: HashMap<VarName, DynVal>,
+    pub pos: Option<Coords>,
+    pub size: Option<Coords>,
+    pub monitor: Option<i32>,
+    pub anchor: Option<AnchorPoint>,
+}
+
+impl WindowArguments {
+    /// Arguments for the default instance of a window, without any overrides
+    pub fn new(window_name: String, args: HashMap<VarName, DynVal>) -> Self {
+        WindowArguments {
+            instance_id: window_name.clone(),
+            window_name,
+            args,
+            pos: None,
+            size: None,
+            monitor: None,
+            anchor: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct EwwWindow {
     pub name: String,
+    /// The arguments this instance of the window was opened with
+    pub arguments: WindowArguments,
     pub definition: config::EwwWindowDefinition,
     pub gtk_window: gtk::Window,
 }
@@ -68,10 +120,14 @@ impl EwwWindow {
 pub struct App {
     pub eww_state: eww_state::EwwState,
     pub eww_config: config::EwwConfig,
+    /// Open window instances, by their instance id
     pub open_windows: HashMap<String, EwwWindow>,
-    /// Window names that are supposed to be open, but failed.
+    /// Window instances that are supposed to be open, but failed, by their instance id.
     /// When reloading the config, these should be opened again.
-    pub failed_windows: HashSet<String>,
+    pub failed_windows: HashMap<String, WindowArguments>,
+    /// Warnings about the configuration the daemon was started with.
+    /// These are returned in the response to the command that started the daemon, if it opens any windows.
+    pub initial_config_warnings: Vec<Diagnostic<usize>>,
     pub css_provider: gtk::CssProvider,
 
     #[debug_stub = "ScriptVarHandler(...)"]
@@ -82,6 +138,46 @@ pub struct App {
     pub paths: EwwPaths,
 }
 
+/// The state of the daemon, as printed by `eww debug` when json output is requested
+#[derive(Debug, serde::Serialize)]
+struct DebugInfo {
+    windows: Vec<WindowDebugInfo>,
+    variables: BTreeMap<String, String>,
+    script_vars: Vec<ScriptVarDebugInfo>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct WindowDebugInfo {
+    instance_id: String,
+    window_name: String,
+    args: BTreeMap<String, String>,
+    /// Whether opening the window failed, in which case it is opened again when reloading the config
+    failed: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum ScriptVarDebugInfo {
+    Poll {
+        name: String,
+        /// The shell command that is run, or `None` for the builtin variables that are computed by eww itself
+        command: Option<String>,
+        interval_ms: u64,
+    },
+    Listen {
+        name: String,
+        command: String,
+    },
+}
+
+impl ScriptVarDebugInfo {
+    fn name(&self) -> &str {
+        match self {
+            ScriptVarDebugInfo::Poll { name, .. } | ScriptVarDebugInfo::Listen { name, .. } => name,
+        }
+    }
+}
+
 impl App {
     /// Handle a DaemonCommand event.
     pub fn handle_command(&mut self, event: DaemonCommand) {
@@ -96,9 +192,14 @@ impl App {
                 }
                 DaemonCommand::ReloadConfigAndCss(sender) => {
                     let mut errors = Vec::new();
+                    let mut warnings = Vec::new();
 
                     let config_result = config::read_from_file(&self.paths.get_yuck_path());
-                    if let Err(e) = config_result.and_then(|new_config| self.load_config(new_config)) {
+                    let config_result = config_result.and_then(|new_config| {
+                        warnings = new_config.get_warnings().to_vec();
+                        self.load_config(new_config)
+                    });
+                    if let Err(e) = config_result {
                         errors.push(e)
                     }
                     let css_result = crate::util::parse_scss_from_file(&self.paths.get_eww_scss_path());
@@ -106,7 +207,7 @@ impl App {
                         errors.push(e)
                     }
 
-                    sender.respond_with_error_list(errors)?;
+                    sender.respond_with_error_list_and_warnings(errors, &warnings)?;
                 }
                 DaemonCommand::UpdateConfig(config) => {
                     self.load_config(config)?;
@@ -121,61 +222,174 @@ impl App {
                 }
                 DaemonCommand::CloseAll => {
                     log::info!("Received close command, closing all windows");
-                    for (window_name, _window) in self.open_windows.clone() {
-                        self.close_window(&window_name)?;
+                    for (instance_id, _window) in self.open_windows.clone() {
+                        self.close_window(&instance_id)?;
                     }
                 }
-                DaemonCommand::OpenMany { windows, should_toggle, sender } => {
+                DaemonCommand::OpenMany { windows, args, should_toggle, sender } => {
                     let errors = windows
                         .iter()
                         .map(|w| {
                             if should_toggle && self.open_windows.contains_key(w) {
                                 self.close_window(w)
                             } else {
-                                self.open_window(w, None, None, None, None)
+                                // only pass the arguments that are declared by the window
+                                let window_args = match self.eww_config.get_window(w) {
+                                    Ok(def) => args
+                                        .iter()
+                                        .filter(|(name, _)| def.expected_args.iter().any(|arg| arg.name.0 == name.0))
+                                        .cloned()
+                                        .collect(),
+                                    Err(_) => HashMap::new(),
+                                };
+                                self.open_window(WindowArguments::new(w.clone(), window_args))
                             }
                         })
-                        .filter_map(Result::err);
-                    sender.respond_with_error_list(errors)?;
+                        .filter_map(Result::err)
+                        .collect::<Vec<_>>();
+                    let warnings = std::mem::take(&mut self.initial_config_warnings);
+                    sender.respond_with_error_list_and_warnings(errors, &warnings)?;
                 }
-                DaemonCommand::OpenWindow { window_name, pos, size, anchor, screen: monitor, should_toggle, sender } => {
-                    let result = if should_toggle && self.open_windows.contains_key(&window_name) {
-                        self.close_window(&window_name)
+                DaemonCommand::OpenWindow {
+                    window_name,
+                    instance_id,
+                    pos,
+                    size,
+                    anchor,
+                    screen: monitor,
+                    should_toggle,
+                    args,
+                    sender,
+                } => {
+                    let instance_id = instance_id.unwrap_or_else(|| window_name.clone());
+                    let result = if should_toggle && self.open_windows.contains_key(&instance_id) {
+                        self.close_window(&instance_id)
                     } else {
-                        self.open_window(&window_name, pos, size, monitor, anchor)
+                        self.open_window(WindowArguments {
+                            window_name,
+                            instance_id,
+                            args: args.into_iter().collect(),
+                            pos,
+                            size,
+                            monitor,
+                            anchor,
+                        })
                     };
-                    sender.respond_with_result(result)?;
+                    let warnings = std::mem::take(&mut self.initial_config_warnings);
+                    sender.respond_with_error_list_and_warnings(result.err(), &warnings)?;
                 }
                 DaemonCommand::CloseWindows { windows, sender } => {
                     let errors = windows.iter().map(|window| self.close_window(&window)).filter_map(Result::err);
                     sender.respond_with_error_list(errors)?;
                 }
-                DaemonCommand::PrintState { all, sender } => {
+                DaemonCommand::PrintState { all, instance_id, sender } => {
                     let vars = self.eww_state.get_variables().iter();
-                    let output = if all {
-                        vars.map(|(key, value)| format!("{}: {}", key, value)).join("\n")
-                    } else {
-                        vars.filter(|(x, _)| self.eww_state.referenced_vars().any(|var| x == &var))
-                            .map(|(key, value)| format!("{}: {}", key, value))
-                            .join("\n")
+                    let vars: Result<Vec<_>, _> = match instance_id {
+                        Some(instance_id) if !self.open_windows.contains_key(&instance_id) => {
+                            Err(format!("No window with the id '{}' is open", instance_id))
+                        }
+                        Some(instance_id) => {
+                            let referenced_vars = self.eww_state.vars_referenced_in(&instance_id);
+                            Ok(vars.filter(|(x, _)| referenced_vars.contains(x)).collect())
+                        }
+                        None if all => Ok(vars.collect()),
+                        None => Ok(vars.filter(|(x, _)| self.eww_state.referenced_vars().any(|var| x == &var)).collect()),
                     };
-                    sender.send_success(output)?
+                    match vars {
+                        Ok(vars) => {
+                            let output = vars.iter().map(|(key, value)| format!("{}: {}", key, value)).join("\n");
+                            let data: HashMap<_, _> =
+                                vars.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+                            sender.send_success_with_data(output, &data)?
+                        }
+                        Err(err) => sender.send_failure(err)?,
+                    }
                 }
                 DaemonCommand::PrintWindows(sender) => {
-                    let output = self
+                    let windows = self
                         .eww_config
                         .get_windows()
                         .keys()
+                        .sorted()
                         .map(|window_name| {
-                            let is_open = self.open_windows.contains_key(window_name);
-                            format!("{}{}", if is_open { "*" } else { "" }, window_name)
+                            let instance_ids = self
+                                .open_windows
+                                .values()
+                                .filter(|window| &window.name == window_name)
+                                .map(|window| window.arguments.instance_id.clone())
+                                .sorted()
+                                .collect::<Vec<_>>();
+                            let failed_instance_ids = self
+                                .failed_windows
+                                .values()
+                                .filter(|arguments| &arguments.window_name == window_name)
+                                .map(|arguments| arguments.instance_id.clone())
+                                .sorted()
+                                .collect::<Vec<_>>();
+                            (window_name, instance_ids, failed_instance_ids)
+                        })
+                        .collect::<Vec<_>>();
+                    let output = windows
+                        .iter()
+                        .map(|(window_name, instance_ids, _)| {
+                            let is_open = !instance_ids.is_empty();
+                            // only list the instance ids when they differ from the window name
+                            let instances = if instance_ids.iter().any(|id| &id != window_name) {
+                                format!(" [{}]", instance_ids.iter().join(", "))
+                            } else {
+                                String::new()
+                            };
+                            format!("{}{}{}", if is_open { "*" } else { "" }, window_name, instances)
                         })
                         .join("\n");
-                    sender.send_success(output)?
+                    let data = windows
+                        .iter()
+                        .map(|(window_name, instance_ids, failed_instance_ids)| {
+                            serde_json::json!({
+                                "name": window_name,
+                                "open": !instance_ids.is_empty(),
+                                "instances": instance_ids,
+                                "failed_instances": failed_instance_ids,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    sender.send_success_with_data(output, &data)?
                 }
+                DaemonCommand::GetVar { names, expr, sender } => {
+                    let vars = self.eww_state.get_variables();
+                    let result: Result<(String, serde_json::Value), String> = match expr {
+                        Some(expr) => expr
+                            .eval(vars)
+                            .map(|value| (value.to_string(), serde_json::Value::String(value.to_string())))
+                            .map_err(|err| err.to_string()),
+                        None => names
+                            .iter()
+                            .map(|name| {
+                                vars.get(name).map(|value| (name, value)).ok_or_else(|| format!("Unknown variable '{}'", name))
+                            })
+                            .collect::<Result<Vec<_>, _>>()
+                            .map(|values| {
+                                let output = values.iter().map(|(_, value)| value).join("\n");
+                                let data = values.iter().map(|(name, value)| (name.to_string(), value.to_string().into()));
+                                (output, serde_json::Value::Object(data.collect()))
+                            }),
+                    };
+                    match result {
+                        Ok((output, data)) => sender.send_success_with_data(output, &data)?,
+                        Err(err) => sender.send_failure(err)?,
+                    }
+                }
+                DaemonCommand::ListenVars { names, sender } => {
+                    if let Some(name) = names.iter().find(|name| !self.eww_state.get_variables().contains_key(*name)) {
+                        sender.send_failure(format!("Unknown variable '{}'", name))?
+                    } else {
+                        self.eww_state.subscribe(eww_state::VariableSubscription::new(names, sender))?
+                    }
+                }
+                DaemonCommand::RemoveClosedSubscriptions => self.eww_state.remove_closed_subscriptions(),
                 DaemonCommand::PrintDebug(sender) => {
                     let output = format!("{:#?}", &self);
-                    sender.send_success(output)?
+                    sender.send_success_with_data(output, &self.debug_info())?
                 }
             }
         };
@@ -185,6 +399,44 @@ impl App {
         }
     }
 
+    /// Collect the state of the daemon, for the json output of `eww debug`
+    fn debug_info(&self) -> DebugInfo {
+        let open_windows = self.open_windows.values().map(|window| (&window.arguments, false));
+        let failed_windows = self.failed_windows.values().map(|arguments| (arguments, true));
+        let windows = open_windows
+            .chain(failed_windows)
+            .map(|(arguments, failed)| WindowDebugInfo {
+                instance_id: arguments.instance_id.clone(),
+                window_name: arguments.window_name.clone(),
+                args: arguments.args.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+                failed,
+            })
+            .sorted_by(|a, b| a.instance_id.cmp(&b.instance_id))
+            .collect();
+        let variables =
+            self.eww_state.get_variables().iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        let script_vars = self
+            .eww_config
+            .get_script_vars()
+            .values()
+            .map(|var| match var {
+                ScriptVarDefinition::Poll(var) => ScriptVarDebugInfo::Poll {
+                    name: var.name.to_string(),
+                    command: match &var.command {
+                        VarSource::Shell(_, command) => Some(command.clone()),
+                        VarSource::Function(_) => None,
+                    },
+                    interval_ms: var.interval.as_millis() as u64,
+                },
+                ScriptVarDefinition::Listen(var) => {
+                    ScriptVarDebugInfo::Listen { name: var.name.to_string(), command: var.command.clone() }
+                }
+            })
+            .sorted_by(|a, b| a.name().cmp(b.name()))
+            .collect();
+        DebugInfo { windows, variables, script_vars }
+    }
+
     fn stop_application(&mut self) {
         self.script_var_handler.stop_all();
         for (_, window) in self.open_windows.drain() {
@@ -197,63 +449,61 @@ impl App {
         self.eww_state.update_variable(fieldname, value)
     }
 
-    fn close_window(&mut self, window_name: &String) -> Result<()> {
-        for unused_var in self.variables_only_used_in(window_name) {
+    fn close_window(&mut self, instance_id: &String) -> Result<()> {
+        for unused_var in self.variables_only_used_in(instance_id) {
             log::debug!("stopping for {}", &unused_var);
             self.script_var_handler.stop_for_variable(unused_var.clone());
         }
 
         self.open_windows
-            .remove(window_name)
-            .with_context(|| format!("Tried to close window named '{}', but no such window was open", window_name))?
+            .remove(instance_id)
+            .with_context(|| format!("Tried to close window with id '{}', but no such window was open", instance_id))?
             .close();
 
-        self.eww_state.clear_window_state(window_name);
+        self.eww_state.clear_window_state(instance_id);
 
         Ok(())
     }
 
-    fn open_window(
-        &mut self,
-        window_name: &String,
-        pos: Option<Coords>,
-        size: Option<Coords>,
-        monitor: Option<i32>,
-        anchor: Option<AnchorPoint>,
-    ) -> Result<()> {
-        self.failed_windows.remove(window_name);
-        log::info!("Opening window {}", window_name);
+    fn open_window(&mut self, arguments: WindowArguments) -> Result<()> {
+        let instance_id = arguments.instance_id.clone();
+        let window_name = arguments.window_name.clone();
+        self.failed_windows.remove(&instance_id);
+        log::info!("Opening window {} as {}", window_name, instance_id);
 
-        // if an instance of this is already running, close it
-        let _ = self.close_window(window_name);
+        // if an instance with this id is already running, close it
+        let _ = self.close_window(&instance_id);
 
         let open_result: Result<_> = try {
-            let mut window_def = self.eww_config.get_window(window_name)?.clone();
-            window_def.geometry = window_def.geometry.map(|x| x.override_if_given(anchor, pos, size));
+            let window_def = self.eww_config.get_window(&window_name)?.clone();
+            let mut window_def =
+                config::EwwWindowDefinition::generate(self.eww_config.get_widget_definitions(), window_def, &arguments.args)?;
+            window_def.geometry =
+                window_def.geometry.map(|x| x.override_if_given(arguments.anchor, arguments.pos, arguments.size));
 
             let root_widget =
-                window_def.widget.render(&mut self.eww_state, window_name, &self.eww_config.get_widget_definitions())?;
+                window_def.widget.render(&mut self.eww_state, &instance_id, &self.eww_config.get_widget_definitions())?;
 
             root_widget.get_style_context().add_class(&window_name.to_string());
 
-            let monitor_geometry = get_monitor_geometry(monitor.or(window_def.monitor_number))?;
+            let monitor_geometry = get_monitor_geometry(arguments.monitor.or(window_def.monitor_number))?;
 
-            let eww_window = initialize_window(monitor_geometry, root_widget, window_def)?;
+            let eww_window = initialize_window(monitor_geometry, root_widget, window_def, arguments.clone())?;
 
-            self.open_windows.insert(window_name.clone(), eww_window);
+            self.open_windows.insert(instance_id.clone(), eww_window);
 
             // initialize script var handlers for variables that where not used before opening this window.
             // TODO somehow make this less shit
             for newly_used_var in
-                self.variables_only_used_in(window_name).filter_map(|var| self.eww_config.get_script_var(var).ok())
+                self.variables_only_used_in(&instance_id).filter_map(|var| self.eww_config.get_script_var(var).ok())
             {
                 self.script_var_handler.add(newly_used_var.clone());
             }
         };
 
         if let Err(err) = open_result {
-            self.failed_windows.insert(window_name.to_string());
-            Err(err).with_context(|| format!("failed to open window `{}`", window_name))
+            self.failed_windows.insert(instance_id.clone(), arguments);
+            Err(err).with_context(|| format!("failed to open window `{}` with id `{}`", window_name, instance_id))
         } else {
             Ok(())
         }
@@ -268,16 +518,20 @@ impl App {
         // refresh script-var poll stuff
         self.script_var_handler.stop_all();
 
-
         log::trace!("loading config: {:#?}", config);
 
         self.eww_config = config;
         self.eww_state.clear_all_window_states();
 
-        let window_names: Vec<String> =
-            self.open_windows.keys().cloned().chain(self.failed_windows.iter().cloned()).dedup().collect();
-        for window_name in &window_names {
-            self.open_window(&window_name, None, None, None, None)?;
+        let windows: Vec<WindowArguments> = self
+            .open_windows
+            .values()
+            .map(|window| window.arguments.clone())
+            .chain(self.failed_windows.values().cloned())
+            .unique_by(|arguments| arguments.instance_id.clone())
+            .collect();
+        for arguments in windows {
+            self.open_window(arguments)?;
         }
         Ok(())
     }
@@ -289,21 +543,21 @@ impl App {
 
     /// Get all variable names that are currently referenced in any of the open windows.
     pub fn get_currently_used_variables(&self) -> impl Iterator<Item = &VarName> {
-        self.open_windows.keys().flat_map(move |window_name| self.eww_state.vars_referenced_in(window_name))
+        self.open_windows.keys().flat_map(move |instance_id| self.eww_state.vars_referenced_in(instance_id))
     }
 
-    /// Get all variables mapped to a list of windows they are being used in.
+    /// Get all variables mapped to a list of window instances they are being used in.
     pub fn currently_used_variables<'a>(&'a self) -> HashMap<&'a VarName, Vec<&'a String>> {
         let mut vars: HashMap<&'a VarName, Vec<_>> = HashMap::new();
-        for window_name in self.open_windows.keys() {
-            for var in self.eww_state.vars_referenced_in(window_name) {
-                vars.entry(var).and_modify(|l| l.push(window_name)).or_insert_with(|| vec![window_name]);
+        for instance_id in self.open_windows.keys() {
+            for var in self.eww_state.vars_referenced_in(instance_id) {
+                vars.entry(var).and_modify(|l| l.push(instance_id)).or_insert_with(|| vec![instance_id]);
             }
         }
         vars
     }
 
-    /// Get all variables that are only used in the given window.
+    /// Get all variables that are only used in the given window instance.
     pub fn variables_only_used_in<'a>(&'a self, window: &'a String) -> impl Iterator<Item = &'a VarName> {
         self.currently_used_variables()
             .into_iter()
@@ -316,6 +570,7 @@ fn initialize_window(
     monitor_geometry: gdk::Rectangle,
     root_widget: gtk::Widget,
     window_def: config::EwwWindowDefinition,
+    arguments: WindowArguments,
 ) -> Result<EwwWindow> {
     let window = display_backend::initialize_window(&window_def, monitor_geometry)
         .with_context(|| format!("monitor {} is unavailable", window_def.monitor_number.unwrap()))?;
@@ -352,7 +607,7 @@ fn initialize_window(
         }
         display_backend::set_xprops(&window, monitor_geometry, &window_def)?;
     }
-    Ok(EwwWindow { name: window_def.name.clone(), definition: window_def, gtk_window: window })
+    Ok(EwwWindow { name: window_def.name.clone(), arguments, definition: window_def, gtk_window: window })
 }
 
 /// Apply the provided window-positioning rules to the window.
@@ -1,8 +1,13 @@
 use anyhow::*;
+use codespan_reporting::diagnostic::Diagnostic;
 use eww_shared_util::VarName;
 use std::{collections::HashMap, path::Path};
-use yuck::config::{
-    file_provider::YuckFiles, script_var_definition::ScriptVarDefinition, widget_definition::WidgetDefinition, Config,
+use yuck::{
+    config::{
+        file_provider::YuckFiles, script_var_definition::ScriptVarDefinition, validate::KnownTypes,
+        widget_definition::WidgetDefinition, window_definition::WindowDefinition, Config,
+    },
+    format_diagnostic::ToDiagnostic,
 };
 
 use simplexpr::dynval::DynVal;
@@ -21,14 +26,22 @@ pub fn read_from_file(path: impl AsRef<Path>) -> Result<EwwConfig> {
 #[derive(Debug, Clone)]
 pub struct EwwConfig {
     widgets: HashMap<String, WidgetDefinition>,
-    windows: HashMap<String, EwwWindowDefinition>,
+    windows: HashMap<String, WindowDefinition>,
     initial_variables: HashMap<VarName, DynVal>,
     script_vars: HashMap<VarName, ScriptVarDefinition>,
+    /// Problems found while validating the configuration that don't prevent it from being loaded, like likely type mismatches
+    warnings: Vec<Diagnostic<usize>>,
 }
 
 impl Default for EwwConfig {
     fn default() -> Self {
-        Self { widgets: HashMap::new(), windows: HashMap::new(), initial_variables: HashMap::new(), script_vars: HashMap::new() }
+        Self {
+            widgets: HashMap::new(),
+            windows: HashMap::new(),
+            initial_variables: HashMap::new(),
+            script_vars: HashMap::new(),
+            warnings: Vec::new(),
+        }
     }
 }
 
@@ -40,18 +53,33 @@ impl EwwConfig {
         let config = Config::generate_from_main_file(files, path)?;
 
         // run some validations on the configuration
-        yuck::config::validate::validate(&config, super::inbuilt::get_inbuilt_vars().keys().cloned().collect())?;
+        let known_types = KnownTypes {
+            globals: super::inbuilt::get_inbuilt_var_types(),
+            widget_attrs: crate::widgets::builtin_widget_attr_types(),
+        };
+        let warnings = yuck::config::validate::validate(&config, &known_types)?
+            .into_iter()
+            .map(|type_mismatch| type_mismatch.to_diagnostic())
+            .collect::<Vec<_>>();
+        for warning in &warnings {
+            log::warn!("{}", error_handling_ctx::stringify_diagnostic_with_files(files, warning.clone())?);
+        }
 
         let Config { widget_definitions, window_definitions, var_definitions, mut script_vars } = config;
         script_vars.extend(crate::config::inbuilt::get_inbuilt_vars());
+
+        // windows are only generated once they are opened with their arguments.
+        // To still report errors when loading the config, generate the ones that can be opened without any arguments here.
+        for window in window_definitions.values().filter(|window| window.expected_args.iter().all(|arg| arg.optional)) {
+            EwwWindowDefinition::generate(&widget_definitions, window.clone(), &HashMap::new())?;
+        }
+
         Ok(EwwConfig {
-            windows: window_definitions
-                .into_iter()
-                .map(|(name, window)| Ok((name, EwwWindowDefinition::generate(&widget_definitions, window)?)))
-                .collect::<Result<HashMap<_, _>>>()?,
+            windows: window_definitions,
             widgets: widget_definitions,
             initial_variables: var_definitions.into_iter().map(|(k, v)| (k, v.initial_value)).collect(),
             script_vars,
+            warnings,
         })
     }
 
@@ -66,11 +94,11 @@ impl EwwConfig {
         Ok(vars)
     }
 
-    pub fn get_windows(&self) -> &HashMap<String, EwwWindowDefinition> {
+    pub fn get_windows(&self) -> &HashMap<String, WindowDefinition> {
         &self.windows
     }
 
-    pub fn get_window(&self, name: &String) -> Result<&EwwWindowDefinition> {
+    pub fn get_window(&self, name: &String) -> Result<&WindowDefinition> {
         self.windows.get(name).with_context(|| {
             format!(
                 "No window named '{}' exists in config.\nThis may also be caused by your config failing to load properly, \
@@ -84,6 +112,15 @@ impl EwwConfig {
         self.script_vars.get(name).with_context(|| format!("No script var named '{}' exists", name))
     }
 
+    pub fn get_script_vars(&self) -> &HashMap<VarName, ScriptVarDefinition> {
+        &self.script_vars
+    }
+
+    /// Warnings about the configuration, which are reported to the user when it is loaded
+    pub fn get_warnings(&self) -> &[Diagnostic<usize>] {
+        &self.warnings
+    }
+
     pub fn get_widget_definitions(&self) -> &HashMap<String, WidgetDefinition> {
         &self.widgets
     }
@@ -1,6 +1,6 @@
 use std::{collections::HashMap, time::Duration};
 
-use simplexpr::dynval::DynVal;
+use simplexpr::{dynval::DynVal, functions::ValueType};
 use yuck::config::script_var_definition::{PollScriptVar, ScriptVarDefinition, VarSource};
 
 use crate::config::system_stats::*;
@@ -21,7 +21,11 @@ macro_rules! builtin_vars {
     }}}
 
 pub fn get_inbuilt_vars() -> HashMap<VarName, ScriptVarDefinition> {
-    builtin_vars! {Duration::new(2, 0),
+    let mut vars = builtin_vars! {Duration::new(1, 0),
+        // @desc EWW_TIME - The current unix timestamp in seconds, updated every second.\nExample: `{formattime(EWW_TIME, "%H:%M")}`
+        "EWW_TIME" => || Ok(DynVal::from(get_time()?.to_string())),
+    };
+    vars.extend(builtin_vars! {Duration::new(2, 0),
         // @desc EWW_TEMPS - Heat of the components in Celcius
         "EWW_TEMPS" => || Ok(DynVal::from(get_temperatures())),
 
@@ -47,5 +51,19 @@ pub fn get_inbuilt_vars() -> HashMap<VarName, ScriptVarDefinition> {
 
         // @desc EWW_NET - Bytes up/down on all interfaces
         "EWW_NET" => || Ok(DynVal::from(net())),
+    });
+    vars
+}
+
+/// The types of the values of the magic variables, used to check the expressions in the config for likely type errors
+pub fn get_inbuilt_var_types() -> HashMap<VarName, ValueType> {
+    maplit::hashmap! {
+        VarName::from("EWW_TIME") => ValueType::Int,
+        VarName::from("EWW_TEMPS") => ValueType::Json,
+        VarName::from("EWW_RAM") => ValueType::Json,
+        VarName::from("EWW_DISK") => ValueType::Json,
+        VarName::from("EWW_BATTERY") => ValueType::Json,
+        VarName::from("EWW_CPU") => ValueType::Json,
+        VarName::from("EWW_NET") => ValueType::Json,
     }
 }
@@ -173,3 +173,7 @@ pub fn net() -> String {
     );
     interfaces
 }
+
+pub fn get_time() -> Result<u64> {
+    Ok(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs())
+}
@@ -1,8 +1,11 @@
 use std::collections::HashMap;
 
 use anyhow::*;
+use eww_shared_util::VarName;
+use simplexpr::{dynval::DynVal, SimplExpr};
 use yuck::config::{
     backend_window_options::BackendWindowOptions,
+    validate::validate_arg_type,
     widget_definition::WidgetDefinition,
     window_definition::{WindowDefinition, WindowStacking},
     window_geometry::WindowGeometry,
@@ -25,14 +28,42 @@ pub struct EwwWindowDefinition {
 }
 
 impl EwwWindowDefinition {
-    pub fn generate(defs: &HashMap<String, WidgetDefinition>, window: WindowDefinition) -> Result<Self> {
+    /// Generate the window, substituting the given arguments into its widget tree.
+    pub fn generate(
+        defs: &HashMap<String, WidgetDefinition>,
+        window: WindowDefinition,
+        args: &HashMap<VarName, DynVal>,
+    ) -> Result<Self> {
+        if let Some(unknown_arg) = args.keys().find(|name| !window.expected_args.iter().any(|arg| arg.name.0 == name.0)) {
+            bail!("Window `{}` has no argument named `{}`", window.name, unknown_arg);
+        }
+
+        let mut local_env = HashMap::new();
+        for arg in window.expected_args.iter() {
+            let var_name = VarName(arg.name.0.clone());
+            let value = match args.get(&var_name) {
+                Some(value) => {
+                    validate_arg_type(&window.name, arg, value, None)?;
+                    SimplExpr::Value(value.clone())
+                }
+                None if arg.optional => arg.default.clone().unwrap_or_else(|| SimplExpr::literal(arg.span, String::new())),
+                None => bail!(
+                    "Missing argument `{}` for window `{}`. Pass it like so: `--arg {}=value`",
+                    arg.name,
+                    window.name,
+                    arg.name
+                ),
+            };
+            local_env.insert(var_name, value);
+        }
+
         Ok(EwwWindowDefinition {
             name: window.name,
             geometry: window.geometry,
             stacking: window.stacking,
             monitor_number: window.monitor_number,
             resizable: window.resizable,
-            widget: widget_node::generate_generic_widget_node(defs, &HashMap::new(), window.widget)?,
+            widget: widget_node::generate_generic_widget_node(defs, &local_env, window.widget)?,
             backend_options: window.backend_options,
         })
     }
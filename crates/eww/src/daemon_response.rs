@@ -1,24 +1,37 @@
 use anyhow::*;
+use codespan_reporting::diagnostic::Diagnostic;
 use itertools::Itertools;
 
 use crate::error_handling_ctx;
 
-/// Response that the app may send as a response to a event.
-/// This is used in `DaemonCommand`s that contain a response sender.
-#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, derive_more::Display)]
-pub enum DaemonResponse {
-    Success(String),
-    Failure(String),
+pub use eww_client::{DaemonResponse, ResponseOutput};
+
+/// Output describing the given errors, including diagnostics where available, followed by the given warnings
+pub fn errors_output(errors: &[anyhow::Error], warnings: &[Diagnostic<usize>]) -> ResponseOutput {
+    let text = errors.iter().map(error_handling_ctx::format_error).chain(warnings.iter().map(format_warning)).join("\n");
+    let json = serde_json::json!({
+        "errors": errors.iter().map(error_handling_ctx::error_to_json).collect::<Vec<_>>(),
+        "warnings": warnings_to_json(warnings),
+    });
+    ResponseOutput { text, json: json.to_string() }
 }
 
-impl DaemonResponse {
-    pub fn is_success(&self) -> bool {
-        matches!(self, DaemonResponse::Success(_))
+/// Output describing the given warnings, or empty output if there are none
+pub fn warnings_output(warnings: &[Diagnostic<usize>]) -> ResponseOutput {
+    if warnings.is_empty() {
+        return ResponseOutput::empty();
     }
+    let text = warnings.iter().map(format_warning).join("\n");
+    let json = serde_json::json!({ "warnings": warnings_to_json(warnings) });
+    ResponseOutput { text, json: json.to_string() }
+}
 
-    pub fn is_failure(&self) -> bool {
-        !self.is_success()
-    }
+fn format_warning(warning: &Diagnostic<usize>) -> String {
+    error_handling_ctx::stringify_diagnostic(warning.clone()).unwrap_or_else(|_| warning.message.clone())
+}
+
+fn warnings_to_json(warnings: &[Diagnostic<usize>]) -> Vec<serde_json::Value> {
+    warnings.iter().cloned().map(error_handling_ctx::diagnostic_to_json).collect()
 }
 
 #[derive(Debug)]
@@ -31,38 +44,71 @@ pub fn create_pair() -> (DaemonResponseSender, tokio::sync::mpsc::UnboundedRecei
 
 impl DaemonResponseSender {
     pub fn send_success(&self, s: String) -> Result<()> {
-        self.0.send(DaemonResponse::Success(s)).context("Failed to send success response from application thread")
+        self.send_success_output(ResponseOutput::text(s))
+    }
+
+    /// Send a successful response, which is represented by the given data when json output is requested.
+    pub fn send_success_with_data(&self, s: String, data: &impl serde::Serialize) -> Result<()> {
+        self.send_success_output(ResponseOutput::with_data(s, data)?)
     }
 
     pub fn send_failure(&self, s: String) -> Result<()> {
-        self.0.send(DaemonResponse::Failure(s)).context("Failed to send failure response from application thread")
+        self.send_failure_output(ResponseOutput::error_message(s))
+    }
+
+    fn send_success_output(&self, output: ResponseOutput) -> Result<()> {
+        self.0.send(DaemonResponse::Success(output)).context("Failed to send success response from application thread")
+    }
+
+    fn send_failure_output(&self, output: ResponseOutput) -> Result<()> {
+        self.0.send(DaemonResponse::Failure(output)).context("Failed to send failure response from application thread")
+    }
+
+    /// Check if the receiving side of the responses has been dropped, i.e. because the client disconnected.
+    pub fn is_closed(&self) -> bool {
+        self.0.is_closed()
     }
 
     /// Given a list of errors, respond with an error value if there are any errors, and respond with success otherwise.
     pub fn respond_with_error_list(&self, errors: impl IntoIterator<Item = anyhow::Error>) -> Result<()> {
-        let errors = errors.into_iter().map(|e| error_handling_ctx::format_error(&e)).join("\n");
+        let errors = errors.into_iter().collect::<Vec<_>>();
+        if errors.is_empty() {
+            self.send_success_output(ResponseOutput::empty())
+        } else {
+            self.respond_with_errors(&errors)
+        }
+    }
+
+    /// Like [DaemonResponseSender::respond_with_error_list], but including the given warnings in the response,
+    /// regardless of whether it is successful.
+    pub fn respond_with_error_list_and_warnings(
+        &self,
+        errors: impl IntoIterator<Item = anyhow::Error>,
+        warnings: &[Diagnostic<usize>],
+    ) -> Result<()> {
+        let errors = errors.into_iter().collect::<Vec<_>>();
         if errors.is_empty() {
-            self.send_success(String::new())
+            self.send_success_output(warnings_output(warnings))
         } else {
-            self.respond_with_error_msg(errors)
+            let output = errors_output(&errors, warnings);
+            println!("Action failed with error: {}", output);
+            self.send_failure_output(output)
         }
     }
 
     /// In case of an Err, send the error message to a sender.
     pub fn respond_with_result<T>(&self, result: Result<T>) -> Result<()> {
         match result {
-            Ok(_) => self.send_success(String::new()),
-            Err(e) => {
-                let formatted = error_handling_ctx::format_error(&e);
-                self.respond_with_error_msg(formatted)
-            }
+            Ok(_) => self.send_success_output(ResponseOutput::empty()),
+            Err(e) => self.respond_with_errors(&[e]),
         }
         .context("sending response from main thread")
     }
 
-    fn respond_with_error_msg(&self, msg: String) -> Result<()> {
-        println!("Action failed with error: {}", msg);
-        self.send_failure(msg)
+    fn respond_with_errors(&self, errors: &[anyhow::Error]) -> Result<()> {
+        let output = errors_output(errors, &[]);
+        println!("Action failed with error: {}", output);
+        self.send_failure_output(output)
     }
 }
 
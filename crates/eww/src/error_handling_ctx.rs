@@ -4,7 +4,8 @@
 use std::sync::{Arc, RwLock};
 
 use codespan_reporting::{
-    diagnostic::Diagnostic,
+    diagnostic::{Diagnostic, LabelStyle},
+    files::Files,
     term::{self, Chars},
 };
 use eww_shared_util::Span;
@@ -47,6 +48,43 @@ pub fn format_error(err: &anyhow::Error) -> String {
     anyhow_err_to_diagnostic(err).and_then(|diag| stringify_diagnostic(diag).ok()).unwrap_or_else(|| format!("{:?}", err))
 }
 
+/// Represent an error as json, including its diagnostic with resolved source locations, if available.
+pub fn error_to_json(err: &anyhow::Error) -> serde_json::Value {
+    serde_json::json!({
+        "message": format!("{:#}", err),
+        "diagnostic": anyhow_err_to_diagnostic(err).map(diagnostic_to_json),
+    })
+}
+
+pub fn diagnostic_to_json(diagnostic: Diagnostic<usize>) -> serde_json::Value {
+    let files = YUCK_FILES.read().unwrap();
+    let location_to_json = |file_id: usize, byte_index: usize| {
+        files.location(file_id, byte_index).ok().map(
+            |location| serde_json::json!({ "line": location.line_number, "column": location.column_number, "byte": byte_index }),
+        )
+    };
+    let labels = diagnostic
+        .labels
+        .iter()
+        .filter(|label| !Span(label.range.start, label.range.end, label.file_id).is_dummy())
+        .map(|label| {
+            serde_json::json!({
+                "primary": label.style == LabelStyle::Primary,
+                "message": label.message,
+                "file": files.name(label.file_id).ok(),
+                "start": location_to_json(label.file_id, label.range.start),
+                "end": location_to_json(label.file_id, label.range.end),
+            })
+        })
+        .collect::<Vec<_>>();
+    serde_json::json!({
+        "severity": format!("{:?}", diagnostic.severity).to_lowercase(),
+        "message": diagnostic.message,
+        "labels": labels,
+        "notes": diagnostic.notes,
+    })
+}
+
 pub fn anyhow_err_to_diagnostic(err: &anyhow::Error) -> Option<Diagnostic<usize>> {
     if let Some(err) = err.downcast_ref::<DiagError>() {
         Some(err.diag.clone())
@@ -72,7 +110,16 @@ pub fn anyhow_err_to_diagnostic(err: &anyhow::Error) -> Option<Diagnostic<usize>
 // log::error!("{:?}", diagnostic);
 //}
 
-pub fn stringify_diagnostic(mut diagnostic: codespan_reporting::diagnostic::Diagnostic<usize>) -> anyhow::Result<String> {
+pub fn stringify_diagnostic(diagnostic: codespan_reporting::diagnostic::Diagnostic<usize>) -> anyhow::Result<String> {
+    stringify_diagnostic_with_files(&YUCK_FILES.read().unwrap(), diagnostic)
+}
+
+/// Like [stringify_diagnostic], but using the given files rather than the global [YUCK_FILES],
+/// for when the diagnostic is generated while those are being loaded.
+pub fn stringify_diagnostic_with_files(
+    files: &YuckFiles,
+    mut diagnostic: codespan_reporting::diagnostic::Diagnostic<usize>,
+) -> anyhow::Result<String> {
     diagnostic.labels.drain_filter(|label| Span(label.range.start, label.range.end, label.file_id).is_dummy());
 
     let mut config = term::Config::default();
@@ -82,7 +129,6 @@ pub fn stringify_diagnostic(mut diagnostic: codespan_reporting::diagnostic::Diag
     config.chars.note_bullet = '→';
     let mut buf = Vec::new();
     let mut writer = term::termcolor::Ansi::new(&mut buf);
-    let files = YUCK_FILES.read().unwrap();
-    term::emit(&mut writer, &config, &*files, &diagnostic)?;
+    term::emit(&mut writer, &config, files, &diagnostic)?;
     Ok(String::from_utf8(buf)?)
 }
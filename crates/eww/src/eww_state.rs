@@ -1,36 +1,50 @@
 use anyhow::*;
 use eww_shared_util::{AttrName, VarName};
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, rc::Rc};
 
-use simplexpr::{dynval::DynVal, SimplExpr};
+use simplexpr::{compiled::CompiledExpr, dynval::DynVal, SimplExpr};
 
-use crate::error_handling_ctx;
+use crate::{daemon_response::DaemonResponseSender, error_handling_ctx};
+
+/// Identifies a group of [StateChangeHandler]s that were registered together,
+/// such that they can be removed again once the widgets they belong to are destroyed.
+/// See [EwwState::with_scope].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScopeId(usize);
+
+type StateChangeFn = dyn Fn(&mut EwwState, HashMap<AttrName, DynVal>) -> Result<()> + 'static;
 
 /// Handler that gets executed to apply the necessary parts of the eww state to
 /// a gtk widget. These are created and initialized in EwwState::resolve.
 pub struct StateChangeHandler {
-    func: Box<dyn Fn(HashMap<AttrName, DynVal>) -> Result<()> + 'static>,
-    unresolved_values: HashMap<AttrName, SimplExpr>,
+    func: Box<StateChangeFn>,
+    /// The expressions of the attributes, compiled once such that they can be evaluated cheaply on every change
+    unresolved_values: Vec<(AttrName, CompiledExpr)>,
+    /// The scopes that were active when this handler was registered.
+    scopes: Vec<ScopeId>,
 }
 
 impl StateChangeHandler {
     fn used_variables(&self) -> impl Iterator<Item = &VarName> {
-        self.unresolved_values.iter().flat_map(|(_, value)| value.var_refs()).map(|(_, value)| value)
+        self.unresolved_values.iter().flat_map(|(_, value)| value.variables())
     }
 
     /// Run the StateChangeHandler.
-    /// [`state`] should be the global [EwwState::state].
-    fn run_with_state(&self, state: &HashMap<VarName, DynVal>) {
+    /// While the handler runs, the scopes it was registered in are active again,
+    /// such that any handlers it registers itself belong to the same scopes.
+    fn run_with_state(&self, eww_state: &mut EwwState) {
         let resolved_attrs = self
             .unresolved_values
-            .clone()
-            .into_iter()
-            .map(|(attr_name, value)| Ok((attr_name, value.eval(state)?)))
+            .iter()
+            .map(|(attr_name, value)| Ok((attr_name.clone(), value.eval(&eww_state.variables_state)?)))
             .collect::<Result<_>>();
 
         match resolved_attrs {
             Ok(resolved_attrs) => {
-                if let Err(err) = (self.func)(resolved_attrs).context("Error while updating UI after state change") {
+                let outer_scopes = std::mem::replace(&mut eww_state.scope_stack, self.scopes.clone());
+                let result = (self.func)(eww_state, resolved_attrs);
+                eww_state.scope_stack = outer_scopes;
+                if let Err(err) = result.context("Error while updating UI after state change") {
                     error_handling_ctx::print_error(err);
                 }
             }
@@ -42,29 +56,65 @@ impl StateChangeHandler {
 }
 
 /// Collection of [StateChangeHandler]s
-/// State specific to one window.
-/// stores the state_change handlers that are used for that window.
+/// State specific to one window instance.
+/// stores the state_change handlers that are used for that window instance.
 #[derive(Default)]
 pub struct EwwWindowState {
-    state_change_handlers: HashMap<VarName, Vec<Arc<StateChangeHandler>>>,
+    state_change_handlers: HashMap<VarName, Vec<Rc<StateChangeHandler>>>,
 }
 
 impl EwwWindowState {
     /// register a new [`StateChangeHandler`]
     fn put_handler(&mut self, handler: StateChangeHandler) {
-        let handler = Arc::new(handler);
+        let handler = Rc::new(handler);
         for var_name in handler.used_variables() {
             self.state_change_handlers.entry(var_name.clone()).or_insert_with(Vec::new).push(handler.clone());
         }
     }
+
+    /// remove all handlers that were registered within the given scope
+    fn remove_scope(&mut self, scope: ScopeId) {
+        for handlers in self.state_change_handlers.values_mut() {
+            handlers.retain(|handler| !handler.scopes.contains(&scope));
+        }
+        self.state_change_handlers.retain(|_, handlers| !handlers.is_empty());
+    }
+
+    fn is_registered(&self, var_name: &VarName, handler: &Rc<StateChangeHandler>) -> bool {
+        self.state_change_handlers.get(var_name).map_or(false, |handlers| handlers.iter().any(|h| Rc::ptr_eq(h, handler)))
+    }
+}
+
+/// A client that subscribed to changes of a set of variables, i.e. via `eww listen`.
+pub struct VariableSubscription {
+    names: Vec<VarName>,
+    sender: DaemonResponseSender,
+}
+
+impl VariableSubscription {
+    pub fn new(names: Vec<VarName>, sender: DaemonResponseSender) -> Self {
+        VariableSubscription { names, sender }
+    }
+
+    /// Send the new value of a variable to the client.
+    /// When subscribed to a single variable, only the value itself is sent, otherwise it is prefixed with the variables name.
+    fn notify(&self, name: &VarName, value: &DynVal) -> Result<()> {
+        let output = if self.names.len() == 1 { value.to_string() } else { format!("{}: {}", name, value) };
+        let data = eww_client::response::VariableChange { name: name.clone(), value: value.to_string() };
+        self.sender.send_success_with_data(output, &data)
+    }
 }
 
 /// Stores the actual state of eww, including the variable state and the
 /// window-specific state-change handlers.
 #[derive(Default)]
 pub struct EwwState {
+    /// State of each window instance, by instance id
     windows: HashMap<String, EwwWindowState>,
     variables_state: HashMap<VarName, DynVal>,
+    subscriptions: Vec<VariableSubscription>,
+    scope_stack: Vec<ScopeId>,
+    next_scope_id: usize,
 }
 
 impl std::fmt::Debug for EwwState {
@@ -82,9 +132,9 @@ impl EwwState {
         &self.variables_state
     }
 
-    /// remove all state stored specific to one window
-    pub fn clear_window_state(&mut self, window_name: &str) {
-        self.windows.remove(window_name);
+    /// remove all state stored specific to one window instance
+    pub fn clear_window_state(&mut self, instance_id: &str) {
+        self.windows.remove(instance_id);
     }
 
     /// remove all state that is specific to any window
@@ -95,14 +145,41 @@ impl EwwState {
     /// Update the value of a variable, running all registered
     /// [StateChangeHandler]s.
     pub fn update_variable(&mut self, key: VarName, value: DynVal) {
-        self.variables_state.insert(key.clone(), value);
+        let old_value = self.variables_state.insert(key.clone(), value.clone());
+
+        if old_value.as_ref() != Some(&value) {
+            // subscriptions whose client disconnected fail to send, and are thus removed
+            self.subscriptions.retain(|sub| !sub.names.contains(&key) || sub.notify(&key, &value).is_ok());
+        }
 
         // run all of the handlers
-        self.windows
+        let handlers = self
+            .windows
             .values()
             .filter_map(|window_state| window_state.state_change_handlers.get(&key))
             .flatten()
-            .for_each(|handler| handler.run_with_state(&self.variables_state));
+            .cloned()
+            .collect::<Vec<_>>();
+        for handler in handlers {
+            // handlers may remove other handlers while running, in which case those must not be run anymore
+            if self.windows.values().any(|window_state| window_state.is_registered(&key, &handler)) {
+                handler.run_with_state(self);
+            }
+        }
+    }
+
+    /// Register a new [`VariableSubscription`], immediately sending it the current values of its variables.
+    pub fn subscribe(&mut self, subscription: VariableSubscription) -> Result<()> {
+        for name in &subscription.names {
+            subscription.notify(name, self.lookup(name)?)?;
+        }
+        self.subscriptions.push(subscription);
+        Ok(())
+    }
+
+    /// Drop all subscriptions whose client disconnected.
+    pub fn remove_closed_subscriptions(&mut self) {
+        self.subscriptions.retain(|sub| !sub.sender.is_closed());
     }
 
     /// Look up a single variable in the eww state, returning an `Err` when the value is not found.
@@ -112,24 +189,57 @@ impl EwwState {
 
     /// resolves a value if possible, using the current eww_state.
     pub fn resolve_once<'a>(&'a self, value: &'a SimplExpr) -> Result<DynVal> {
-        Ok(value.clone().eval(&self.variables_state)?)
+        Ok(value.eval(&self.variables_state)?)
     }
 
     /// Resolve takes a function that applies a set of fully resolved attribute
     /// values to it's gtk widget.
     pub fn resolve<F: Fn(HashMap<AttrName, DynVal>) -> Result<()> + 'static + Clone>(
         &mut self,
-        window_name: &str,
+        instance_id: &str,
         required_attributes: HashMap<AttrName, SimplExpr>,
         set_value: F,
     ) {
-        let handler = StateChangeHandler { func: Box::new(set_value), unresolved_values: required_attributes };
+        self.resolve_with_state(instance_id, required_attributes, move |_, attrs| set_value(attrs))
+    }
 
-        handler.run_with_state(&self.variables_state);
+    /// Like [EwwState::resolve], but the function additionally gets access to the [EwwState],
+    /// which allows it to render new widgets and register their handlers.
+    pub fn resolve_with_state<F: Fn(&mut EwwState, HashMap<AttrName, DynVal>) -> Result<()> + 'static>(
+        &mut self,
+        instance_id: &str,
+        required_attributes: HashMap<AttrName, SimplExpr>,
+        set_value: F,
+    ) {
+        let handler = StateChangeHandler {
+            func: Box::new(set_value),
+            unresolved_values: required_attributes.iter().map(|(name, expr)| (name.clone(), CompiledExpr::new(expr))).collect(),
+            scopes: self.scope_stack.clone(),
+        };
+
+        handler.run_with_state(self);
 
         // only store the handler if at least one variable is being used
         if handler.used_variables().next().is_some() {
-            self.windows.entry(window_name.to_string()).or_insert_with(EwwWindowState::default).put_handler(handler);
+            self.windows.entry(instance_id.to_string()).or_insert_with(EwwWindowState::default).put_handler(handler);
+        }
+    }
+
+    /// Run the given function within a new scope.
+    /// All handlers registered while it runs can later be removed via [EwwState::remove_scope].
+    pub fn with_scope<T>(&mut self, f: impl FnOnce(&mut EwwState) -> T) -> (ScopeId, T) {
+        let scope = ScopeId(self.next_scope_id);
+        self.next_scope_id += 1;
+        self.scope_stack.push(scope);
+        let result = f(self);
+        self.scope_stack.pop();
+        (scope, result)
+    }
+
+    /// remove all handlers of the given window instance that were registered within the given scope
+    pub fn remove_scope(&mut self, instance_id: &str, scope: ScopeId) {
+        if let Some(window_state) = self.windows.get_mut(instance_id) {
+            window_state.remove_scope(scope);
         }
     }
 
@@ -137,7 +247,7 @@ impl EwwState {
         self.windows.values().flat_map(|w| w.state_change_handlers.keys())
     }
 
-    pub fn vars_referenced_in(&self, window_name: &str) -> std::collections::HashSet<&VarName> {
-        self.windows.get(window_name).map(|window| window.state_change_handlers.keys().collect()).unwrap_or_default()
+    pub fn vars_referenced_in(&self, instance_id: &str) -> std::collections::HashSet<&VarName> {
+        self.windows.get(instance_id).map(|window| window.state_change_handlers.keys().collect()).unwrap_or_default()
     }
 }
@@ -1,6 +1,6 @@
-use crate::{app, opts};
+use crate::{app, opts::ActionWithServerExt};
 use anyhow::*;
-use std::time::Duration;
+use eww_client::protocol::{self as ipc_protocol, Handshake, HandshakeResponse, Request, ServerMessage};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     sync::mpsc::*,
@@ -29,36 +29,84 @@ pub async fn run_server<P: AsRef<std::path::Path>>(evt_send: UnboundedSender<app
 async fn handle_connection(mut stream: tokio::net::UnixStream, evt_send: UnboundedSender<app::DaemonCommand>) -> Result<()> {
     let (mut stream_read, mut stream_write) = stream.split();
 
-    let action: opts::ActionWithServer = read_action_from_stream(&mut stream_read).await?;
+    let handshake: Option<Handshake> = ipc_protocol::read_message_async(&mut stream_read)
+        .await
+        .context("Failed to read handshake, the client is likely running an incompatible version of eww")?;
+    let handshake = match handshake {
+        Some(handshake) => handshake,
+        None => return Ok(()),
+    };
+    if !handshake.is_compatible() {
+        ipc_protocol::write_message_async(&mut stream_write, &HandshakeResponse::version_mismatch()).await?;
+        stream_write.shutdown().await?;
+        bail!(
+            "Rejected IPC client running an incompatible version of eww ({}, protocol version {})",
+            handshake.eww_version,
+            handshake.protocol_version
+        );
+    }
+    ipc_protocol::write_message_async(&mut stream_write, &HandshakeResponse::Accepted).await?;
 
-    log::debug!("received command from IPC: {:?}", &action);
+    while let Some(request) = ipc_protocol::read_message_async::<Request, _>(&mut stream_read).await? {
+        log::debug!("received command from IPC: {:?}", &request);
+        if !handle_request(request, &mut stream_read, &mut stream_write, &evt_send).await? {
+            log::debug!("IPC client disconnected");
+            return Ok(());
+        }
+    }
+    stream_write.shutdown().await?;
+    Ok(())
+}
 
+/// Forward a single request to the app, and send all responses back to the client.
+/// Returns `false` if the client disconnected while sending the responses.
+async fn handle_request(
+    request: Request,
+    stream_read: &mut tokio::net::unix::ReadHalf<'_>,
+    stream_write: &mut tokio::net::unix::WriteHalf<'_>,
+    evt_send: &UnboundedSender<app::DaemonCommand>,
+) -> Result<bool> {
+    let Request { id: request_id, action } = request;
+    let is_streaming = action.is_streaming();
     let (command, maybe_response_recv) = action.into_daemon_command();
 
     evt_send.send(command)?;
 
     if let Some(mut response_recv) = maybe_response_recv {
-        log::debug!("Waiting for response for IPC client");
-        if let Ok(Some(response)) = tokio::time::timeout(Duration::from_millis(100), response_recv.recv()).await {
-            let response = bincode::serialize(&response)?;
-            let result = &stream_write.write_all(&response).await;
-            crate::print_result_err!("sending text response to ipc client", &result);
+        loop {
+            let response = tokio::select! {
+                // the app drops the sender once it stops sending responses.
+                response = response_recv.recv() => match response {
+                    Some(response) => response,
+                    None => break,
+                },
+                // clients don't send anything while a request is streaming, so the read half only finishes once they disconnect.
+                // A streaming response may not be sent for a long time, so the app is told to drop it right away.
+                _ = wait_for_disconnect(stream_read), if is_streaming => {
+                    drop(response_recv);
+                    evt_send.send(app::DaemonCommand::RemoveClosedSubscriptions)?;
+                    return Ok(false);
+                }
+            };
+            let message = ServerMessage::Response { request_id, response };
+            if ipc_protocol::write_message_async(stream_write, &message).await.is_err() {
+                return Ok(false);
+            }
+            if !is_streaming {
+                break;
+            }
         }
     }
-    stream_write.shutdown().await?;
-    Ok(())
+    Ok(ipc_protocol::write_message_async(stream_write, &ServerMessage::Done { request_id }).await.is_ok())
 }
 
-/// Read a single message from a unix stream, and parses it into a `ActionWithServer`
-/// The format here requires the first 4 bytes to be the size of the rest of the message (in big-endian), followed by the rest of the message.
-async fn read_action_from_stream(stream_read: &'_ mut tokio::net::unix::ReadHalf<'_>) -> Result<opts::ActionWithServer> {
-    let mut message_byte_length = [0u8; 4];
-    stream_read.read_exact(&mut message_byte_length).await.context("Failed to read message size header in IPC message")?;
-    let message_byte_length = u32::from_be_bytes(message_byte_length);
-    let mut raw_message = Vec::<u8>::with_capacity(message_byte_length as usize);
-    while raw_message.len() < message_byte_length as usize {
-        stream_read.read_buf(&mut raw_message).await.context("Failed to read actual IPC message")?;
+/// Wait until the client closes its side of the connection, discarding anything it sends in the meantime.
+async fn wait_for_disconnect(stream_read: &mut tokio::net::unix::ReadHalf<'_>) {
+    let mut buf = [0; 64];
+    loop {
+        match stream_read.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
     }
-
-    bincode::deserialize(&raw_message).context("Failed to parse client message")
 }
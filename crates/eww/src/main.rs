@@ -13,10 +13,10 @@ extern crate gtk;
 extern crate gtk_layer_shell as gtk_layer_shell;
 
 use anyhow::*;
-use daemon_response::DaemonResponseReceiver;
-use opts::ActionWithServer;
+use daemon_response::{DaemonResponse, DaemonResponseReceiver};
+use eww_client::{ActionWithServer, Client, VersionMismatchError};
+use opts::ActionWithServerExt;
 use std::{
-    os::unix::net,
     path::{Path, PathBuf},
     time::Duration,
 };
@@ -51,6 +51,7 @@ fn main() {
         pretty_env_logger::formatted_timed_builder().filter(Some("eww"), log_level_filter).init();
     }
 
+    let call_options = CallOptions { timeout: opts.timeout, json: opts.json };
     let result: Result<()> = try {
         let paths = opts
             .config_path
@@ -67,39 +68,45 @@ fn main() {
             // a running daemon is necessary for this command
             opts::Action::WithServer(action) if action.can_start_daemon() => {
                 if opts.restart {
-                    let _ = handle_server_command(&paths, &ActionWithServer::KillServer, 1);
+                    let _ = handle_server_command(&paths, &ActionWithServer::KillServer, 1, &call_options);
                     std::thread::sleep(std::time::Duration::from_millis(200));
                 }
 
                 // attempt to just send the command to a running daemon
-                if let Err(err) = handle_server_command(&paths, &action, 5) {
-                    // connecting to the daemon failed. Thus, start the daemon here!
-                    log::warn!("Failed to connect to daemon: {}", err);
-                    log::info!("Initializing eww server. ({})", paths.get_ipc_socket_file().display());
-                    let _ = std::fs::remove_file(paths.get_ipc_socket_file());
-                    if !opts.show_logs {
-                        println!("Run `{} logs` to see any errors while editing your configuration.", eww_binary_name);
+                match attempt_connect(&paths.get_ipc_socket_file(), 5) {
+                    Ok(mut client) => {
+                        send_server_command(&mut client, &action, &call_options)?;
+                        true
                     }
-
-                    let (command, response_recv) = action.into_daemon_command();
-                    // start the daemon and give it the command
-                    let fork_result = server::initialize_server(paths.clone(), Some(command))?;
-                    let is_parent = fork_result == ForkResult::Parent;
-                    if let (Some(recv), true) = (response_recv, is_parent) {
-                        listen_for_daemon_response(recv);
+                    // a daemon is running, but we can't talk to it. Starting a second one would not help.
+                    Err(err) if err.is::<VersionMismatchError>() => Err(err)?,
+                    Err(err) => {
+                        // connecting to the daemon failed. Thus, start the daemon here!
+                        log::warn!("Failed to connect to daemon: {}", err);
+                        log::info!("Initializing eww server. ({})", paths.get_ipc_socket_file().display());
+                        let _ = std::fs::remove_file(paths.get_ipc_socket_file());
+                        if !opts.show_logs {
+                            println!("Run `{} logs` to see any errors while editing your configuration.", eww_binary_name);
+                        }
+
+                        let (command, response_recv) = action.into_daemon_command();
+                        // start the daemon and give it the command
+                        let fork_result = server::initialize_server(paths.clone(), Some(command))?;
+                        let is_parent = fork_result == ForkResult::Parent;
+                        if let (Some(recv), true) = (response_recv, is_parent) {
+                            listen_for_daemon_response(recv, call_options.json);
+                        }
+                        is_parent
                     }
-                    is_parent
-                } else {
-                    true
                 }
             }
             opts::Action::WithServer(ActionWithServer::KillServer) => {
-                handle_server_command(&paths, &ActionWithServer::KillServer, 1)?;
+                handle_server_command(&paths, &ActionWithServer::KillServer, 1, &call_options)?;
                 false
             }
 
             opts::Action::WithServer(action) => {
-                handle_server_command(&paths, &action, 5)?;
+                handle_server_command(&paths, &action, 5, &call_options)?;
                 true
             }
 
@@ -125,49 +132,97 @@ fn main() {
     };
 
     if let Err(e) = result {
-        error_handling_ctx::print_error(e);
+        if call_options.json {
+            println!("{}", daemon_response::errors_output(&[e], &[]).json);
+        } else {
+            error_handling_ctx::print_error(e);
+        }
         std::process::exit(1);
     }
 }
 
-fn listen_for_daemon_response(mut recv: DaemonResponseReceiver) {
+/// Options regarding how the client talks to the daemon
+struct CallOptions {
+    /// Overrides the default timeout of the action
+    timeout: Option<Duration>,
+    /// Print the json representation of the responses, instead of their text
+    json: bool,
+}
+
+fn listen_for_daemon_response(mut recv: DaemonResponseReceiver, json: bool) {
     let rt = tokio::runtime::Builder::new_current_thread().enable_time().build().expect("Failed to initialize tokio runtime");
     rt.block_on(async {
         if let Ok(Some(response)) = tokio::time::timeout(Duration::from_millis(100), recv.recv()).await {
-            println!("{}", response);
+            print_daemon_response(response, json);
         }
     })
 }
 
-fn handle_server_command(paths: &EwwPaths, action: &ActionWithServer, connect_attempts: usize) -> Result<()> {
+fn handle_server_command(
+    paths: &EwwPaths,
+    action: &ActionWithServer,
+    connect_attempts: usize,
+    call_options: &CallOptions,
+) -> Result<()> {
     log::debug!("Trying to find server process at socket {}", paths.get_ipc_socket_file().display());
-    let mut stream = attempt_connect(&paths.get_ipc_socket_file(), connect_attempts).context("Failed to connect to daemon")?;
+    let mut client = attempt_connect(&paths.get_ipc_socket_file(), connect_attempts)?;
     log::debug!("Connected to Eww server ({}).", &paths.get_ipc_socket_file().display());
-    let response = client::do_server_call(&mut stream, action).context("Error while forwarding command to server")?;
+    send_server_command(&mut client, action, call_options)
+}
+
+/// Send a command to the daemon and print its response.
+fn send_server_command(client: &mut Client, action: &ActionWithServer, call_options: &CallOptions) -> Result<()> {
+    client.set_timeout(call_options.timeout);
+    if action.is_streaming() {
+        return client
+            .call_streaming(action, |response| print_daemon_response(response, call_options.json))
+            .context("Error while receiving responses from server");
+    }
+    let response = client.call(action).context("Error while forwarding command to server")?;
     if let Some(response) = response {
-        println!("{}", response);
+        print_daemon_response(response, call_options.json);
     }
     Ok(())
 }
 
-fn attempt_connect(socket_path: impl AsRef<Path>, attempts: usize) -> Option<net::UnixStream> {
-    for _ in 0..attempts {
-        if let Ok(mut con) = net::UnixStream::connect(&socket_path) {
-            if client::do_server_call(&mut con, &opts::ActionWithServer::Ping).is_ok() {
-                return net::UnixStream::connect(&socket_path).ok();
+/// Print the response of the daemon, exiting with a non-zero exit code if the command failed.
+/// In json mode, failures are printed to stdout as well, such that scripts can parse them.
+fn print_daemon_response(response: DaemonResponse, json: bool) {
+    match response {
+        DaemonResponse::Success(output) if json => println!("{}", output.json),
+        DaemonResponse::Success(output) => println!("{}", output),
+        DaemonResponse::Failure(output) => {
+            if json {
+                println!("{}", output.json);
+            } else {
+                eprintln!("{}", output);
             }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Connect to the daemon, making sure it responds to a ping.
+/// Fails with a [`VersionMismatchError`] if the daemon runs an incompatible version of eww.
+fn attempt_connect(socket_path: impl AsRef<Path>, attempts: usize) -> Result<Client> {
+    for _ in 0..attempts {
+        match Client::connect(&socket_path).and_then(|mut client| client.ping().map(|_| client)) {
+            Ok(client) => return Ok(client),
+            Err(err) if err.is::<VersionMismatchError>() => return Err(err),
+            Err(_) => {}
         }
         std::thread::sleep(Duration::from_millis(200));
     }
-    None
+    bail!("Failed to connect to daemon")
 }
 
 /// Check if a eww server is currently running by trying to send a ping message to it.
 fn check_server_running(socket_path: impl AsRef<Path>) -> bool {
-    let response = net::UnixStream::connect(socket_path)
-        .ok()
-        .and_then(|mut stream| client::do_server_call(&mut stream, &opts::ActionWithServer::Ping).ok());
-    response.is_some()
+    match Client::connect(socket_path).and_then(|mut client| client.ping()) {
+        Ok(_) => true,
+        // a daemon running an incompatible version is still running
+        Err(err) => err.is::<VersionMismatchError>(),
+    }
 }
 
 #[derive(Debug, Clone)]
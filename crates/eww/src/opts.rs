@@ -1,13 +1,17 @@
 use anyhow::*;
-use eww_shared_util::VarName;
+use eww_client::{geometry, ActionWithServer};
+use extend::ext;
 use serde::{Deserialize, Serialize};
 use simplexpr::dynval::DynVal;
 use structopt::StructOpt;
-use yuck::{config::window_geometry::AnchorPoint, value::Coords};
+use yuck::{
+    config::window_geometry::{AnchorAlignment, AnchorPoint},
+    value::{Coords, NumWithUnit},
+};
 
 use crate::{
     app,
-    daemon_response::{self, DaemonResponse, DaemonResponseSender},
+    daemon_response::{self, DaemonResponse, DaemonResponseSender, ResponseOutput},
 };
 
 /// Struct that gets generated from `RawOpt`.
@@ -17,6 +21,8 @@ pub struct Opt {
     pub show_logs: bool,
     pub restart: bool,
     pub config_path: Option<std::path::PathBuf>,
+    pub timeout: Option<std::time::Duration>,
+    pub json: bool,
     pub action: Action,
 }
 
@@ -38,6 +44,15 @@ struct RawOpt {
     #[structopt(long = "restart", global = true)]
     restart: bool,
 
+    /// How long to wait for the daemon to respond, like "500ms" or "10s".
+    /// Defaults to a longer timeout for slow commands like `reload` and `open`.
+    #[structopt(long = "timeout", global = true, parse(try_from_str = parse_duration))]
+    timeout: Option<std::time::Duration>,
+
+    /// Print the responses of the daemon as json documents, for use in scripts
+    #[structopt(long = "json", global = true)]
+    json: bool,
+
     #[structopt(subcommand)]
     action: Action,
 }
@@ -62,94 +77,6 @@ pub enum ActionClientOnly {
     Logs,
 }
 
-#[derive(StructOpt, Debug, Serialize, Deserialize, PartialEq)]
-pub enum ActionWithServer {
-    /// Ping the eww server, checking if it is reachable.
-    #[structopt(name = "ping")]
-    Ping,
-
-    /// Update the value of a variable, in a running eww instance
-    #[structopt(name = "update", alias = "u")]
-    Update {
-        /// variable_name="new_value"-pairs that will be updated
-        #[structopt(parse(try_from_str = parse_var_update_arg))]
-        mappings: Vec<(VarName, DynVal)>,
-    },
-
-    /// open a window
-    #[structopt(name = "open", alias = "o")]
-    OpenWindow {
-        /// Name of the window you want to open.
-        window_name: String,
-
-        /// Monitor-index the window should open on
-        #[structopt(long)]
-        screen: Option<i32>,
-
-        /// The position of the window, where it should open.
-        #[structopt(short, long)]
-        pos: Option<Coords>,
-
-        /// The size of the window to open
-        #[structopt(short, long)]
-        size: Option<Coords>,
-
-        /// Sidepoint of the window, formatted like "top right"
-        #[structopt(short, long)]
-        anchor: Option<AnchorPoint>,
-
-        /// If the window is already open, close it instead
-        #[structopt(long = "toggle")]
-        should_toggle: bool,
-    },
-
-    /// Open multiple windows at once.
-    /// NOTE: This will in the future be part of eww open, and will then be removed.
-    #[structopt(name = "open-many")]
-    OpenMany {
-        windows: Vec<String>,
-
-        /// If a window is already open, close it instead
-        #[structopt(long = "toggle")]
-        should_toggle: bool,
-    },
-
-    /// Close the given windows
-    #[structopt(name = "close", alias = "c")]
-    CloseWindows { windows: Vec<String> },
-
-    /// Reload the configuration
-    #[structopt(name = "reload", alias = "r")]
-    Reload,
-
-    /// kill the eww daemon
-    #[structopt(name = "kill", alias = "k")]
-    KillServer,
-
-    /// Close all windows, without killing the daemon
-    #[structopt(name = "close-all", alias = "ca")]
-    CloseAll,
-
-    /// Prints the variables used in all currently open window
-    #[structopt(name = "state")]
-    ShowState {
-        /// Shows all variables, including not currently used ones
-        #[structopt(short, long)]
-        all: bool,
-    },
-
-    /// Print the names of all configured windows. Windows with a * in front of them are currently opened.
-    #[structopt(name = "windows")]
-    ShowWindows,
-
-    /// Print out the widget structure as seen by eww.
-    ///
-    /// This may be useful if you are facing issues with how eww is interpreting your configuration,
-    /// and to provide additional context to the eww developers if you are filing a bug.
-    #[structopt(name = "debug")]
-    ShowDebug,
-}
-
 impl Opt {
     pub fn from_env() -> Self {
         let raw: RawOpt = StructOpt::from_args();
@@ -159,27 +86,36 @@ impl Opt {
 
 impl From<RawOpt> for Opt {
     fn from(other: RawOpt) -> Self {
-        let RawOpt { action, log_debug, show_logs, config, restart } = other;
-        Opt { action, log_debug, show_logs, config_path: config, restart }
+        let RawOpt { action, log_debug, show_logs, config, restart, timeout, json } = other;
+        Opt { action, log_debug, show_logs, config_path: config, restart, timeout, json }
     }
 }
 
-fn parse_var_update_arg(s: &str) -> Result<(VarName, DynVal)> {
-    let (name, value) = s
-        .split_once('=')
-        .with_context(|| format!("arguments must be in the shape `variable_name=\"new_value\"`, but got: {}", s))?;
-    Ok((name.into(), DynVal::from_string(value.to_owned())))
+/// Convert the geometry of a window given when opening it into the types used in the configuration
+fn coords_from_wire(coords: geometry::Coords) -> Coords {
+    let num_from_wire = |num| match num {
+        geometry::NumWithUnit::Percent(n) => NumWithUnit::Percent(n),
+        geometry::NumWithUnit::Pixels(n) => NumWithUnit::Pixels(n),
+    };
+    Coords { x: num_from_wire(coords.x), y: num_from_wire(coords.y) }
 }
 
-impl ActionWithServer {
-    pub fn can_start_daemon(&self) -> bool {
-        match self {
-            ActionWithServer::OpenWindow { .. } | ActionWithServer::OpenMany { .. } => true,
-            _ => false,
-        }
-    }
+fn anchor_from_wire(anchor: geometry::AnchorPoint) -> AnchorPoint {
+    let alignment_from_wire = |alignment| match alignment {
+        geometry::AnchorAlignment::Start => AnchorAlignment::START,
+        geometry::AnchorAlignment::Center => AnchorAlignment::CENTER,
+        geometry::AnchorAlignment::End => AnchorAlignment::END,
+    };
+    AnchorPoint { x: alignment_from_wire(anchor.x), y: alignment_from_wire(anchor.y) }
+}
+
+fn parse_duration(s: &str) -> Result<std::time::Duration> {
+    Ok(DynVal::from_string(s.to_owned()).as_duration()?)
+}
 
-    pub fn into_daemon_command(self) -> (app::DaemonCommand, Option<daemon_response::DaemonResponseReceiver>) {
+#[ext(pub, name = ActionWithServerExt)]
+impl ActionWithServer {
+    fn into_daemon_command(self) -> (app::DaemonCommand, Option<daemon_response::DaemonResponseReceiver>) {
         let command = match self {
             ActionWithServer::Update { mappings } => app::DaemonCommand::UpdateVars(mappings),
 
@@ -187,20 +123,22 @@ impl ActionWithServer {
             ActionWithServer::CloseAll => app::DaemonCommand::CloseAll,
             ActionWithServer::Ping => {
                 let (send, recv) = tokio::sync::mpsc::unbounded_channel();
-                let _ = send.send(DaemonResponse::Success("pong".to_owned()));
+                let _ = send.send(DaemonResponse::Success(ResponseOutput::text("pong".to_owned())));
                 return (app::DaemonCommand::NoOp, Some(recv));
             }
-            ActionWithServer::OpenMany { windows, should_toggle } => {
-                return with_response_channel(|sender| app::DaemonCommand::OpenMany { windows, should_toggle, sender });
+            ActionWithServer::OpenMany { windows, should_toggle, args } => {
+                return with_response_channel(|sender| app::DaemonCommand::OpenMany { windows, args, should_toggle, sender });
             }
-            ActionWithServer::OpenWindow { window_name, pos, size, screen, anchor, should_toggle } => {
+            ActionWithServer::OpenWindow { window_name, id, pos, size, screen, anchor, should_toggle, args } => {
                 return with_response_channel(|sender| app::DaemonCommand::OpenWindow {
                     window_name,
-                    pos,
-                    size,
-                    anchor,
+                    instance_id: id,
+                    pos: pos.map(coords_from_wire),
+                    size: size.map(coords_from_wire),
+                    anchor: anchor.map(anchor_from_wire),
                     screen,
                     should_toggle,
+                    args,
                     sender,
                 })
             }
@@ -209,8 +147,14 @@ impl ActionWithServer {
             }
             ActionWithServer::Reload => return with_response_channel(app::DaemonCommand::ReloadConfigAndCss),
             ActionWithServer::ShowWindows => return with_response_channel(app::DaemonCommand::PrintWindows),
-            ActionWithServer::ShowState { all } => {
-                return with_response_channel(|sender| app::DaemonCommand::PrintState { all, sender })
+            ActionWithServer::ShowState { all, instance_id } => {
+                return with_response_channel(|sender| app::DaemonCommand::PrintState { all, instance_id, sender })
+            }
+            ActionWithServer::Get { names, expr } => {
+                return with_response_channel(|sender| app::DaemonCommand::GetVar { names, expr, sender })
+            }
+            ActionWithServer::Listen { names } => {
+                return with_response_channel(|sender| app::DaemonCommand::ListenVars { names, sender })
             }
             ActionWithServer::ShowDebug => return with_response_channel(app::DaemonCommand::PrintDebug),
         };
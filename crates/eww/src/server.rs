@@ -7,7 +7,7 @@ use crate::{
 use anyhow::*;
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashMap,
     os::unix::io::AsRawFd,
     path::Path,
     sync::{atomic::Ordering, Arc},
@@ -59,11 +59,13 @@ pub fn initialize_server(paths: EwwPaths, action: Option<DaemonCommand>) -> Resu
     log::debug!("Initializing script var handler");
     let script_var_handler = script_var_handler::init(ui_send.clone());
 
+    let initial_config_warnings = eww_config.get_warnings().to_vec();
     let mut app = app::App {
         eww_state: EwwState::from_default_vars(eww_config.generate_initial_state()?),
         eww_config,
         open_windows: HashMap::new(),
-        failed_windows: HashSet::new(),
+        failed_windows: HashMap::new(),
+        initial_config_warnings,
         css_provider: gtk::CssProvider::new(),
         script_var_handler,
         app_evt_send: ui_send.clone(),
@@ -86,6 +88,7 @@ pub fn initialize_server(paths: EwwPaths, action: Option<DaemonCommand>) -> Resu
         if let Some(action) = action {
             app.handle_command(action);
         }
+        app.initial_config_warnings.clear();
         while let Some(event) = ui_recv.recv().await {
             app.handle_command(event);
         }
@@ -4,12 +4,14 @@ use codespan_reporting::diagnostic::Severity;
 use eww_shared_util::AttrName;
 use gtk::prelude::*;
 use itertools::Itertools;
+use simplexpr::functions::ValueType;
 use std::collections::HashMap;
 use yuck::{config::widget_definition::WidgetDefinition, gen_diagnostic};
 
 use std::process::Command;
 use widget_definitions::*;
 
+pub mod widget_attr_types;
 pub mod widget_definitions;
 pub mod widget_node;
 
@@ -40,6 +42,16 @@ pub(self) fn run_command<T: 'static + std::fmt::Display + Send + Sync>(timeout:
     });
 }
 
+/// The types of the attributes of the builtin widgets, by widget name, as declared in [widget_attr_types].
+pub fn builtin_widget_attr_types() -> HashMap<String, HashMap<AttrName, ValueType>> {
+    widget_attr_types::builtin_widgets()
+        .map(|(name, attrs)| {
+            let attrs = attrs.map(|(attr, value_type)| (AttrName(attr.to_string()), value_type));
+            (name.to_string(), attrs.collect())
+        })
+        .collect()
+}
+
 struct BuilderArgs<'a, 'b, 'c, 'd, 'e> {
     eww_state: &'a mut EwwState,
     widget: &'b widget_node::Generic,
@@ -116,6 +128,17 @@ macro_rules! resolve_block {
     }) => {
         $({
             $(
+                ::std::debug_assert!(
+                    $crate::widgets::widget_attr_types::is_declared(
+                        &$args.widget.name,
+                        &::std::stringify!($attr_name).replace('_', "-"),
+                        ::std::stringify!($typecast_func),
+                    ),
+                    "attribute `{}` of `{}` is not declared in widget_attr_types with the type it is read as by `{}`",
+                    ::std::stringify!($attr_name).replace('_', "-"),
+                    $args.widget.name,
+                    ::std::stringify!($typecast_func),
+                );
                 $args.unhandled_attrs.retain(|a| &a.0 != &::std::stringify!($attr_name).replace('_', "-"));
             )*
 
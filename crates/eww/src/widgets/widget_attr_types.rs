@@ -0,0 +1,131 @@
+//! The types of the attributes of the builtin widgets, as they are read by the `prop` declarations in
+//! [widget_definitions](super::widget_definitions).
+//!
+//! In debug builds, `resolve_block!` checks that every attribute it reads is declared here with the type it is read as,
+//! so this table can't silently get out of sync with the widgets.
+
+use simplexpr::functions::ValueType::{self, *};
+
+type Attrs = &'static [(&'static str, ValueType)];
+
+/// attributes that apply to all widgets
+const WIDGET: Attrs = &[
+    ("class", String),
+    ("valign", String),
+    ("halign", String),
+    ("vexpand", Bool),
+    ("hexpand", Bool),
+    ("width", Number),
+    ("height", Number),
+    ("active", Bool),
+    ("tooltip", String),
+    ("visible", Bool),
+    ("style", String),
+    ("timeout", Duration),
+    ("onscroll", String),
+    ("onhover", String),
+    ("cursor", String),
+];
+
+/// attributes of widgets that are a `gtk::Range`
+const RANGE: Attrs = &[("value", Number), ("min", Number), ("max", Number), ("timeout", Duration), ("onchange", String)];
+
+const ORIENTABLE: Attrs = &[("orientation", String)];
+
+/// The builtin widgets, with the groups of attributes that apply to each of them.
+/// Later groups take precedence over earlier ones, such that the `width` of an `image` is read as an integer.
+const BUILTIN_WIDGETS: &[(&str, &[Attrs])] = &[
+    ("box", &[WIDGET, &[("spacing", Int), ("orientation", String), ("space-evenly", Bool)]]),
+    ("centerbox", &[WIDGET, &[("orientation", String)]]),
+    ("scale", &[WIDGET, RANGE, ORIENTABLE, &[("flipped", Bool), ("draw-value", Bool)]]),
+    ("progress", &[WIDGET, &[("flipped", Bool), ("value", Number), ("orientation", String)]]),
+    ("image", &[WIDGET, &[("path", String), ("width", Int), ("height", Int)]]),
+    ("button", &[WIDGET, &[("timeout", Duration), ("onclick", String), ("onmiddleclick", String), ("onrightclick", String)]]),
+    (
+        "label",
+        &[
+            WIDGET,
+            &[
+                ("text", String),
+                ("limit-width", Int),
+                ("show-truncated", Bool),
+                ("markup", String),
+                ("wrap", Bool),
+                ("angle", Number),
+            ],
+        ],
+    ),
+    ("literal", &[WIDGET, &[("content", String)]]),
+    ("input", &[WIDGET, &[("value", String), ("timeout", Duration), ("onchange", String)]]),
+    (
+        "calendar",
+        &[
+            WIDGET,
+            &[
+                ("day", Number),
+                ("month", Number),
+                ("year", Number),
+                ("show-details", Bool),
+                ("show-heading", Bool),
+                ("show-day-names", Bool),
+                ("show-week-numbers", Bool),
+                ("timeout", Duration),
+                ("onclick", String),
+            ],
+        ],
+    ),
+    ("color-button", &[WIDGET, &[("use-alpha", Bool), ("timeout", Duration), ("onchange", String)]]),
+    ("expander", &[WIDGET, &[("name", String), ("expanded", Bool)]]),
+    ("color-chooser", &[WIDGET, &[("use-alpha", Bool), ("timeout", Duration), ("onchange", String)]]),
+    ("combo-box-text", &[WIDGET, &[("timeout", Duration), ("onchange", String)]]),
+    ("checkbox", &[WIDGET, &[("timeout", Duration), ("onchecked", String), ("onunchecked", String)]]),
+    ("revealer", &[WIDGET, &[("transition", String), ("reveal", Bool), ("duration", Duration)]]),
+    ("if-else", &[WIDGET, &[("cond", Bool)]]),
+];
+
+/// The attributes of all builtin widgets, with the types they are read as.
+pub fn builtin_widgets() -> impl Iterator<Item = (&'static str, impl Iterator<Item = (&'static str, ValueType)>)> {
+    BUILTIN_WIDGETS.iter().map(|(name, groups)| (*name, groups.iter().flat_map(|attrs| attrs.iter().copied())))
+}
+
+/// The [ValueType] of the values an attribute is read as by the given `DynVal::as_*` function,
+/// or `None` if the type can't be checked statically.
+fn value_type_of_typecast(typecast: &str) -> Option<ValueType> {
+    match typecast {
+        "as_string" => Some(String),
+        "as_f64" => Some(Number),
+        "as_i32" | "as_i64" => Some(Int),
+        "as_bool" => Some(Bool),
+        "as_duration" => Some(Duration),
+        _ => None,
+    }
+}
+
+/// Check that the given attribute of a builtin widget is declared with the type it is read as by the given typecast function.
+pub fn is_declared(widget: &str, attr: &str, typecast: &str) -> bool {
+    let value_type = match value_type_of_typecast(typecast) {
+        Some(value_type) => value_type,
+        None => return true,
+    };
+    BUILTIN_WIDGETS
+        .iter()
+        .filter(|(name, _)| *name == widget)
+        .flat_map(|(_, groups)| groups.iter().flat_map(|attrs| attrs.iter()))
+        .any(|(name, declared)| *name == attr && *declared == value_type)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_declared() {
+        assert!(is_declared("image", "width", "as_i32"));
+        assert!(is_declared("image", "width", "as_f64"));
+        assert!(is_declared("scale", "orientation", "as_string"));
+        assert!(!is_declared("label", "text", "as_bool"));
+        assert!(!is_declared("label", "nonexistent", "as_string"));
+        assert!(!is_declared("nonexistent", "class", "as_string"));
+        assert!(is_declared("combo-box-text", "items", "as_vec"));
+    }
+}
@@ -1,11 +1,17 @@
-use crate::eww_state::EwwState;
+use crate::eww_state::{EwwState, ScopeId};
 use anyhow::*;
 use dyn_clone;
 use eww_shared_util::{AttrName, Span, Spanned, VarName};
-use simplexpr::SimplExpr;
-use std::collections::HashMap;
+use gtk::prelude::*;
+use simplexpr::{dynval::DynVal, SimplExpr};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 use yuck::{
-    config::{validate::ValidationError, widget_definition::WidgetDefinition, widget_use::WidgetUse},
+    config::{
+        attributes::AttrError,
+        validate::{validate_arg_type, ValidationError},
+        widget_definition::{AttrSpec, WidgetDefinition},
+        widget_use::{BasicWidgetUse, ChildrenWidgetUse, LoopWidgetUse, WidgetUse},
+    },
     error::{AstError, AstResult},
 };
 
@@ -33,6 +39,8 @@ pub struct UserDefined {
     name: String,
     span: Span,
     content: Box<dyn WidgetNode>,
+    /// The values given to arguments with a declared type, which get checked whenever they change
+    typed_args: Vec<(AttrSpec, SimplExpr)>,
 }
 
 impl WidgetNode for UserDefined {
@@ -46,6 +54,19 @@ impl WidgetNode for UserDefined {
         window_name: &str,
         widget_definitions: &HashMap<String, WidgetDefinition>,
     ) -> Result<gtk::Widget> {
+        if !self.typed_args.is_empty() {
+            let widget_name = self.name.clone();
+            let typed_args = self.typed_args.clone();
+            let required_attributes = typed_args.iter().map(|(arg, value)| (arg.name.clone(), value.clone())).collect();
+            eww_state.resolve(window_name, required_attributes, move |values| {
+                for (arg, value_expr) in typed_args.iter() {
+                    if let Some(value) = values.get(&arg.name) {
+                        validate_arg_type(&widget_name, arg, value, Some(value_expr.span()))?;
+                    }
+                }
+                Ok(())
+            });
+        }
         self.content.render(eww_state, window_name, widget_definitions)
     }
 }
@@ -106,33 +127,243 @@ impl Spanned for Generic {
     }
 }
 
+/// A `for`-loop, rendering its body once for every element of a json array.
+/// Whenever the array changes, only the widgets for elements that weren't there before are newly created.
+#[derive(Debug, Clone)]
+pub struct Loop {
+    element_name: VarName,
+    elements_expr: SimplExpr,
+    /// The location of the elements expression within the loop, which errors about the elements are reported at
+    elements_span: Span,
+    body: WidgetUse,
+    /// The environment the body gets generated in, without the loop element
+    local_env: HashMap<VarName, SimplExpr>,
+    /// The children given to the user-defined widget this loop is part of
+    children: Vec<Box<dyn WidgetNode>>,
+    span: Span,
+}
+
+/// An element that is currently rendered by a [Loop], together with its widget and the scope its handlers are registered in
+type RenderedElement = (DynVal, gtk::Widget, ScopeId);
+
+impl WidgetNode for Loop {
+    fn get_name(&self) -> &str {
+        "for"
+    }
+
+    fn render(
+        &self,
+        eww_state: &mut EwwState,
+        window_name: &str,
+        widget_definitions: &HashMap<String, WidgetDefinition>,
+    ) -> Result<gtk::Widget> {
+        let gtk_widget = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+
+        // lay out the elements like the children of the surrounding box
+        gtk_widget.connect_parent_set(|gtk_widget, _| {
+            if let Some(parent) = gtk_widget.get_parent().and_then(|parent| parent.downcast::<gtk::Box>().ok()) {
+                for property in &["orientation", "spacing", "homogeneous"] {
+                    parent.bind_property(*property, gtk_widget, *property).flags(glib::BindingFlags::SYNC_CREATE).build();
+                }
+            }
+        });
+
+        let element_name = self.element_name.clone();
+        let body = self.body.clone();
+        let local_env = self.local_env.clone();
+        let children = self.children.clone();
+        let elements_span = self.elements_span;
+        let widget_definitions = widget_definitions.clone();
+        let loop_window_name = window_name.to_string();
+
+        let rendered: Rc<RefCell<Vec<RenderedElement>>> = Rc::new(RefCell::new(Vec::new()));
+
+        let elements_attr = AttrName("elements".to_string());
+        eww_state.resolve_with_state(
+            window_name,
+            maplit::hashmap! { elements_attr.clone() => self.elements_expr.clone() },
+            glib::clone!(@strong gtk_widget => move |eww_state, attrs| {
+                let elements = attrs.get(&elements_attr).context("something went terribly wrong....")?.clone().at(elements_span).as_json_array()?;
+                let mut previous = rendered.replace(Vec::new());
+                let mut current = Vec::new();
+
+                let result: Result<()> = try {
+                    for (index, element) in elements.iter().enumerate() {
+                        let element = DynVal::from(element).at(elements_span);
+                        let reused = previous.iter().position(|(value, ..)| value.as_str() == element.as_str()).map(|idx| previous.remove(idx));
+                        let (widget, scope) = match reused {
+                            Some((_, widget, scope)) => (widget, scope),
+                            None => {
+                                let mut env = local_env.clone();
+                                env.insert(element_name.clone(), SimplExpr::Value(element.clone()));
+                                let (scope, widget) = eww_state.with_scope(|eww_state| -> Result<gtk::Widget> {
+                                    let node = generate_widget_node(&widget_definitions, &env, &children, body.clone())?;
+                                    node.render(eww_state, &loop_window_name, &widget_definitions)
+                                });
+                                let widget = widget.map_err(|e| {
+                                    eww_state.remove_scope(&loop_window_name, scope);
+                                    e
+                                })?;
+                                gtk_widget.add(&widget);
+                                widget.show();
+                                (widget, scope)
+                            }
+                        };
+                        gtk_widget.reorder_child(&widget, index as i32);
+                        current.push((element, widget, scope));
+                    }
+                };
+
+                for (_, widget, scope) in previous {
+                    gtk_widget.remove(&widget);
+                    eww_state.remove_scope(&loop_window_name, scope);
+                }
+                *rendered.borrow_mut() = current;
+                result
+            }),
+        );
+        Ok(gtk_widget.upcast())
+    }
+}
+
+impl Spanned for Loop {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
 pub fn generate_generic_widget_node(
     defs: &HashMap<String, WidgetDefinition>,
     local_env: &HashMap<VarName, SimplExpr>,
     w: WidgetUse,
 ) -> AstResult<Box<dyn WidgetNode>> {
-    if let Some(def) = defs.get(&w.name) {
-        if !w.children.is_empty() {
-            Err(AstError::TooManyNodes(w.children_span(), 0).note("User-defined widgets cannot be given children."))?
+    generate_widget_node(defs, local_env, &[], w)
+}
+
+/// Generate the [WidgetNode] for a [WidgetUse].
+/// `children` are the children given to the user-defined widget `w` is part of the definition of,
+/// which will be inserted for any `(children)` placeholders.
+fn generate_widget_node(
+    defs: &HashMap<String, WidgetDefinition>,
+    local_env: &HashMap<VarName, SimplExpr>,
+    children: &[Box<dyn WidgetNode>],
+    w: WidgetUse,
+) -> AstResult<Box<dyn WidgetNode>> {
+    match w {
+        WidgetUse::Basic(w) => generate_basic_widget_node(defs, local_env, children, w),
+        WidgetUse::Loop(w) => generate_loop_widget_node(defs, local_env, children, w),
+        WidgetUse::Children(ChildrenWidgetUse { span, nth_expr }) => {
+            let nth = match nth_expr {
+                Some(nth_expr) => {
+                    let nth_expr = resolve_expr(nth_expr, local_env)?;
+                    let nth = nth_expr.eval_no_vars().map_err(|err| AttrError::EvaluationError(nth_expr.span(), err))?;
+                    Some(nth.as_i32()?)
+                }
+                None => None,
+            };
+            match nth {
+                Some(nth) => children.get(nth as usize).filter(|_| nth >= 0).cloned().ok_or_else(|| {
+                    AstError::MissingNode(span).note(&format!(
+                        "Tried to use child number {}, but only {} children were given",
+                        nth,
+                        children.len()
+                    ))
+                }),
+                None if children.len() == 1 => Ok(children[0].clone()),
+                None => Err(AstError::TooManyNodes(span, 1).note(
+                    "`(children)` can only be used in the place of a single widget when exactly one child is given. Use `:nth` \
+                     to select a single child, or place it inside a container.",
+                )),
+            }
+        }
+    }
+}
+
+/// Substitute the widget arguments referenced in an expression, and fold the parts of it that are constant,
+/// such that they don't need to be evaluated again whenever a variable changes.
+fn resolve_expr(expr: SimplExpr, local_env: &HashMap<VarName, SimplExpr>) -> AstResult<SimplExpr> {
+    let expr = expr.resolve_one_level(local_env);
+    let span = expr.span();
+    Ok(expr.fold_constants().map_err(|err| AttrError::EvaluationError(span, err))?)
+}
+
+/// Generate the [WidgetNode]s for the children of a widget, inserting all of the given `children`
+/// in the place of a `(children)` placeholder that does not select a single child.
+fn generate_children_widget_nodes(
+    defs: &HashMap<String, WidgetDefinition>,
+    local_env: &HashMap<VarName, SimplExpr>,
+    children: &[Box<dyn WidgetNode>],
+    uses: Vec<WidgetUse>,
+) -> AstResult<Vec<Box<dyn WidgetNode>>> {
+    let mut nodes = Vec::new();
+    for child in uses {
+        match child {
+            WidgetUse::Children(ChildrenWidgetUse { nth_expr: None, .. }) => nodes.extend(children.iter().cloned()),
+            child => nodes.push(generate_widget_node(defs, local_env, children, child)?),
         }
+    }
+    Ok(nodes)
+}
+
+fn generate_loop_widget_node(
+    defs: &HashMap<String, WidgetDefinition>,
+    local_env: &HashMap<VarName, SimplExpr>,
+    children: &[Box<dyn WidgetNode>],
+    w: LoopWidgetUse,
+) -> AstResult<Box<dyn WidgetNode>> {
+    // generate the body once, such that errors in it are reported even before the loop has any elements
+    let mut body_env = local_env.clone();
+    body_env.remove(&w.element_name);
+    generate_widget_node(defs, &body_env, children, (*w.body).clone())?;
+
+    Ok(Box::new(Loop {
+        element_name: w.element_name,
+        elements_expr: resolve_expr(w.elements_expr, local_env)?,
+        elements_span: w.elements_expr_span,
+        body: *w.body,
+        local_env: body_env,
+        children: children.to_vec(),
+        span: w.span,
+    }))
+}
 
+fn generate_basic_widget_node(
+    defs: &HashMap<String, WidgetDefinition>,
+    local_env: &HashMap<VarName, SimplExpr>,
+    children: &[Box<dyn WidgetNode>],
+    w: BasicWidgetUse,
+) -> AstResult<Box<dyn WidgetNode>> {
+    if let Some(def) = defs.get(&w.name) {
         let mut new_local_env = w
             .attrs
             .attrs
             .into_iter()
-            .map(|(name, value)| Ok((VarName(name.0), value.value.as_simplexpr()?.resolve_one_level(local_env))))
+            .map(|(name, value)| Ok((VarName(name.0), resolve_expr(value.value.as_simplexpr()?, local_env)?)))
             .collect::<AstResult<HashMap<VarName, _>>>()?;
 
+        let typed_args = def
+            .expected_args
+            .iter()
+            .filter(|expected| expected.arg_type.is_some())
+            .filter_map(|expected| {
+                let value = new_local_env.get(&VarName(expected.name.0.clone())).or_else(|| expected.default.as_ref())?;
+                Some((expected.clone(), value.clone()))
+            })
+            .collect();
+
         // handle default value for optional arguments
         for expected in def.expected_args.iter().filter(|x| x.optional) {
             let var_name = VarName(expected.name.clone().0);
             if !new_local_env.contains_key(&var_name) {
-                new_local_env.insert(var_name, SimplExpr::literal(expected.span, String::new()));
+                let default = expected.default.clone().unwrap_or_else(|| SimplExpr::literal(expected.span, String::new()));
+                new_local_env.insert(var_name, default);
             }
         }
 
-        let content = generate_generic_widget_node(defs, &new_local_env, def.widget.clone())?;
-        Ok(Box::new(UserDefined { name: w.name, span: w.span, content }))
+        // the children are generated in the scope they are given in, not the one of the widget definition
+        let given_children = generate_children_widget_nodes(defs, local_env, children, w.children)?;
+        let content = generate_widget_node(defs, &new_local_env, &given_children, def.widget.clone())?;
+        Ok(Box::new(UserDefined { name: w.name, span: w.span, content, typed_args }))
     } else {
         Ok(Box::new(Generic {
             name: w.name,
@@ -142,14 +373,10 @@ pub fn generate_generic_widget_node(
                 .attrs
                 .attrs
                 .into_iter()
-                .map(|(name, value)| Ok((name, value.value.as_simplexpr()?.resolve_one_level(local_env))))
+                .map(|(name, value)| Ok((name, resolve_expr(value.value.as_simplexpr()?, local_env)?)))
                 .collect::<AstResult<HashMap<_, _>>>()?,
 
-            children: w
-                .children
-                .into_iter()
-                .map(|child| generate_generic_widget_node(defs, local_env, child))
-                .collect::<AstResult<Vec<_>>>()?,
+            children: generate_children_widget_nodes(defs, local_env, children, w.children)?,
         }))
     }
 }
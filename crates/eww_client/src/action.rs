@@ -0,0 +1,184 @@
+use eww_shared_util::VarName;
+use serde::{Deserialize, Serialize};
+use simplexpr::{dynval::DynVal, SimplExpr};
+use std::time::Duration;
+
+use crate::geometry::{AnchorPoint, Coords};
+
+/// An action that is sent to the daemon.
+/// This is the wire format spoken over the IPC socket.
+/// With the `cli` feature enabled, it is also parsed directly from the commands of the `eww` command line interface,
+/// so the documentation of the variants and their fields doubles as their help text.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "cli", derive(structopt::StructOpt))]
+pub enum ActionWithServer {
+    /// Ping the eww server, checking if it is reachable. The daemon responds with `pong`.
+    #[cfg_attr(feature = "cli", structopt(name = "ping"))]
+    Ping,
+
+    /// Update the value of a variable, in a running eww instance
+    #[cfg_attr(feature = "cli", structopt(name = "update", alias = "u"))]
+    Update {
+        /// variable_name="new_value"-pairs that will be updated
+        #[cfg_attr(feature = "cli", structopt(parse(try_from_str = parse_var_update_arg)))]
+        mappings: Vec<(VarName, DynVal)>,
+    },
+
+    /// open a window
+    #[cfg_attr(feature = "cli", structopt(name = "open", alias = "o"))]
+    OpenWindow {
+        /// Name of the window you want to open.
+        window_name: String,
+
+        /// Id of this instance of the window, which allows opening the same window multiple times.
+        /// Defaults to the name of the window.
+        #[cfg_attr(feature = "cli", structopt(long))]
+        id: Option<String>,
+
+        /// Monitor-index the window should open on
+        #[cfg_attr(feature = "cli", structopt(long))]
+        screen: Option<i32>,
+
+        /// The position of the window, where it should open.
+        #[cfg_attr(feature = "cli", structopt(short, long))]
+        pos: Option<Coords>,
+
+        /// The size of the window to open
+        #[cfg_attr(feature = "cli", structopt(short, long))]
+        size: Option<Coords>,
+
+        /// Sidepoint of the window, formatted like "top right"
+        #[cfg_attr(feature = "cli", structopt(short, long))]
+        anchor: Option<AnchorPoint>,
+
+        /// If the window is already open, close it instead
+        #[cfg_attr(feature = "cli", structopt(long = "toggle"))]
+        should_toggle: bool,
+
+        /// Arguments passed to the window, formatted like `name=value`
+        #[cfg_attr(feature = "cli", structopt(long = "arg", parse(try_from_str = parse_window_arg)))]
+        args: Vec<(VarName, DynVal)>,
+    },
+
+    /// Open multiple windows at once.
+    /// NOTE: This will in the future be part of eww open, and will then be removed.
+    #[cfg_attr(feature = "cli", structopt(name = "open-many"))]
+    OpenMany {
+        windows: Vec<String>,
+
+        /// If a window is already open, close it instead
+        #[cfg_attr(feature = "cli", structopt(long = "toggle"))]
+        should_toggle: bool,
+
+        /// Arguments passed to the windows, formatted like `name=value`.
+        /// Each window only receives the arguments it declares.
+        #[cfg_attr(feature = "cli", structopt(long = "arg", parse(try_from_str = parse_window_arg)))]
+        args: Vec<(VarName, DynVal)>,
+    },
+
+    /// Close the given windows, by their instance ids
+    #[cfg_attr(feature = "cli", structopt(name = "close", alias = "c"))]
+    CloseWindows { windows: Vec<String> },
+
+    /// Reload the configuration
+    #[cfg_attr(feature = "cli", structopt(name = "reload", alias = "r"))]
+    Reload,
+
+    /// kill the eww daemon
+    #[cfg_attr(feature = "cli", structopt(name = "kill", alias = "k"))]
+    KillServer,
+
+    /// Close all windows, without killing the daemon
+    #[cfg_attr(feature = "cli", structopt(name = "close-all", alias = "ca"))]
+    CloseAll,
+
+    /// Prints the variables used in all currently open window
+    #[cfg_attr(feature = "cli", structopt(name = "state"))]
+    ShowState {
+        /// Shows all variables, including not currently used ones
+        #[cfg_attr(feature = "cli", structopt(short, long))]
+        all: bool,
+
+        /// Only show the variables used in the window instance with the given id
+        #[cfg_attr(feature = "cli", structopt(long = "window", conflicts_with = "all"))]
+        instance_id: Option<String>,
+    },
+
+    /// Print the current value of variables, in a running eww instance.
+    /// Fails if any of the variables is unknown.
+    #[cfg_attr(feature = "cli", structopt(name = "get"))]
+    Get {
+        /// Names of the variables to print, each on its own line
+        #[cfg_attr(feature = "cli", structopt(required_unless = "expr"))]
+        names: Vec<VarName>,
+
+        /// Evaluate the given simplexpr against the current state, and print its result instead
+        #[cfg_attr(feature = "cli", structopt(short, long, conflicts_with = "names", parse(try_from_str = parse_simplexpr)))]
+        expr: Option<SimplExpr>,
+    },
+
+    /// Print the values of variables whenever they change, in a running eww instance.
+    /// When listening to multiple variables, each value is prefixed with the variables name.
+    #[cfg_attr(feature = "cli", structopt(name = "listen"))]
+    Listen {
+        /// Names of the variables to listen to
+        #[cfg_attr(feature = "cli", structopt(required = true))]
+        names: Vec<VarName>,
+    },
+
+    /// Print the names of all configured windows. Windows with a * in front of them are currently opened.
+    /// If a window is opened with custom ids, these are listed after the window name.
+    #[cfg_attr(feature = "cli", structopt(name = "windows"))]
+    ShowWindows,
+
+    /// Print out the widget structure as seen by eww.
+    ///
+    /// This may be useful if you are facing issues with how eww is interpreting your configuration,
+    /// and to provide additional context to the eww developers if you are filing a bug.
+    #[cfg_attr(feature = "cli", structopt(name = "debug"))]
+    ShowDebug,
+}
+
+impl ActionWithServer {
+    /// How long to wait for the daemon to respond to this action, if not overridden.
+    pub fn default_timeout(&self) -> Duration {
+        match self {
+            ActionWithServer::Reload | ActionWithServer::OpenWindow { .. } | ActionWithServer::OpenMany { .. } => {
+                Duration::from_secs(10)
+            }
+            _ => Duration::from_secs(2),
+        }
+    }
+
+    /// Whether the daemon keeps sending responses to this action, until the client disconnects.
+    pub fn is_streaming(&self) -> bool {
+        matches!(self, ActionWithServer::Listen { .. })
+    }
+
+    /// Whether running this action from the command line starts the daemon if it isn't running yet.
+    pub fn can_start_daemon(&self) -> bool {
+        matches!(self, ActionWithServer::OpenWindow { .. } | ActionWithServer::OpenMany { .. })
+    }
+}
+
+#[cfg(feature = "cli")]
+fn parse_var_update_arg(s: &str) -> anyhow::Result<(VarName, DynVal)> {
+    use anyhow::Context;
+    let (name, value) = s
+        .split_once('=')
+        .with_context(|| format!("arguments must be in the shape `variable_name=\"new_value\"`, but got: {}", s))?;
+    Ok((name.into(), DynVal::from_string(value.to_owned())))
+}
+
+#[cfg(feature = "cli")]
+fn parse_window_arg(s: &str) -> anyhow::Result<(VarName, DynVal)> {
+    use anyhow::Context;
+    let (name, value) =
+        s.split_once('=').with_context(|| format!("arguments must be in the shape `name=value`, but got: {}", s))?;
+    Ok((name.into(), DynVal::from_string(value.to_owned())))
+}
+
+#[cfg(feature = "cli")]
+fn parse_simplexpr(s: &str) -> anyhow::Result<SimplExpr> {
+    simplexpr::parse_string(0, 0, s).map_err(|e| anyhow::anyhow!("Failed to parse expression: {}", e))
+}
@@ -0,0 +1,225 @@
+use anyhow::*;
+use eww_shared_util::VarName;
+use simplexpr::dynval::DynVal;
+use std::{collections::HashMap, path::Path, time::Duration};
+use tokio::net::UnixStream;
+
+use crate::{
+    action::ActionWithServer,
+    client::{handshake_result, open_window_action, HANDSHAKE_TIMEOUT},
+    protocol::{self, Handshake, HandshakeResponse, Request, ServerMessage, VersionMismatchError},
+    response::{DaemonResponse, ResponseOutput, VariableChange},
+};
+
+/// Asynchronous connection to a running eww daemon, for use within a tokio runtime.
+#[derive(Debug)]
+pub struct AsyncClient {
+    stream: UnixStream,
+    timeout: Option<Duration>,
+    next_request_id: u64,
+    /// Set once reading from the daemon failed, e.g. due to a timeout.
+    /// The stream may then be stuck in the middle of a message, so it can't be used for any further requests.
+    broken: bool,
+}
+
+impl AsyncClient {
+    /// Connect to the daemon listening on the given socket, and perform the protocol handshake.
+    /// Fails with a [`VersionMismatchError`] if the daemon runs an incompatible version of eww.
+    pub async fn connect(socket_path: impl AsRef<Path>) -> Result<Self> {
+        let stream = UnixStream::connect(socket_path).await.context("Failed to connect to the eww daemon")?;
+        Self::from_stream(stream).await
+    }
+
+    /// Perform the protocol handshake on an already connected stream.
+    pub async fn from_stream(mut stream: UnixStream) -> Result<Self> {
+        protocol::write_message_async(&mut stream, &Handshake::new()).await.context("Failed to send handshake to daemon")?;
+        let response: Option<HandshakeResponse> =
+            tokio::time::timeout(HANDSHAKE_TIMEOUT, protocol::read_message_async(&mut stream))
+                .await
+                .context("The daemon did not respond to the handshake")??;
+        // daemons running older versions of eww close the connection without responding
+        handshake_result(response.ok_or(VersionMismatchError { daemon_version: None })?)?;
+        Ok(AsyncClient { stream, timeout: None, next_request_id: 0, broken: false })
+    }
+
+    /// Override how long to wait for responses of the daemon.
+    /// If this is not set, the [default timeout](ActionWithServer::default_timeout) of each action is used.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Send an action to the daemon, and wait for its response.
+    /// Fails if the daemon doesn't finish handling the action within the timeout,
+    /// after which the client can't be used anymore, and a new one needs to be [connected](AsyncClient::connect).
+    pub async fn call(&mut self, action: &ActionWithServer) -> Result<Option<DaemonResponse>> {
+        let timeout = self.timeout.unwrap_or_else(|| action.default_timeout());
+        let request_id = self.send_request(action).await?;
+        let stream = &mut self.stream;
+        let read_response = async move {
+            let mut response = None;
+            loop {
+                let message = protocol::read_message_async(stream)
+                    .await?
+                    .context("The daemon closed the connection before finishing the request")?;
+                match message {
+                    ServerMessage::Response { request_id: id, response: r } if id == request_id => response = Some(r),
+                    ServerMessage::Done { request_id: id } if id == request_id => return Ok(response),
+                    other => log::warn!("Received a response for an unknown request: {:?}", other),
+                }
+            }
+        };
+        let result = tokio::time::timeout(timeout, read_response)
+            .await
+            .with_context(|| format!("The daemon did not respond within {}ms", timeout.as_millis()))
+            .and_then(|result| result);
+        self.broken |= result.is_err();
+        result
+    }
+
+    pub async fn ping(&mut self) -> Result<()> {
+        self.call_expecting_success(&ActionWithServer::Ping).await.map(|_| ())
+    }
+
+    /// Open the window with the given name, passing it the given arguments.
+    pub async fn open_window(&mut self, window_name: &str, args: Vec<(VarName, DynVal)>) -> Result<()> {
+        self.call_expecting_success(&open_window_action(window_name, args)).await.map(|_| ())
+    }
+
+    /// Update the values of the given variables.
+    pub async fn update(&mut self, mappings: Vec<(VarName, DynVal)>) -> Result<()> {
+        self.call_expecting_success(&ActionWithServer::Update { mappings }).await.map(|_| ())
+    }
+
+    /// Get the current values of the given variables.
+    pub async fn get(&mut self, names: Vec<VarName>) -> Result<HashMap<VarName, String>> {
+        let output = self.call_expecting_success(&ActionWithServer::Get { names, expr: None }).await?;
+        output.context("The daemon did not respond with the values")?.data()
+    }
+
+    /// Subscribe to changes of the given variables.
+    /// As the daemon keeps streaming changes over this connection, this consumes the client.
+    pub async fn subscribe(mut self, names: Vec<VarName>) -> Result<AsyncSubscription> {
+        let request_id = self.send_request(&ActionWithServer::Listen { names }).await?;
+        Ok(AsyncSubscription { stream: self.stream, request_id })
+    }
+
+    async fn call_expecting_success(&mut self, action: &ActionWithServer) -> Result<Option<ResponseOutput>> {
+        self.call(action).await?.map(DaemonResponse::into_result).transpose()
+    }
+
+    async fn send_request(&mut self, action: &ActionWithServer) -> Result<u64> {
+        if self.broken {
+            bail!("The connection to the daemon was left in an inconsistent state by a previous request, reconnect to it");
+        }
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        protocol::write_message_async(&mut self.stream, &Request { id, action: action.clone() }).await?;
+        Ok(id)
+    }
+}
+
+/// Changes of variables streamed by the daemon, created by [`AsyncClient::subscribe`].
+#[derive(Debug)]
+pub struct AsyncSubscription {
+    stream: UnixStream,
+    request_id: u64,
+}
+
+impl AsyncSubscription {
+    /// Wait for the next change of any of the subscribed variables.
+    /// The current values of the variables are sent first.
+    /// Returns `None` once the daemon stops sending changes.
+    pub async fn next(&mut self) -> Result<Option<VariableChange>> {
+        loop {
+            match protocol::read_message_async(&mut self.stream).await? {
+                Some(ServerMessage::Response { request_id, response }) if request_id == self.request_id => {
+                    return Ok(Some(response.into_result()?.data()?))
+                }
+                Some(ServerMessage::Done { request_id }) if request_id == self.request_id => return Ok(None),
+                Some(other) => log::warn!("Received a response for an unknown request: {:?}", other),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock_daemon;
+
+    fn success() -> Vec<DaemonResponse> {
+        vec![DaemonResponse::Success(ResponseOutput::empty())]
+    }
+
+    fn variable_change(name: &str, value: &str) -> DaemonResponse {
+        let change = VariableChange { name: name.into(), value: value.to_string() };
+        DaemonResponse::Success(ResponseOutput::with_data(value.to_string(), &change).unwrap())
+    }
+
+    /// Connect an [`AsyncClient`] to a mock daemon that answers requests with the given responses
+    async fn connect(
+        respond: impl FnMut(&ActionWithServer) -> Vec<DaemonResponse> + Send + 'static,
+    ) -> (AsyncClient, std::thread::JoinHandle<Vec<ActionWithServer>>) {
+        let (stream, daemon) = mock_daemon::connect(respond);
+        stream.set_nonblocking(true).unwrap();
+        let client = AsyncClient::from_stream(UnixStream::from_std(stream).unwrap()).await.unwrap();
+        (client, daemon)
+    }
+
+    #[tokio::test]
+    async fn test_open_window_and_update() {
+        let (mut client, daemon) = connect(|_| success()).await;
+        let args = vec![("monitor".into(), DynVal::from(1))];
+        client.open_window("bar", args.clone()).await.unwrap();
+        client.update(vec![("volume".into(), DynVal::from(50))]).await.unwrap();
+        drop(client);
+
+        assert_eq!(
+            daemon.join().unwrap(),
+            vec![
+                open_window_action("bar", args),
+                ActionWithServer::Update { mappings: vec![("volume".into(), DynVal::from(50))] }
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get() {
+        let (mut client, daemon) = connect(|_| {
+            let data = serde_json::json!({ "volume": "50" });
+            vec![DaemonResponse::Success(ResponseOutput::with_data("50".to_string(), &data).unwrap())]
+        })
+        .await;
+        let values = client.get(vec!["volume".into()]).await.unwrap();
+        drop(client);
+
+        assert_eq!(values.get(&VarName::from("volume")).map(String::as_str), Some("50"));
+        assert_eq!(daemon.join().unwrap(), vec![ActionWithServer::Get { names: vec!["volume".into()], expr: None }]);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let (client, daemon) = connect(|_| vec![variable_change("volume", "50"), variable_change("muted", "true")]).await;
+        let mut subscription = client.subscribe(vec!["volume".into(), "muted".into()]).await.unwrap();
+        let first = subscription.next().await.unwrap().unwrap();
+        let second = subscription.next().await.unwrap().unwrap();
+        assert_eq!(subscription.next().await.unwrap(), None);
+        drop(subscription);
+
+        assert_eq!((first.name, first.value), (VarName::from("volume"), "50".to_string()));
+        assert_eq!((second.name, second.value), (VarName::from("muted"), "true".to_string()));
+        assert_eq!(daemon.join().unwrap(), vec![ActionWithServer::Listen { names: vec!["volume".into(), "muted".into()] }]);
+    }
+
+    #[tokio::test]
+    async fn test_failure() {
+        let (mut client, daemon) =
+            connect(|_| vec![DaemonResponse::Failure(ResponseOutput::error_message("Unknown variable 'foo'".into()))]).await;
+        let err = client.update(vec![("foo".into(), DynVal::from(1))]).await.unwrap_err();
+        drop(client);
+
+        assert_eq!(err.to_string(), "Unknown variable 'foo'");
+        daemon.join().unwrap();
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::*;
+use eww_shared_util::VarName;
+use simplexpr::dynval::DynVal;
+use std::{collections::HashMap, os::unix::net::UnixStream, path::Path, time::Duration};
+
+use crate::{
+    action::ActionWithServer,
+    protocol::{self, Handshake, HandshakeResponse, Request, ServerMessage, VersionMismatchError},
+    response::{DaemonResponse, ResponseOutput, VariableChange},
+};
+
+/// How long to wait for the daemon to answer the handshake
+pub(crate) const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Blocking connection to a running eww daemon.
+#[derive(Debug)]
+pub struct Client {
+    stream: UnixStream,
+    timeout: Option<Duration>,
+    next_request_id: u64,
+    /// Set once reading from the daemon failed, e.g. due to a timeout.
+    /// The stream may then be stuck in the middle of a message, so it can't be used for any further requests.
+    broken: bool,
+}
+
+impl Client {
+    /// Connect to the daemon listening on the given socket, and perform the protocol handshake.
+    /// Fails with a [`VersionMismatchError`] if the daemon runs an incompatible version of eww.
+    pub fn connect(socket_path: impl AsRef<Path>) -> Result<Self> {
+        let stream = UnixStream::connect(socket_path).context("Failed to connect to the eww daemon")?;
+        Self::from_stream(stream)
+    }
+
+    /// Perform the protocol handshake on an already connected stream.
+    pub fn from_stream(mut stream: UnixStream) -> Result<Self> {
+        stream.set_nonblocking(false).context("Failed to set stream to blocking")?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).context("Failed to set read timeout")?;
+        protocol::write_message(&mut stream, &Handshake::new()).context("Failed to send handshake to daemon")?;
+        // daemons running older versions of eww close the connection without responding
+        let response = protocol::read_message(&mut stream)
+            .map_err(|e| timeout_error(e, HANDSHAKE_TIMEOUT))?
+            .ok_or(VersionMismatchError { daemon_version: None })?;
+        handshake_result(response)?;
+        Ok(Client { stream, timeout: None, next_request_id: 0, broken: false })
+    }
+
+    /// Override how long to wait for responses of the daemon.
+    /// If this is not set, the [default timeout](ActionWithServer::default_timeout) of each action is used.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Send an action to the daemon, and wait for its response.
+    /// Fails if the daemon doesn't finish handling the action within the timeout,
+    /// after which the client can't be used anymore, and a new one needs to be [connected](Client::connect).
+    pub fn call(&mut self, action: &ActionWithServer) -> Result<Option<DaemonResponse>> {
+        let timeout = self.timeout.unwrap_or_else(|| action.default_timeout());
+        let request_id = self.send_request(action)?;
+        self.stream.set_read_timeout(Some(timeout)).context("Failed to set read timeout")?;
+
+        let mut response = None;
+        loop {
+            let message = self
+                .read_message()
+                .map_err(|e| timeout_error(e, timeout))?
+                .context("The daemon closed the connection before finishing the request")?;
+            match message {
+                ServerMessage::Response { request_id: id, response: r } if id == request_id => response = Some(r),
+                ServerMessage::Done { request_id: id } if id == request_id => return Ok(response),
+                other => log::warn!("Received a response for an unknown request: {:?}", other),
+            }
+        }
+    }
+
+    /// Send an action to the daemon, and call the given function for every response the daemon streams back,
+    /// until the daemon finishes the request.
+    pub fn call_streaming(&mut self, action: &ActionWithServer, mut on_response: impl FnMut(DaemonResponse)) -> Result<()> {
+        let request_id = self.send_request(action)?;
+        // responses may be arbitrarily far apart
+        self.stream.set_read_timeout(None).context("Failed to unset read timeout")?;
+        while let Some(message) = self.read_message()? {
+            match message {
+                ServerMessage::Response { request_id: id, response } if id == request_id => on_response(response),
+                ServerMessage::Done { request_id: id } if id == request_id => break,
+                other => log::warn!("Received a response for an unknown request: {:?}", other),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn ping(&mut self) -> Result<()> {
+        self.call_expecting_success(&ActionWithServer::Ping).map(|_| ())
+    }
+
+    /// Open the window with the given name, passing it the given arguments.
+    pub fn open_window(&mut self, window_name: &str, args: Vec<(VarName, DynVal)>) -> Result<()> {
+        self.call_expecting_success(&open_window_action(window_name, args)).map(|_| ())
+    }
+
+    /// Update the values of the given variables.
+    pub fn update(&mut self, mappings: Vec<(VarName, DynVal)>) -> Result<()> {
+        self.call_expecting_success(&ActionWithServer::Update { mappings }).map(|_| ())
+    }
+
+    /// Get the current values of the given variables.
+    pub fn get(&mut self, names: Vec<VarName>) -> Result<HashMap<VarName, String>> {
+        let output = self.call_expecting_success(&ActionWithServer::Get { names, expr: None })?;
+        output.context("The daemon did not respond with the values")?.data()
+    }
+
+    /// Subscribe to changes of the given variables, calling the given function with their current value,
+    /// and again whenever one of them changes. This blocks until the daemon stops sending updates.
+    pub fn subscribe(&mut self, names: Vec<VarName>, mut on_change: impl FnMut(VariableChange)) -> Result<()> {
+        let mut result = Ok(());
+        self.call_streaming(&ActionWithServer::Listen { names }, |response| {
+            if result.is_ok() {
+                result = response.into_result().and_then(|output| output.data()).map(&mut on_change);
+            }
+        })?;
+        result
+    }
+
+    fn call_expecting_success(&mut self, action: &ActionWithServer) -> Result<Option<ResponseOutput>> {
+        self.call(action)?.map(DaemonResponse::into_result).transpose()
+    }
+
+    fn send_request(&mut self, action: &ActionWithServer) -> Result<u64> {
+        if self.broken {
+            bail!("The connection to the daemon was left in an inconsistent state by a previous request, reconnect to it");
+        }
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        protocol::write_message(&mut self.stream, &Request { id, action: action.clone() })?;
+        Ok(id)
+    }
+
+    fn read_message(&mut self) -> Result<Option<ServerMessage>> {
+        let result = protocol::read_message(&mut self.stream);
+        self.broken |= result.is_err();
+        result
+    }
+}
+
+pub(crate) fn handshake_result(response: HandshakeResponse) -> Result<()> {
+    match response {
+        HandshakeResponse::Accepted => Ok(()),
+        HandshakeResponse::VersionMismatch { eww_version, .. } => {
+            Err(VersionMismatchError { daemon_version: Some(eww_version) }.into())
+        }
+    }
+}
+
+pub(crate) fn open_window_action(window_name: &str, args: Vec<(VarName, DynVal)>) -> ActionWithServer {
+    ActionWithServer::OpenWindow {
+        window_name: window_name.to_string(),
+        id: None,
+        screen: None,
+        pos: None,
+        size: None,
+        anchor: None,
+        should_toggle: false,
+        args,
+    }
+}
+
+/// Give a more helpful error message in case reading from the daemon timed out.
+fn timeout_error(err: anyhow::Error, timeout: Duration) -> anyhow::Error {
+    let timed_out = err
+        .downcast_ref::<std::io::Error>()
+        .map_or(false, |e| matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut));
+    if timed_out {
+        err.context(format!("The daemon did not respond within {}ms", timeout.as_millis()))
+    } else {
+        err
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock_daemon;
+
+    fn success() -> Vec<DaemonResponse> {
+        vec![DaemonResponse::Success(ResponseOutput::empty())]
+    }
+
+    fn variable_change(name: &str, value: &str) -> DaemonResponse {
+        let change = VariableChange { name: name.into(), value: value.to_string() };
+        DaemonResponse::Success(ResponseOutput::with_data(value.to_string(), &change).unwrap())
+    }
+
+    #[test]
+    fn test_open_window_and_update() {
+        let (stream, daemon) = mock_daemon::connect(|_| success());
+        let mut client = Client::from_stream(stream).unwrap();
+        let args = vec![("monitor".into(), DynVal::from(1))];
+        client.open_window("bar", args.clone()).unwrap();
+        client.update(vec![("volume".into(), DynVal::from(50))]).unwrap();
+        drop(client);
+
+        assert_eq!(
+            daemon.join().unwrap(),
+            vec![
+                open_window_action("bar", args),
+                ActionWithServer::Update { mappings: vec![("volume".into(), DynVal::from(50))] }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get() {
+        let (stream, daemon) = mock_daemon::connect(|_| {
+            let data = serde_json::json!({ "volume": "50", "muted": "false" });
+            vec![DaemonResponse::Success(ResponseOutput::with_data("50\nfalse".to_string(), &data).unwrap())]
+        });
+        let mut client = Client::from_stream(stream).unwrap();
+        let values = client.get(vec!["volume".into(), "muted".into()]).unwrap();
+        drop(client);
+
+        assert_eq!(values.get(&VarName::from("volume")).map(String::as_str), Some("50"));
+        assert_eq!(values.get(&VarName::from("muted")).map(String::as_str), Some("false"));
+        assert_eq!(
+            daemon.join().unwrap(),
+            vec![ActionWithServer::Get { names: vec!["volume".into(), "muted".into()], expr: None }]
+        );
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let (stream, daemon) = mock_daemon::connect(|_| vec![variable_change("volume", "50"), variable_change("volume", "60")]);
+        let mut client = Client::from_stream(stream).unwrap();
+        let mut changes = Vec::new();
+        client.subscribe(vec!["volume".into()], |change| changes.push(change.value)).unwrap();
+        drop(client);
+
+        assert_eq!(changes, vec!["50", "60"]);
+        assert_eq!(daemon.join().unwrap(), vec![ActionWithServer::Listen { names: vec!["volume".into()] }]);
+    }
+
+    #[test]
+    fn test_failure() {
+        let (stream, daemon) = mock_daemon::connect(|_| {
+            vec![DaemonResponse::Failure(ResponseOutput::error_message("No window named 'bar'".into()))]
+        });
+        let mut client = Client::from_stream(stream).unwrap();
+        let err = client.open_window("bar", Vec::new()).unwrap_err();
+        drop(client);
+
+        assert_eq!(err.to_string(), "No window named 'bar'");
+        daemon.join().unwrap();
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (daemon_stream, stream) = UnixStream::pair().unwrap();
+        let daemon = std::thread::spawn(move || {
+            let mut daemon_stream = daemon_stream;
+            let _: Option<Handshake> = protocol::read_message(&mut daemon_stream).unwrap();
+            protocol::write_message(&mut daemon_stream, &HandshakeResponse::Accepted).unwrap();
+            let _: Option<Request> = protocol::read_message(&mut daemon_stream).unwrap();
+            // never respond, and keep the connection open until the client is done
+            let _: Option<Request> = protocol::read_message(&mut daemon_stream).ok().flatten();
+        });
+        let mut client = Client::from_stream(stream).unwrap();
+        client.set_timeout(Some(Duration::from_millis(50)));
+        let err = client.ping().unwrap_err();
+        assert!(err.to_string().contains("did not respond within 50ms"));
+        assert!(client.ping().unwrap_err().to_string().contains("reconnect"));
+        drop(client);
+        daemon.join().unwrap();
+    }
+
+    #[test]
+    fn test_version_mismatch() {
+        let (mut daemon_stream, stream) = UnixStream::pair().unwrap();
+        let daemon = std::thread::spawn(move || {
+            let _: Option<Handshake> = protocol::read_message(&mut daemon_stream).unwrap();
+            let response = HandshakeResponse::VersionMismatch { protocol_version: 0, eww_version: "0.1.0".to_string() };
+            protocol::write_message(&mut daemon_stream, &response).unwrap();
+        });
+        let err = Client::from_stream(stream).unwrap_err();
+        daemon.join().unwrap();
+
+        let err = err.downcast::<VersionMismatchError>().unwrap();
+        assert_eq!(err.daemon_version.as_deref(), Some("0.1.0"));
+    }
+}
@@ -0,0 +1,138 @@
+//! Geometry of a window, which can be overridden when opening it via [`crate::ActionWithServer::OpenWindow`].
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// A geometry value given in a format that can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseGeometryError(String);
+
+impl std::error::Error for ParseGeometryError {}
+impl std::fmt::Display for ParseGeometryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A length that is either given in pixels, or relative to the size of the monitor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NumWithUnit {
+    Percent(i32),
+    Pixels(i32),
+}
+
+/// Parses a number of pixels like `50` or `50px`, or a percentage like `50%`.
+impl FromStr for NumWithUnit {
+    type Err = ParseGeometryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit() && c != '-').unwrap_or_else(|| s.len()));
+        let number = number.parse().map_err(|_| ParseGeometryError(format!("Failed to parse \"{}\" as a length value", s)))?;
+        match unit {
+            "px" | "" => Ok(NumWithUnit::Pixels(number)),
+            "%" => Ok(NumWithUnit::Percent(number)),
+            unit => Err(ParseGeometryError(format!("Invalid unit \"{}\", must be either % or px", unit))),
+        }
+    }
+}
+
+/// A position or size, given for both axes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Coords {
+    pub x: NumWithUnit,
+    pub y: NumWithUnit,
+}
+
+/// Parses coordinates formatted like `200x100` or `50%*20px`.
+impl FromStr for Coords {
+    type Err = ParseGeometryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (x, y) = s
+            .split_once(|c: char| c.eq_ignore_ascii_case(&'x') || c == '*')
+            .ok_or_else(|| ParseGeometryError("Invalid format. Coordinates must be formated like 200x100".to_string()))?;
+        Ok(Coords { x: x.parse()?, y: y.parse()? })
+    }
+}
+
+impl Coords {
+    pub fn from_pixels(x: i32, y: i32) -> Self {
+        Coords { x: NumWithUnit::Pixels(x), y: NumWithUnit::Pixels(y) }
+    }
+}
+
+/// Where a window is aligned along one axis of the monitor.
+/// `Start` is the left or the top, and `End` the right or the bottom side of the monitor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnchorAlignment {
+    Start,
+    Center,
+    End,
+}
+
+/// The point of the monitor a window is positioned relative to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnchorPoint {
+    pub x: AnchorAlignment,
+    pub y: AnchorAlignment,
+}
+
+impl AnchorAlignment {
+    fn from_x_alignment(s: &str) -> Option<Self> {
+        match s {
+            "l" | "left" => Some(AnchorAlignment::Start),
+            "c" | "center" => Some(AnchorAlignment::Center),
+            "r" | "right" => Some(AnchorAlignment::End),
+            _ => None,
+        }
+    }
+
+    fn from_y_alignment(s: &str) -> Option<Self> {
+        match s {
+            "t" | "top" => Some(AnchorAlignment::Start),
+            "c" | "center" => Some(AnchorAlignment::Center),
+            "b" | "bottom" => Some(AnchorAlignment::End),
+            _ => None,
+        }
+    }
+}
+
+/// Parses an anchor point formatted like `top right` or `right top`, or just `center`.
+impl FromStr for AnchorPoint {
+    type Err = ParseGeometryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let wrong_format = || {
+            ParseGeometryError("Could not parse anchor: Must either be \"center\" or be formatted like \"top left\"".to_string())
+        };
+        if s == "center" {
+            return Ok(AnchorPoint { x: AnchorAlignment::Center, y: AnchorAlignment::Center });
+        }
+        let (first, second) = s.split_once(' ').ok_or_else(wrong_format)?;
+        let x_y = AnchorAlignment::from_x_alignment(first).zip(AnchorAlignment::from_y_alignment(second));
+        let y_x = AnchorAlignment::from_x_alignment(second).zip(AnchorAlignment::from_y_alignment(first));
+        x_y.or(y_x).map(|(x, y)| AnchorPoint { x, y }).ok_or_else(wrong_format)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_coords() {
+        assert_eq!("50x60".parse(), Ok(Coords::from_pixels(50, 60)));
+        assert_eq!("-10%*20px".parse(), Ok(Coords { x: NumWithUnit::Percent(-10), y: NumWithUnit::Pixels(20) }));
+        assert!("5060".parse::<Coords>().is_err());
+        assert!("50pp".parse::<NumWithUnit>().is_err());
+    }
+
+    #[test]
+    fn test_parse_anchor_point() {
+        use AnchorAlignment::*;
+        assert_eq!("top right".parse(), Ok(AnchorPoint { x: End, y: Start }));
+        assert_eq!("right top".parse(), Ok(AnchorPoint { x: End, y: Start }));
+        assert_eq!("center".parse(), Ok(AnchorPoint { x: Center, y: Center }));
+        assert!("top bottom".parse::<AnchorPoint>().is_err());
+    }
+}
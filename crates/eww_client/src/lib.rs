@@ -0,0 +1,29 @@
+//! Client library to control a running eww daemon over its IPC socket.
+//!
+//! This contains the types that make up the wire format spoken between the `eww` command line client and the daemon,
+//! as well as a blocking [`Client`] and an [`AsyncClient`] to talk to the daemon.
+//! Enabling the `cli` feature allows parsing an [`ActionWithServer`] from command line arguments, using `structopt`.
+//!
+//! ```no_run
+//! use eww_client::Client;
+//!
+//! let mut client = Client::connect("/run/user/1000/eww-server_...")?;
+//! client.update(vec![("volume".into(), "50".to_string().into())])?;
+//! client.open_window("bar", Vec::new())?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod action;
+pub mod async_client;
+pub mod client;
+pub mod geometry;
+#[cfg(test)]
+mod mock_daemon;
+pub mod protocol;
+pub mod response;
+
+pub use action::ActionWithServer;
+pub use async_client::{AsyncClient, AsyncSubscription};
+pub use client::Client;
+pub use protocol::VersionMismatchError;
+pub use response::{DaemonResponse, ResponseOutput};
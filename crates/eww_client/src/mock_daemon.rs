@@ -0,0 +1,41 @@
+//! A fake daemon that the clients can be tested against.
+
+use std::{os::unix::net::UnixStream, thread::JoinHandle};
+
+use crate::{
+    action::ActionWithServer,
+    protocol::{self, Handshake, HandshakeResponse, Request, ServerMessage},
+    response::DaemonResponse,
+};
+
+/// Serve a single client on the given stream like the daemon would,
+/// answering every request with the responses that `respond` returns for it.
+/// Once the client disconnects, the thread returns all actions it received.
+pub fn spawn(
+    mut stream: UnixStream,
+    mut respond: impl FnMut(&ActionWithServer) -> Vec<DaemonResponse> + Send + 'static,
+) -> JoinHandle<Vec<ActionWithServer>> {
+    std::thread::spawn(move || {
+        let handshake: Handshake = protocol::read_message(&mut stream).unwrap().unwrap();
+        assert!(handshake.is_compatible());
+        protocol::write_message(&mut stream, &HandshakeResponse::Accepted).unwrap();
+
+        let mut received = Vec::new();
+        while let Some(Request { id, action }) = protocol::read_message(&mut stream).unwrap() {
+            for response in respond(&action) {
+                protocol::write_message(&mut stream, &ServerMessage::Response { request_id: id, response }).unwrap();
+            }
+            protocol::write_message(&mut stream, &ServerMessage::Done { request_id: id }).unwrap();
+            received.push(action);
+        }
+        received
+    })
+}
+
+/// Create a connected pair of streams, with a mock daemon serving the second one.
+pub fn connect(
+    respond: impl FnMut(&ActionWithServer) -> Vec<DaemonResponse> + Send + 'static,
+) -> (UnixStream, JoinHandle<Vec<ActionWithServer>>) {
+    let (client_stream, daemon_stream) = UnixStream::pair().unwrap();
+    (client_stream, spawn(daemon_stream, respond))
+}
@@ -0,0 +1,192 @@
+//! The protocol spoken between the eww client and the daemon over the IPC socket.
+//!
+//! Every message is framed by prefixing it with its size as a 4-byte big-endian integer,
+//! followed by the bincode-serialized message itself.
+//!
+//! After connecting, the client sends a [`Handshake`], which the daemon answers with a [`HandshakeResponse`].
+//! Once the handshake was accepted, the client may send any number of [`Request`]s.
+//! The daemon answers each request with any number of [`ServerMessage::Response`]s,
+//! followed by a [`ServerMessage::Done`] once it won't send any more responses for that request.
+
+use anyhow::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::{Read, Write};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{action::ActionWithServer, response::DaemonResponse};
+
+/// Version of the IPC protocol. This needs to be incremented whenever any of the messages change.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Sent at the start of every handshake.
+/// Daemons running versions of eww from before the handshake was introduced fail to parse this, and close the connection.
+const HANDSHAKE_MAGIC: [u8; 4] = *b"eww!";
+
+/// Largest message accepted on either side of the connection, such that a corrupted or malicious size header
+/// can't make the reader allocate an arbitrary amount of memory.
+pub const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Handshake {
+    magic: [u8; 4],
+    pub protocol_version: u32,
+    pub eww_version: String,
+}
+
+impl Handshake {
+    pub fn new() -> Self {
+        Handshake { magic: HANDSHAKE_MAGIC, protocol_version: PROTOCOL_VERSION, eww_version: eww_version() }
+    }
+
+    pub fn is_compatible(&self) -> bool {
+        self.magic == HANDSHAKE_MAGIC && self.protocol_version == PROTOCOL_VERSION
+    }
+}
+
+impl Default for Handshake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HandshakeResponse {
+    Accepted,
+    VersionMismatch { protocol_version: u32, eww_version: String },
+}
+
+impl HandshakeResponse {
+    /// Rejects the handshake of a client, reporting the versions used on this side of the connection.
+    pub fn version_mismatch() -> Self {
+        HandshakeResponse::VersionMismatch { protocol_version: PROTOCOL_VERSION, eww_version: eww_version() }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    pub id: u64,
+    pub action: ActionWithServer,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServerMessage {
+    Response { request_id: u64, response: DaemonResponse },
+    Done { request_id: u64 },
+}
+
+/// The client and the daemon can't communicate, as they are running incompatible versions of eww.
+#[derive(Debug)]
+pub struct VersionMismatchError {
+    /// Version of the daemon, if it was able to report it
+    pub daemon_version: Option<String>,
+}
+
+impl std::error::Error for VersionMismatchError {}
+impl std::fmt::Display for VersionMismatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.daemon_version {
+            Some(daemon_version) => write!(
+                f,
+                "The running eww daemon (version {}) is incompatible with this eww client (version {})",
+                daemon_version,
+                eww_version()
+            )?,
+            None => write!(
+                f,
+                "The running eww daemon closed the connection during the handshake, it is likely running an older version of \
+                 eww than this client (version {})",
+                eww_version()
+            )?,
+        }
+        write!(f, ". Restart the daemon by running `eww kill`, and try again.")
+    }
+}
+
+fn eww_version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+/// Write a single framed message.
+pub fn write_message<T: Serialize, W: Write>(stream: &mut W, message: &T) -> Result<()> {
+    let message_bytes = bincode::serialize(message)?;
+    check_message_size(message_bytes.len())?;
+    stream.write_all(&(message_bytes.len() as u32).to_be_bytes()).context("Failed to send message size header to IPC stream")?;
+    stream.write_all(&message_bytes).context("Failed to write message to IPC stream")?;
+    Ok(())
+}
+
+/// Read a single framed message. Returns `None` if the stream was closed before a new message started.
+pub fn read_message<T: DeserializeOwned, R: Read>(stream: &mut R) -> Result<Option<T>> {
+    let mut message_byte_length = [0u8; 4];
+    match stream.read_exact(&mut message_byte_length) {
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result.context("Failed to read message size header from IPC stream")?,
+    }
+    let mut message = vec![0u8; check_message_size(u32::from_be_bytes(message_byte_length) as usize)?];
+    stream.read_exact(&mut message).context("Failed to read message from IPC stream")?;
+    Ok(Some(bincode::deserialize(&message).context("Failed to parse IPC message")?))
+}
+
+/// Write a single framed message to an async stream.
+pub async fn write_message_async<T: Serialize, W: AsyncWrite + Unpin>(stream: &mut W, message: &T) -> Result<()> {
+    let message_bytes = bincode::serialize(message)?;
+    check_message_size(message_bytes.len())?;
+    stream
+        .write_all(&(message_bytes.len() as u32).to_be_bytes())
+        .await
+        .context("Failed to send message size header to IPC stream")?;
+    stream.write_all(&message_bytes).await.context("Failed to write message to IPC stream")?;
+    Ok(())
+}
+
+/// Read a single framed message from an async stream. Returns `None` if the stream was closed before a new message started.
+pub async fn read_message_async<T: DeserializeOwned, R: AsyncRead + Unpin>(stream: &mut R) -> Result<Option<T>> {
+    let mut message_byte_length = [0u8; 4];
+    match stream.read_exact(&mut message_byte_length).await {
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result.context("Failed to read message size header from IPC stream")?,
+    };
+    let mut message = vec![0u8; check_message_size(u32::from_be_bytes(message_byte_length) as usize)?];
+    stream.read_exact(&mut message).await.context("Failed to read message from IPC stream")?;
+    Ok(Some(bincode::deserialize(&message).context("Failed to parse IPC message")?))
+}
+
+fn check_message_size(size: usize) -> Result<usize> {
+    if size > MAX_MESSAGE_SIZE {
+        bail!("IPC message of {} bytes exceeds the maximum message size of {} bytes", size, MAX_MESSAGE_SIZE);
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_message_roundtrip() {
+        let request = Request { id: 3, action: ActionWithServer::Reload };
+        let mut buf = Vec::new();
+        write_message(&mut buf, &request).unwrap();
+        write_message(&mut buf, &Handshake::new()).unwrap();
+
+        let mut reader = buf.as_slice();
+        assert_eq!(read_message::<Request, _>(&mut reader).unwrap(), Some(request));
+        assert_eq!(read_message::<Handshake, _>(&mut reader).unwrap(), Some(Handshake::new()));
+        assert_eq!(read_message::<Request, _>(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_oversized_message() {
+        let mut buf = ((MAX_MESSAGE_SIZE + 1) as u32).to_be_bytes().to_vec();
+        buf.extend_from_slice(&[0u8; 16]);
+        assert!(read_message::<Request, _>(&mut buf.as_slice()).is_err());
+        assert!(write_message(&mut Vec::new(), &vec![0u8; MAX_MESSAGE_SIZE]).is_err());
+    }
+
+    #[test]
+    fn test_handshake_compatibility() {
+        assert!(Handshake::new().is_compatible());
+        let outdated = Handshake { protocol_version: PROTOCOL_VERSION + 1, ..Handshake::new() };
+        assert!(!outdated.is_compatible());
+    }
+}
@@ -0,0 +1,72 @@
+use anyhow::*;
+use eww_shared_util::VarName;
+
+/// Response that the daemon sends as a response to an action.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, derive_more::Display)]
+pub enum DaemonResponse {
+    Success(ResponseOutput),
+    Failure(ResponseOutput),
+}
+
+impl DaemonResponse {
+    pub fn is_success(&self) -> bool {
+        matches!(self, DaemonResponse::Success(_))
+    }
+
+    pub fn is_failure(&self) -> bool {
+        !self.is_success()
+    }
+
+    /// Turn a [`DaemonResponse::Failure`] into an `Err` containing its text.
+    pub fn into_result(self) -> Result<ResponseOutput> {
+        match self {
+            DaemonResponse::Success(output) => Ok(output),
+            DaemonResponse::Failure(output) => Err(anyhow!("{}", output)),
+        }
+    }
+}
+
+/// Output of a command, both as human-readable text, and as a json document for use in scripts.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, derive_more::Display)]
+#[display(fmt = "{}", text)]
+pub struct ResponseOutput {
+    pub text: String,
+    /// The serialized json document. This is kept as a string, as bincode can't deserialize arbitrary json values.
+    pub json: String,
+}
+
+impl ResponseOutput {
+    /// Output without any content
+    pub fn empty() -> Self {
+        ResponseOutput { text: String::new(), json: serde_json::Value::Null.to_string() }
+    }
+
+    /// Output that is represented as a simple json string
+    pub fn text(text: String) -> Self {
+        let json = serde_json::Value::String(text.clone()).to_string();
+        ResponseOutput { text, json }
+    }
+
+    pub fn with_data(text: String, data: &impl serde::Serialize) -> Result<Self> {
+        Ok(ResponseOutput { text, json: serde_json::to_string(data)? })
+    }
+
+    /// Output describing a failure with the given message
+    pub fn error_message(message: String) -> Self {
+        let json = serde_json::json!({ "errors": [{ "message": message }] }).to_string();
+        ResponseOutput { text: message, json }
+    }
+
+    /// Parse the json document of this output.
+    pub fn data<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+        serde_json::from_str(&self.json).context("Failed to parse json output of the daemon")
+    }
+}
+
+/// A new value of a variable, as streamed to clients that subscribed to it.
+/// This is the json representation of the responses to [`crate::ActionWithServer::Listen`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct VariableChange {
+    pub name: VarName,
+    pub value: String,
+}
@@ -0,0 +1,74 @@
+//! Compares evaluating the expressions of a bar with hundreds of bindings directly from the AST,
+//! as eww used to do on every variable change, against evaluating their compiled form.
+//! Note that the interpreter caches the regexes it parses as well, so this mostly measures the cost of resolving variables
+//! and re-evaluating constant sub-expressions.
+//! Run via `cargo bench -p simplexpr`.
+#![feature(test)]
+extern crate test;
+
+use std::collections::HashMap;
+
+use eww_shared_util::VarName;
+use simplexpr::{compiled::CompiledExpr, dynval::DynVal, SimplExpr};
+use test::Bencher;
+
+const BINDINGS: usize = 300;
+
+const TEMPLATES: &[&str] = &[
+    r#""${round(EWW_CPU.avg, 1)}%""#,
+    r#"EWW_CPU.avg > 80 ? "high" : EWW_CPU.avg > 40 ? "medium" : "low""#,
+    r#"replace(title, "\\s+-\\s+Mozilla Firefox$", "")"#,
+    r#"title =~ "^\\[.*\\]" ? "tagged" : "plain""#,
+    r#""${EWW_RAM.used_mem:.1bytes} / ${formatbytes(16 * 1024 * 1024 * 1024)}""#,
+    r#"let disk = EWW_DISK["/"] in disk.used_perc > 90 ? "full" : "${disk.used_perc:>5.1}%""#,
+    r#"map(workspaces, ws => ws.name + (ws.focused ? "*" : ""))"#,
+    r#"volume ?? 0 + (3 * 4 - 2) / 5"#,
+];
+
+fn variables() -> HashMap<VarName, DynVal> {
+    let variables = vec![
+        ("EWW_CPU", r#"{"avg": 42.3, "cores": [{"core": "cpu0", "usage": 20}]}"#),
+        ("EWW_RAM", r#"{"used_mem": 4563402752, "total_mem": 16777216000}"#),
+        ("EWW_DISK", r#"{"/": {"used_perc": 63.2, "free": 1000000}}"#),
+        ("title", "[eww] simplexpr - Mozilla Firefox"),
+        ("workspaces", r#"[{"name": "1", "focused": false}, {"name": "2", "focused": true}, {"name": "3", "focused": false}]"#),
+        ("volume", "55"),
+    ];
+    variables.into_iter().map(|(name, value)| (VarName::from(name), DynVal::from(value))).collect()
+}
+
+fn expressions() -> Vec<SimplExpr> {
+    (0..BINDINGS).map(|i| simplexpr::parse_string(0, 0, TEMPLATES[i % TEMPLATES.len()]).unwrap()).collect()
+}
+
+#[bench]
+fn bench_interpreted(b: &mut Bencher) {
+    let variables = variables();
+    let expressions = expressions();
+    b.iter(|| {
+        for expr in &expressions {
+            test::black_box(expr.clone().eval(&variables).unwrap());
+        }
+    });
+}
+
+#[bench]
+fn bench_compiled(b: &mut Bencher) {
+    let variables = variables();
+    let expressions = expressions().iter().map(CompiledExpr::new).collect::<Vec<_>>();
+    b.iter(|| {
+        for expr in &expressions {
+            test::black_box(expr.eval(&variables).unwrap());
+        }
+    });
+}
+
+#[bench]
+fn bench_compile(b: &mut Bencher) {
+    let expressions = expressions();
+    b.iter(|| {
+        for expr in &expressions {
+            test::black_box(CompiledExpr::new(expr));
+        }
+    });
+}
@@ -1,4 +1,4 @@
-use crate::dynval::DynVal;
+use crate::{dynval::DynVal, format_spec::FormatSpec};
 use eww_shared_util::{Span, Spanned};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
@@ -12,14 +12,19 @@ pub enum BinOp {
     #[strum(serialize = "-") ] Minus,
     #[strum(serialize = "*") ] Times,
     #[strum(serialize = "/") ] Div,
+    #[strum(serialize = "//")] IntDiv,
     #[strum(serialize = "%") ] Mod,
+    #[strum(serialize = "**")] Pow,
     #[strum(serialize = "==")] Equals,
     #[strum(serialize = "!=")] NotEquals,
     #[strum(serialize = "&&")] And,
     #[strum(serialize = "||")] Or,
     #[strum(serialize = ">") ] GT,
     #[strum(serialize = "<") ] LT,
+    #[strum(serialize = ">=")] GE,
+    #[strum(serialize = "<=")] LE,
     #[strum(serialize = "?:")] Elvis,
+    #[strum(serialize = "??")] NullCoalesce,
     #[strum(serialize = "=~")] RegexMatch,
 }
 
@@ -27,11 +32,18 @@ pub enum BinOp {
 pub enum UnaryOp {
     #[strum(serialize = "!")]
     Not,
+    #[strum(serialize = "-")]
+    Negative,
 }
 
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SimplExpr {
+    /// A string literal, or another constant that is known to be a string
     Literal(DynVal),
+    /// A number or boolean literal, or a value that was substituted or computed ahead of time.
+    /// Evaluates just like a [SimplExpr::Literal], but keeps the json type given by its text when used in a json literal,
+    /// rather than always being a json string.
+    Value(DynVal),
     JsonArray(Span, Vec<SimplExpr>),
     JsonObject(Span, Vec<(SimplExpr, SimplExpr)>),
     Concat(Span, Vec<SimplExpr>),
@@ -40,18 +52,27 @@ pub enum SimplExpr {
     UnaryOp(Span, UnaryOp, Box<SimplExpr>),
     IfElse(Span, Box<SimplExpr>, Box<SimplExpr>, Box<SimplExpr>),
     JsonAccess(Span, Box<SimplExpr>, Box<SimplExpr>),
+    /// Optional json access (`value?.field`), which results in `null` instead of failing if the value can't be indexed
+    JsonSafeAccess(Span, Box<SimplExpr>, Box<SimplExpr>),
     FunctionCall(Span, String, Vec<SimplExpr>),
+    /// A function of one argument, like `x => x.usage`, which can only be passed to functions like `map`
+    Lambda(Span, VarName, Box<SimplExpr>),
+    /// `let name = value in body`, binding a name that can be referenced within the body
+    Let(Span, VarName, Box<SimplExpr>, Box<SimplExpr>),
+    /// A value within a string interpolation that is formatted according to a format spec, as in `"${value:.2}"`
+    Formatted(Span, Box<SimplExpr>, FormatSpec),
 }
 
 impl std::fmt::Display for SimplExpr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            SimplExpr::Literal(x) => write!(f, "\"{}\"", x),
+            SimplExpr::Literal(x) | SimplExpr::Value(x) => write!(f, "\"{}\"", x),
             SimplExpr::Concat(_, elems) => {
                 let text = elems
                     .iter()
                     .map(|x| match x {
-                        SimplExpr::Literal(lit) => lit.to_string(),
+                        SimplExpr::Literal(lit) | SimplExpr::Value(lit) => lit.to_string(),
+                        SimplExpr::Formatted(_, value, spec) => format!("${{{}:{}}}", value, spec),
                         other => format!("${{{}}}", other),
                     })
                     .join("");
@@ -62,9 +83,13 @@ impl std::fmt::Display for SimplExpr {
             SimplExpr::UnaryOp(_, op, x) => write!(f, "{}{}", op, x),
             SimplExpr::IfElse(_, a, b, c) => write!(f, "({} ? {} : {})", a, b, c),
             SimplExpr::JsonAccess(_, value, index) => write!(f, "{}[{}]", value, index),
+            SimplExpr::JsonSafeAccess(_, value, index) => write!(f, "{}?.[{}]", value, index),
             SimplExpr::FunctionCall(_, function_name, args) => {
                 write!(f, "{}({})", function_name, args.iter().join(", "))
             }
+            SimplExpr::Lambda(_, param, body) => write!(f, "{} => {}", param, body),
+            SimplExpr::Let(_, name, value, body) => write!(f, "(let {} = {} in {})", name, value, body),
+            SimplExpr::Formatted(_, value, spec) => write!(f, "\"${{{}:{}}}\"", value, spec),
             SimplExpr::JsonArray(_, values) => write!(f, "[{}]", values.iter().join(", ")),
             SimplExpr::JsonObject(_, entries) => {
                 write!(f, "{{{}}}", entries.iter().map(|(k, v)| format!("{}: {}", k, v)).join(", "))
@@ -74,12 +99,21 @@ impl std::fmt::Display for SimplExpr {
 }
 impl SimplExpr {
     pub fn literal(span: Span, s: String) -> Self {
-        Self::Literal(DynVal(s, span))
+        Self::Literal(DynVal::from_string(s).at(span))
+    }
+
+    pub fn value(span: Span, s: String) -> Self {
+        Self::Value(DynVal::from_string(s).at(span))
+    }
+
+    /// Whether this expression is a string literal or a string interpolation, which always result in a string.
+    pub fn is_string(&self) -> bool {
+        matches!(self, SimplExpr::Literal(_) | SimplExpr::Concat(..) | SimplExpr::Formatted(..))
     }
 
     /// Construct a synthetic simplexpr from a literal string, without adding any relevant span information (uses [DUMMY_SPAN])
     pub fn synth_string(s: String) -> Self {
-        Self::Literal(DynVal(s, Span::DUMMY))
+        Self::Literal(DynVal::from_string(s))
     }
 
     /// Construct a synthetic simplexpr from a literal dynval, without adding any relevant span information (uses [DUMMY_SPAN])
@@ -91,7 +125,7 @@ impl SimplExpr {
 impl Spanned for SimplExpr {
     fn span(&self) -> Span {
         match self {
-            SimplExpr::Literal(x) => x.span(),
+            SimplExpr::Literal(x) | SimplExpr::Value(x) => x.span(),
             SimplExpr::JsonArray(span, _) => *span,
             SimplExpr::JsonObject(span, _) => *span,
             SimplExpr::Concat(span, _) => *span,
@@ -100,7 +134,11 @@ impl Spanned for SimplExpr {
             SimplExpr::UnaryOp(span, ..) => *span,
             SimplExpr::IfElse(span, ..) => *span,
             SimplExpr::JsonAccess(span, ..) => *span,
+            SimplExpr::JsonSafeAccess(span, ..) => *span,
             SimplExpr::FunctionCall(span, ..) => *span,
+            SimplExpr::Lambda(span, ..) => *span,
+            SimplExpr::Let(span, ..) => *span,
+            SimplExpr::Formatted(span, ..) => *span,
         }
     }
 }
@@ -0,0 +1,476 @@
+use std::collections::HashMap;
+
+use eww_shared_util::{Span, Spanned, VarName};
+
+use crate::{
+    ast::{BinOp, SimplExpr, UnaryOp},
+    dynval::DynVal,
+    eval::{self, EvalError},
+    format_spec::FormatSpec,
+    functions::FunctionRegistry,
+};
+
+/// A [SimplExpr] that has been prepared for repeated evaluation.
+///
+/// Variables are resolved to slots once, such that evaluating only needs a single lookup per variable,
+/// sub-expressions that don't reference any variables are evaluated ahead of time,
+/// and regexes and format specs that are given as constants are only parsed once.
+#[derive(Debug, Clone)]
+pub struct CompiledExpr {
+    root: Node,
+    /// The variables referenced by the expression, indexed by their slot
+    variables: Vec<VarName>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Const(DynVal),
+    /// A variable given to the expression, by its slot
+    Var(Span, usize),
+    /// A variable bound within the expression via `let` or a lambda, by its position on the stack of local values
+    Local(Span, usize),
+    Concat(Span, Vec<Node>),
+    BinOp(Span, Box<Node>, BinOp, Box<Node>),
+    RegexMatch(Span, Box<Node>, regex::Regex),
+    UnaryOp(Span, UnaryOp, Box<Node>),
+    IfElse(Span, Box<Node>, Box<Node>, Box<Node>),
+    JsonAccess(Span, Box<Node>, Box<Node>),
+    JsonSafeAccess(Span, Box<Node>, Box<Node>),
+    FunctionCall(Span, String, Vec<Node>),
+    /// A function like `map` called with a collection and a lambda, whose parameter is pushed onto the stack of local values
+    LambdaCall(Span, String, Box<Node>, Box<Node>),
+    Let(Span, Box<Node>, Box<Node>),
+    /// A format spec that failed to parse is kept as a string, to fail once the expression is evaluated
+    Formatted(Span, Box<Node>, FormatSpec),
+    /// A json array literal, with whether each of its values is a string (see [SimplExpr::is_string])
+    JsonArray(Span, Vec<(Node, bool)>),
+    JsonObject(Span, Vec<(Node, Node, bool)>),
+    /// Expressions that can only ever fail to evaluate, like lambdas outside of function calls
+    Uncompiled(SimplExpr),
+}
+
+impl Node {
+    fn span(&self) -> Span {
+        match self {
+            Node::Const(x) => x.span(),
+            Node::Uncompiled(x) => x.span(),
+            Node::Var(span, ..)
+            | Node::Local(span, ..)
+            | Node::Concat(span, ..)
+            | Node::BinOp(span, ..)
+            | Node::RegexMatch(span, ..)
+            | Node::UnaryOp(span, ..)
+            | Node::IfElse(span, ..)
+            | Node::JsonAccess(span, ..)
+            | Node::JsonSafeAccess(span, ..)
+            | Node::FunctionCall(span, ..)
+            | Node::LambdaCall(span, ..)
+            | Node::Let(span, ..)
+            | Node::Formatted(span, ..)
+            | Node::JsonArray(span, ..)
+            | Node::JsonObject(span, ..) => *span,
+        }
+    }
+}
+
+struct Compiler<'a> {
+    functions: &'a FunctionRegistry,
+    variables: Vec<VarName>,
+    /// The names bound by the surrounding `let`s and lambdas, innermost last
+    locals: Vec<VarName>,
+}
+
+impl<'a> Compiler<'a> {
+    fn compile(&mut self, expr: &SimplExpr) -> Node {
+        let mut compile_box = |x: &SimplExpr| Box::new(self.compile(x));
+        let node = match expr {
+            SimplExpr::Literal(x) | SimplExpr::Value(x) => return Node::Const(x.clone()),
+            SimplExpr::VarRef(span, name) => match self.locals.iter().rposition(|x| x == name) {
+                Some(position) => Node::Local(*span, position),
+                None => Node::Var(*span, self.slot(name)),
+            },
+            SimplExpr::Concat(span, elems) => Node::Concat(*span, elems.iter().map(|x| self.compile(x)).collect()),
+            SimplExpr::BinOp(span, a, BinOp::RegexMatch, b) => {
+                let a = compile_box(a);
+                match self.compile(b) {
+                    Node::Const(pattern) => match regex::Regex::new(&pattern.0) {
+                        Ok(regex) => Node::RegexMatch(*span, a, regex),
+                        Err(_) => Node::BinOp(*span, a, BinOp::RegexMatch, Box::new(Node::Const(pattern))),
+                    },
+                    b => Node::BinOp(*span, a, BinOp::RegexMatch, Box::new(b)),
+                }
+            }
+            SimplExpr::BinOp(span, a, BinOp::NullCoalesce, b) => return self.compile_null_coalesce(*span, a, b),
+            SimplExpr::BinOp(span, a, op, b) => Node::BinOp(*span, compile_box(a), op.clone(), compile_box(b)),
+            SimplExpr::UnaryOp(span, op, a) => Node::UnaryOp(*span, op.clone(), compile_box(a)),
+            SimplExpr::IfElse(span, a, b, c) => Node::IfElse(*span, compile_box(a), compile_box(b), compile_box(c)),
+            SimplExpr::JsonAccess(span, a, b) => Node::JsonAccess(*span, compile_box(a), compile_box(b)),
+            SimplExpr::JsonSafeAccess(span, a, b) => Node::JsonSafeAccess(*span, compile_box(a), compile_box(b)),
+            SimplExpr::FunctionCall(span, name, args) => match args.as_slice() {
+                [collection, SimplExpr::Lambda(_, param, body)] => {
+                    let collection = self.compile(collection);
+                    let body = self.compile_in_scope(param, body);
+                    Node::LambdaCall(*span, name.clone(), Box::new(collection), Box::new(body))
+                }
+                _ => Node::FunctionCall(*span, name.clone(), args.iter().map(|x| self.compile(x)).collect()),
+            },
+            SimplExpr::Let(span, name, value, body) => {
+                let value = self.compile(value);
+                let body = self.compile_in_scope(name, body);
+                Node::Let(*span, Box::new(value), Box::new(body))
+            }
+            SimplExpr::Formatted(span, value, spec) => Node::Formatted(*span, compile_box(value), spec.clone()),
+            SimplExpr::JsonArray(span, values) => {
+                Node::JsonArray(*span, values.iter().map(|x| (self.compile(x), x.is_string())).collect())
+            }
+            SimplExpr::JsonObject(span, entries) => {
+                Node::JsonObject(*span, entries.iter().map(|(k, v)| (self.compile(k), self.compile(v), v.is_string())).collect())
+            }
+            SimplExpr::Lambda(..) => return Node::Uncompiled(expr.clone()),
+        };
+        self.fold(node)
+    }
+
+    /// Evaluate the given node ahead of time, if all of the sub-expressions it evaluates are constant.
+    /// As these are compiled and folded first, this only needs to look at the direct children of the node.
+    /// Nodes that fail to evaluate are kept as they are, such that the error is reported whenever they are evaluated.
+    fn fold(&self, node: Node) -> Node {
+        let is_constant = match &node {
+            Node::Const(_) | Node::Var(..) | Node::Local(..) | Node::Uncompiled(_) => false,
+            // only the branch that is taken is evaluated
+            Node::IfElse(_, box Node::Const(cond), yes, no) => is_const(if cond.as_bool().unwrap_or(true) { yes } else { no }),
+            Node::IfElse(..) => false,
+            Node::BinOp(_, box Node::Const(a), BinOp::Elvis, b) => !a.0.is_empty() || is_const(b),
+            // the left side of a `??` isn't folded on its own, see [Compiler::compile_null_coalesce]
+            Node::BinOp(_, _, BinOp::NullCoalesce, _) => false,
+            Node::BinOp(_, a, _, b)
+            | Node::JsonAccess(_, a, b)
+            | Node::JsonSafeAccess(_, a, b)
+            | Node::LambdaCall(_, _, a, b)
+            | Node::Let(_, a, b) => is_const(a) && is_const(b),
+            Node::RegexMatch(_, a, _) | Node::UnaryOp(_, _, a) | Node::Formatted(_, a, _) => is_const(a),
+            Node::Concat(_, elems) | Node::FunctionCall(_, _, elems) => elems.iter().all(is_const),
+            Node::JsonArray(_, values) => values.iter().all(|(x, _)| is_const(x)),
+            Node::JsonObject(_, entries) => entries.iter().all(|(k, v, _)| is_const(k) && is_const(v)),
+        };
+        if !is_constant {
+            return node;
+        }
+        match self.constant_evaluation().eval(&node) {
+            Ok(value) => Node::Const(value),
+            Err(_) => node,
+        }
+    }
+
+    /// Compile a `??`, folding it if its left side is constant and either present, or the right side is constant as well.
+    fn compile_null_coalesce(&mut self, span: Span, a: &SimplExpr, b: &SimplExpr) -> Node {
+        let (a, a_is_constant) = self.compile_coalesced(a);
+        let b = self.compile(b);
+        if a_is_constant {
+            match (self.constant_evaluation().eval_coalesced(&a), &b) {
+                (Ok(Some(a)), _) => return Node::Const(a.at(span)),
+                (Ok(None), Node::Const(b)) => return Node::Const(b.clone().at(span)),
+                _ => {}
+            }
+        }
+        Node::BinOp(span, Box::new(a), BinOp::NullCoalesce, Box::new(b))
+    }
+
+    /// Compile the left side of a `??`, where failing to index into a value counts as the value being absent.
+    /// Thus, `.` and `[]` accesses along it are compiled like `?.`, and both sides of a `??` on the left side are compiled like this.
+    /// As folding these accesses on their own would lose whether the value is absent, this also returns if the node is constant.
+    fn compile_coalesced(&mut self, expr: &SimplExpr) -> (Node, bool) {
+        match expr {
+            SimplExpr::JsonAccess(span, a, b) | SimplExpr::JsonSafeAccess(span, a, b) => {
+                let (a, a_is_constant) = self.compile_coalesced(a);
+                let b = self.compile(b);
+                let is_constant = a_is_constant && is_const(&b);
+                (Node::JsonSafeAccess(*span, Box::new(a), Box::new(b)), is_constant)
+            }
+            SimplExpr::BinOp(span, a, BinOp::NullCoalesce, b) => {
+                let (a, a_is_constant) = self.compile_coalesced(a);
+                let (b, b_is_constant) = self.compile_coalesced(b);
+                (Node::BinOp(*span, Box::new(a), BinOp::NullCoalesce, Box::new(b)), a_is_constant && b_is_constant)
+            }
+            _ => {
+                let node = self.compile(expr);
+                let is_constant = is_const(&node);
+                (node, is_constant)
+            }
+        }
+    }
+
+    /// An evaluation without any variables, for nodes that only consist of constants
+    fn constant_evaluation(&self) -> Evaluation<'a> {
+        Evaluation { slots: &[], variables: &[], functions: self.functions, locals: Vec::new() }
+    }
+
+    fn compile_in_scope(&mut self, name: &VarName, expr: &SimplExpr) -> Node {
+        self.locals.push(name.clone());
+        let node = self.compile(expr);
+        self.locals.pop();
+        node
+    }
+
+    fn slot(&mut self, name: &VarName) -> usize {
+        match self.variables.iter().position(|x| x == name) {
+            Some(slot) => slot,
+            None => {
+                self.variables.push(name.clone());
+                self.variables.len() - 1
+            }
+        }
+    }
+}
+
+fn is_const(node: &Node) -> bool {
+    matches!(node, Node::Const(_))
+}
+
+impl CompiledExpr {
+    /// Compile an expression that calls the builtin functions.
+    pub fn new(expr: &SimplExpr) -> Self {
+        Self::with_functions(expr, FunctionRegistry::builtins())
+    }
+
+    /// Compile an expression that calls functions from the given registry.
+    /// The same registry must then be used to evaluate it, as constant sub-expressions are evaluated during compilation.
+    pub fn with_functions(expr: &SimplExpr, functions: &FunctionRegistry) -> Self {
+        let mut compiler = Compiler { functions, variables: Vec::new(), locals: Vec::new() };
+        let root = compiler.compile(expr);
+        CompiledExpr { root, variables: compiler.variables }
+    }
+
+    /// The variables referenced by the expression. Their index in this list is the slot they need to be given in when
+    /// calling [CompiledExpr::eval_slots].
+    pub fn variables(&self) -> &[VarName] {
+        &self.variables
+    }
+
+    /// Evaluate the expression, using the builtin functions.
+    pub fn eval(&self, values: &HashMap<VarName, DynVal>) -> Result<DynVal, EvalError> {
+        self.eval_with(values, FunctionRegistry::builtins())
+    }
+
+    pub fn eval_with(&self, values: &HashMap<VarName, DynVal>, functions: &FunctionRegistry) -> Result<DynVal, EvalError> {
+        let slots = self.variables.iter().map(|name| values.get(name)).collect::<Vec<_>>();
+        self.eval_slots(&slots, functions).map_err(|err| {
+            err.map_in_span(|err| match err {
+                EvalError::UnknownVariable(name, _) => eval::unknown_variable(&name, values.keys()),
+                other => other,
+            })
+        })
+    }
+
+    /// Evaluate the expression with the values of its [variables](CompiledExpr::variables), given by their slot.
+    /// Variables that are [None] result in an error when they are used.
+    pub fn eval_slots(&self, slots: &[Option<&DynVal>], functions: &FunctionRegistry) -> Result<DynVal, EvalError> {
+        let mut evaluation = Evaluation { slots, variables: &self.variables, functions, locals: Vec::new() };
+        evaluation.eval(&self.root)
+    }
+}
+
+struct Evaluation<'a> {
+    slots: &'a [Option<&'a DynVal>],
+    variables: &'a [VarName],
+    functions: &'a FunctionRegistry,
+    locals: Vec<DynVal>,
+}
+
+impl<'a> Evaluation<'a> {
+    fn eval(&mut self, node: &Node) -> Result<DynVal, EvalError> {
+        let span = node.span();
+        let value = match node {
+            Node::Const(x) => Ok(x.clone()),
+            Node::Var(span, slot) => match self.slots.get(*slot).copied().flatten() {
+                Some(value) => Ok(value.clone().at(*span)),
+                None => Err(EvalError::UnknownVariable(self.variables[*slot].clone(), Vec::new()).at(*span)),
+            },
+            Node::Local(span, position) => Ok(self.locals[*position].clone().at(*span)),
+            Node::Concat(span, elems) => {
+                let mut output = String::new();
+                for elem in elems {
+                    output.push_str(&self.eval(elem)?.0);
+                }
+                Ok(DynVal::from_string(output).at(*span))
+            }
+            Node::BinOp(span, a, BinOp::NullCoalesce, b) => match self.eval_coalesced(a)? {
+                Some(a) => Ok(a.at(*span)),
+                None => Ok(self.eval(b)?.at(*span)),
+            },
+            Node::BinOp(span, a, op, b) => {
+                let a = self.eval(a)?;
+                let b = self.eval(b)?;
+                Ok(eval::eval_binop(op, a, b)?.at(*span))
+            }
+            Node::RegexMatch(span, a, regex) => Ok(DynVal::from(regex.is_match(&self.eval(a)?.as_string()?)).at(*span)),
+            Node::UnaryOp(span, op, a) => {
+                let a = self.eval(a)?;
+                Ok(eval::eval_unaryop(op, a)?.at(*span))
+            }
+            Node::IfElse(_, cond, yes, no) => {
+                if self.eval(cond)?.as_bool()? {
+                    self.eval(yes)
+                } else {
+                    self.eval(no)
+                }
+            }
+            Node::JsonAccess(span, val, index) => {
+                let val = self.eval(val)?;
+                let index = self.eval(index)?;
+                eval::json_access(&val, &index).map(|x| x.at(*span)).map_err(|e| e.at(*span))
+            }
+            Node::JsonSafeAccess(span, val, index) => {
+                let val = self.eval(val)?;
+                let index = self.eval(index)?;
+                Ok(eval::json_safe_access(&val, &index).unwrap_or_else(eval::json_null).at(*span))
+            }
+            Node::FunctionCall(span, name, args) => {
+                let args = args.iter().map(|x| self.eval(x)).collect::<Result<Vec<_>, _>>()?;
+                self.functions.call(name, &args).map(|x| x.at(*span)).map_err(|e| e.at(*span))
+            }
+            Node::LambdaCall(span, name, collection, body) => {
+                let collection = self.eval(collection)?;
+                let functions = self.functions;
+                let mut apply = |item| {
+                    self.locals.push(item);
+                    let result = self.eval(body);
+                    self.locals.pop();
+                    result
+                };
+                functions.call_lambda(name, &collection, &mut apply).map(|x| x.at(*span)).map_err(|e| e.at(*span))
+            }
+            Node::Let(_, value, body) => {
+                let value = self.eval(value)?;
+                self.locals.push(value);
+                let result = self.eval(body);
+                self.locals.pop();
+                result
+            }
+            Node::Formatted(span, value, spec) => {
+                let value = self.eval(value)?;
+                Ok(DynVal::from(spec.apply(&value).map_err(|e| e.at(*span))?).at(*span))
+            }
+            Node::JsonArray(span, entries) => {
+                let entries = entries
+                    .iter()
+                    .map(|(x, is_string)| Ok(eval::json_element(*is_string, &self.eval(x)?)))
+                    .collect::<Result<Vec<_>, EvalError>>()?;
+                Ok(eval::json_array(entries)?.at(*span))
+            }
+            Node::JsonObject(span, entries) => {
+                let entries = entries
+                    .iter()
+                    .map(|(k, v, is_string)| Ok((self.eval(k)?, eval::json_element(*is_string, &self.eval(v)?))))
+                    .collect::<Result<Vec<_>, EvalError>>()?;
+                Ok(eval::json_object(entries)?.at(*span))
+            }
+            Node::Uncompiled(expr) => expr.eval_with(&HashMap::new(), self.functions),
+        };
+        Ok(value?.at(span))
+    }
+
+    /// Evaluate the left side of a `??`, resulting in `None` if the value is absent. See [SimplExpr::eval_coalesced].
+    fn eval_coalesced(&mut self, node: &Node) -> Result<Option<DynVal>, EvalError> {
+        match node {
+            Node::JsonSafeAccess(span, val, index) => {
+                let val = self.eval_coalesced(val)?;
+                let index = self.eval(index)?;
+                Ok(val.and_then(|val| eval::json_safe_access(&val, &index)).map(|x| x.at(*span)))
+            }
+            Node::BinOp(span, a, BinOp::NullCoalesce, b) => match self.eval_coalesced(a)? {
+                Some(a) => Ok(Some(a.at(*span))),
+                None => Ok(self.eval_coalesced(b)?.map(|x| x.at(*span))),
+            },
+            _ => self.eval(node).map(Some),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_result_as_interpreter() {
+        let variables: HashMap<VarName, DynVal> = vec![
+            ("cpu", "42.567"),
+            ("name", "eww"),
+            ("disks", r#"[{"name": "/", "used": 80}, {"name": "/home", "used": 20.5}]"#),
+            ("empty", ""),
+        ]
+        .into_iter()
+        .map(|(name, value)| (VarName::from(name), DynVal::from(value)))
+        .collect();
+
+        let expressions = [
+            "cpu > 50 ? 'high' : 'low'",
+            "\"${round(cpu, 1)}% ${cpu:>8.2} ${1536:bytes}\"",
+            "name =~ '^e+w' && !(name =~ 'x')",
+            "replace(name, 'w+', 'W') + upper(name)",
+            "let x = cpu * 2 in let y = x + 1 in [x, y, {\"name\": name}]",
+            "map(disks, d => d.used * 2)",
+            "let factor = 10 in map(disks, d => let used = d.used in used * factor)",
+            "filter(disks, d => d.used > cpu)[0].name",
+            "sort_by(disks, d => d.used)[0]?.name ?? 'none'",
+            "sum(disks, d => d.used) + sum('[1, 2]')",
+            "empty ?: name",
+            "disks?.[5]?.name ?? empty ?? 'default'",
+            "disks[5].name ?? name.first ?? 'default'",
+            "disks[0].missing ?? empty ?? 'never used'",
+            "(disks[9] ?? disks[0]).name",
+            "2 ** 10 + 7 // 2 - -cpu",
+            "nonexistent + 1",
+            "nmae",
+            "cpu.field",
+            "round(name, 1)",
+            "unknown_function(cpu)",
+            "\"${name:bytes}\"",
+            "name =~ '('",
+            "strlength(x => x)",
+            "[cpu, '1.50', 1.50, \"${1 + 1}\", name?.x, {\"a\": '2', \"b\": 2}]",
+            "true ? 1 : 1 / 'x'",
+            "'x' ?: 1 / 'x'",
+            "'{\"a\": 1}'.b ?? 1 + 1",
+            "name =~ '^' + 'e'",
+        ];
+        for text in &expressions {
+            let expr = crate::parser::parse_string(0, 0, text).unwrap();
+            let interpreted = expr.eval(&variables).map(|x| x.0).map_err(|e| e.to_string());
+            let compiled = CompiledExpr::new(&expr).eval(&variables).map(|x| x.0).map_err(|e| e.to_string());
+            assert_eq!(interpreted, compiled, "evaluating {}", text);
+        }
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let compile = |text| CompiledExpr::new(&crate::parser::parse_string(0, 0, text).unwrap()).root;
+        assert!(matches!(compile("1 + 2 * 3"), Node::Const(x) if x.0 == "7"));
+        assert!(matches!(compile("a + 2 * 3"), Node::BinOp(_, box Node::Var(..), _, box Node::Const(x)) if x.0 == "6"));
+        assert!(matches!(compile("false ? a : 1 + 1"), Node::Const(x) if x.0 == "2"));
+        assert!(matches!(compile("'{\"a\": 1}'.b ?? 'absent'"), Node::Const(x) if x.0 == "absent"));
+        assert!(matches!(compile("'{\"a\": 1}'.a ?? a"), Node::Const(x) if x.0 == "1"));
+        assert!(matches!(compile("a =~ '^' + 'x'"), Node::RegexMatch(..)));
+        // expressions that fail are kept, to report the error whenever they are evaluated
+        assert!(matches!(compile("1 / 'x'"), Node::BinOp(..)));
+        assert!(matches!(compile("let x = 2 in x * 3"), Node::Let(..)));
+    }
+
+    #[test]
+    fn test_slots() {
+        let expr = crate::parser::parse_string(0, 0, "let x = a in map(b, y => y + x + a) ?? c ?? (1 + 2) * 3").unwrap();
+        let compiled = CompiledExpr::new(&expr);
+        assert_eq!(compiled.variables(), &[VarName::from("a"), VarName::from("b"), VarName::from("c")]);
+        assert!(matches!(
+            compiled.root,
+            Node::Let(
+                _,
+                box Node::Var(_, 0),
+                box Node::BinOp(_, box Node::BinOp(_, box Node::LambdaCall(..), ..), _, box Node::Const(_))
+            )
+        ));
+        let (a, b) = (DynVal::from("1"), DynVal::from("[1, 2]"));
+        let result = compiled.eval_slots(&[Some(&a), Some(&b), None], FunctionRegistry::builtins());
+        assert_eq!(result.unwrap().0, "[3,4]");
+    }
+}
@@ -1,7 +1,8 @@
 use eww_shared_util::{Span, Spanned};
 use itertools::Itertools;
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
-use std::{convert::TryFrom, fmt, iter::FromIterator, str::FromStr};
+use std::{borrow::Cow, convert::TryFrom, fmt, iter::FromIterator, str::FromStr, sync::Arc};
 
 pub type Result<T> = std::result::Result<T, ConversionError>;
 
@@ -24,12 +25,34 @@ impl Spanned for ConversionError {
     }
 }
 
-#[derive(Clone, Deserialize, Serialize, Eq)]
-pub struct DynVal(pub String, pub Span);
+/// A value, which is represented as text, but can be read as any other type the text can be parsed as.
+///
+/// The typed representations of a value are parsed lazily, the first time they are needed, and cached.
+/// The cache is only allocated once the value is read as a type other than text, and is shared with the clones made after that.
+/// It remembers the text it was created for, so it is not used anymore if the text is changed afterwards.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct DynVal(pub String, pub Span, #[serde(skip)] OnceCell<Arc<TypedCache>>);
+
+/// The results of parsing the text of a [DynVal] as each of the types it may be read as, where `None` means that parsing failed.
+#[derive(Debug)]
+struct TypedCache {
+    /// The text the cached values were parsed from
+    text: String,
+    f64: OnceCell<Option<f64>>,
+    i64: OnceCell<Option<i64>>,
+    bool: OnceCell<Option<bool>>,
+    json: OnceCell<Option<serde_json::Value>>,
+}
+
+impl TypedCache {
+    fn new(text: String) -> Self {
+        TypedCache { text, f64: OnceCell::new(), i64: OnceCell::new(), bool: OnceCell::new(), json: OnceCell::new() }
+    }
+}
 
 impl From<String> for DynVal {
     fn from(s: String) -> Self {
-        DynVal(s, Span::DUMMY)
+        DynVal::from_string(s)
     }
 }
 
@@ -55,9 +78,11 @@ impl std::cmp::PartialEq<Self> for DynVal {
     }
 }
 
+impl Eq for DynVal {}
+
 impl FromIterator<DynVal> for DynVal {
     fn from_iter<T: IntoIterator<Item = DynVal>>(iter: T) -> Self {
-        DynVal(iter.into_iter().join(""), Span::DUMMY)
+        DynVal::from_string(iter.into_iter().join(""))
     }
 }
 
@@ -87,36 +112,36 @@ impl<E, T: FromStr<Err = E>> FromDynVal for T {
 macro_rules! impl_dynval_from {
     ($($t:ty),*) => {
         $(impl From<$t> for DynVal {
-            fn from(x: $t) -> Self { DynVal(x.to_string(), Span::DUMMY) }
+            fn from(x: $t) -> Self { DynVal::from_string(x.to_string()) }
         })*
     };
 }
 
-impl_dynval_from!(bool, i32, u32, f32, u8, f64, &str);
+impl_dynval_from!(bool, i32, u32, i64, f32, u8, f64, &str);
 
 impl TryFrom<serde_json::Value> for DynVal {
     type Error = serde_json::Error;
 
     fn try_from(value: serde_json::Value) -> std::result::Result<Self, Self::Error> {
-        Ok(DynVal(serde_json::to_string(&value)?, Span::DUMMY))
+        Ok(DynVal::from_string(serde_json::to_string(&value)?).with_json(value))
     }
 }
 
 impl From<std::time::Duration> for DynVal {
     fn from(d: std::time::Duration) -> Self {
-        DynVal(format!("{}ms", d.as_millis()), Span::DUMMY)
+        DynVal::from_string(format!("{}ms", d.as_millis()))
     }
 }
 
 impl From<&serde_json::Value> for DynVal {
     fn from(v: &serde_json::Value) -> Self {
-        DynVal(
-            v.as_str()
-                .map(|x| x.to_string())
-                .or_else(|| serde_json::to_string(v).ok())
-                .unwrap_or_else(|| "<invalid json value>".to_string()),
-            Span::DUMMY,
-        )
+        match v.as_str() {
+            Some(x) => DynVal::from_string(x.to_string()),
+            None => match serde_json::to_string(v) {
+                Ok(text) => DynVal::from_string(text).with_json(v.clone()),
+                Err(_) => DynVal::from_string("<invalid json value>".to_string()),
+            },
+        }
     }
 }
 
@@ -133,7 +158,45 @@ impl DynVal {
     }
 
     pub fn from_string(s: String) -> Self {
-        DynVal(s, Span::DUMMY)
+        DynVal(s, Span::DUMMY, OnceCell::new())
+    }
+
+    /// Fill in the parsed json of this value, if it was created from that json.
+    fn with_json(self, json: serde_json::Value) -> Self {
+        let cache = TypedCache::new(self.0.clone());
+        let _ = cache.json.set(Some(json));
+        let _ = self.2.set(Arc::new(cache));
+        self
+    }
+
+    /// The cache of the typed representations of this value, or `None` if the text was changed since the cache was created.
+    fn cache(&self) -> Option<&TypedCache> {
+        let cache = self.2.get_or_init(|| Arc::new(TypedCache::new(self.0.clone())));
+        if cache.text == self.0 {
+            Some(cache)
+        } else {
+            None
+        }
+    }
+
+    /// Parse the text, using the given entry of the cache if the cache is still valid.
+    fn parse_cached<T: Copy>(&self, entry: fn(&TypedCache) -> &OnceCell<Option<T>>, parse: fn(&str) -> Option<T>) -> Option<T> {
+        match self.cache() {
+            Some(cache) => *entry(cache).get_or_init(|| parse(&self.0)),
+            None => parse(&self.0),
+        }
+    }
+
+    /// The error for a failed conversion, parsing the text again to get the cause of the failure, which isn't cached.
+    fn parse_error<T>(&self, target_type: &'static str) -> ConversionError
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Sync + Send + 'static,
+    {
+        match self.0.parse::<T>() {
+            Err(e) => ConversionError::new(self.clone(), target_type, e),
+            Ok(_) => ConversionError { value: self.clone(), target_type, source: None },
+        }
     }
 
     pub fn read_as<E, T: FromDynVal<Err = E>>(&self) -> std::result::Result<T, E> {
@@ -144,45 +207,56 @@ impl DynVal {
         self.0
     }
 
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
     /// This will never fail
     pub fn as_string(&self) -> Result<String> {
         Ok(self.0.to_owned())
     }
 
     pub fn as_f64(&self) -> Result<f64> {
-        self.0.parse().map_err(|e| ConversionError::new(self.clone(), "f64", e))
+        self.parse_cached(|cache| &cache.f64, |text| text.parse().ok()).ok_or_else(|| self.parse_error::<f64>("f64"))
     }
 
     pub fn as_i32(&self) -> Result<i32> {
-        self.0.parse().map_err(|e| ConversionError::new(self.clone(), "i32", e))
+        self.as_i64().ok().and_then(|x| i32::try_from(x).ok()).ok_or_else(|| self.parse_error::<i32>("i32"))
+    }
+
+    pub fn as_i64(&self) -> Result<i64> {
+        self.parse_cached(|cache| &cache.i64, |text| text.parse().ok()).ok_or_else(|| self.parse_error::<i64>("i64"))
     }
 
     pub fn as_bool(&self) -> Result<bool> {
-        self.0.parse().map_err(|e| ConversionError::new(self.clone(), "bool", e))
+        self.parse_cached(|cache| &cache.bool, |text| text.parse().ok()).ok_or_else(|| self.parse_error::<bool>("bool"))
     }
 
+    /// Parse a duration like `500ms`, `1.5s`, `2m` or `1h30m`.
+    /// Supported units are `ms`, `s`, `m` and `h`, and several of them can be combined.
     pub fn as_duration(&self) -> Result<std::time::Duration> {
-        use std::time::Duration;
-        let s = &self.0;
-        if s.ends_with("ms") {
-            Ok(Duration::from_millis(
-                s.trim_end_matches("ms").parse().map_err(|e| ConversionError::new(self.clone(), "integer", e))?,
-            ))
-        } else if s.ends_with('s') {
-            Ok(Duration::from_secs(
-                s.trim_end_matches('s').parse().map_err(|e| ConversionError::new(self.clone(), "integer", e))?,
-            ))
-        } else if s.ends_with('m') {
-            Ok(Duration::from_secs(
-                s.trim_end_matches('m').parse::<u64>().map_err(|e| ConversionError::new(self.clone(), "integer", e))? * 60,
-            ))
-        } else if s.ends_with('h') {
-            Ok(Duration::from_secs(
-                s.trim_end_matches('h').parse::<u64>().map_err(|e| ConversionError::new(self.clone(), "integer", e))? * 60 * 60,
-            ))
-        } else {
-            Err(ConversionError { value: self.clone(), target_type: "duration", source: None })
+        let invalid = || ConversionError { value: self.clone(), target_type: "duration", source: None };
+        let mut rest = self.0.trim();
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+        let mut nanos = 0.0;
+        while !rest.is_empty() {
+            let number_end = rest.find(|c: char| !c.is_ascii_digit() && c != '.').ok_or_else(invalid)?;
+            let amount: f64 = rest[..number_end].parse().map_err(|e| ConversionError::new(self.clone(), "duration", e))?;
+            rest = &rest[number_end..];
+            let unit_end = rest.find(|c: char| c.is_ascii_digit() || c == '.').unwrap_or_else(|| rest.len());
+            let nanos_per_unit = match &rest[..unit_end] {
+                "ms" => 1e6,
+                "s" => 1e9,
+                "m" => 60e9,
+                "h" => 3600e9,
+                _ => return Err(invalid()),
+            };
+            nanos += amount * nanos_per_unit;
+            rest = &rest[unit_end..];
         }
+        Ok(std::time::Duration::from_nanos(nanos.round() as u64))
     }
 
     pub fn as_vec(&self) -> Result<Vec<String>> {
@@ -211,8 +285,47 @@ impl DynVal {
     }
 
     pub fn as_json_value(&self) -> Result<serde_json::Value> {
-        serde_json::from_str::<serde_json::Value>(&self.0)
-            .map_err(|e| ConversionError::new(self.clone(), "json-value", Box::new(e)))
+        self.as_json_ref().map(Cow::into_owned)
+    }
+
+    /// Read this value as json, without copying the parsed json if it is cached.
+    pub fn as_json_ref(&self) -> Result<Cow<serde_json::Value>> {
+        let json = match self.cache() {
+            Some(cache) => cache.json.get_or_init(|| serde_json::from_str(&self.0).ok()).as_ref().map(Cow::Borrowed),
+            None => serde_json::from_str(&self.0).ok().map(Cow::Owned),
+        };
+        json.ok_or_else(|| match serde_json::from_str::<serde_json::Value>(&self.0) {
+            Err(e) => ConversionError::new(self.clone(), "json-value", Box::new(e)),
+            Ok(_) => ConversionError { value: self.clone(), target_type: "json-value", source: None },
+        })
+    }
+
+    /// Turn this value into json, keeping its type if it is valid json, and using a json string otherwise.
+    /// Numbers, booleans and `null` are only converted if that doesn't change their text (i.e. `1.50` stays a string),
+    /// such that turning the result back into a [DynVal] gives the original value.
+    pub fn to_json_value(&self) -> serde_json::Value {
+        use serde_json::Value;
+        match self.as_json_ref().as_deref() {
+            Ok(json @ (Value::Array(_) | Value::Object(_))) => json.clone(),
+            // compare the text of the json with the text of this value, rather than the json with a json string
+            #[allow(clippy::cmp_owned)]
+            Ok(json @ (Value::Null | Value::Bool(_) | Value::Number(_))) if json.to_string() == self.0 => json.clone(),
+            _ => Value::String(self.0.clone()),
+        }
+    }
+
+    pub fn as_json_array(&self) -> Result<Vec<serde_json::Value>> {
+        match &*self.as_json_ref()? {
+            serde_json::Value::Array(x) => Ok(x.clone()),
+            _ => Err(ConversionError { value: self.clone(), target_type: "json-array", source: None }),
+        }
+    }
+
+    pub fn as_json_object(&self) -> Result<serde_json::Map<String, serde_json::Value>> {
+        match &*self.as_json_ref()? {
+            serde_json::Value::Object(x) => Ok(x.clone()),
+            _ => Err(ConversionError { value: self.clone(), target_type: "json-object", source: None }),
+        }
     }
 }
 
@@ -230,4 +343,118 @@ mod test {
         insta::assert_debug_snapshot!(DynVal::from_string("[a,b".to_string()).as_vec());
         insta::assert_debug_snapshot!(DynVal::from_string("a]".to_string()).as_vec());
     }
+
+    #[test]
+    fn test_parse_duration() {
+        use std::time::Duration;
+        let parse = |x: &str| DynVal::from(x).as_duration().ok();
+        assert_eq!(parse("200ms"), Some(Duration::from_millis(200)));
+        assert_eq!(parse("2s"), Some(Duration::from_secs(2)));
+        assert_eq!(parse("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse("0.29s"), Some(Duration::from_millis(290)));
+        assert_eq!(parse("1m30s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse("1h2m3s400ms"), Some(Duration::from_millis(3_723_400)));
+        for invalid in &["", "12", "s", "1x", "1.2.3s", "-1s", "1 s"] {
+            assert_eq!(parse(invalid), None, "{} should not be a valid duration", invalid);
+        }
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_equality() {
+        // the comparison as it was done before the typed representations were cached
+        let reference_eq = |a: &str, b: &str| match (a.parse::<f64>(), b.parse::<f64>()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => a == b,
+        };
+        let values = [
+            "1",
+            "1.0",
+            "01",
+            "+1",
+            "1e0",
+            "2.50",
+            "2.5",
+            "-0",
+            "0",
+            "NaN",
+            "inf",
+            "true",
+            "True",
+            "abc",
+            "",
+            " 1",
+            "[1]",
+            "[ 1 ]",
+            "{}",
+            "null",
+            "3000000000",
+        ];
+        for a in values.iter() {
+            for b in values.iter() {
+                let expected = reference_eq(a, b);
+                assert_eq!(DynVal::from(*a) == DynVal::from(*b), expected, "comparing `{}` and `{}`", a, b);
+
+                // reading the values as other types first must not change the result
+                let (a_val, b_val) = (DynVal::from(*a), DynVal::from(*b));
+                let _ = (a_val.as_bool(), a_val.as_json_value(), a_val.as_i64(), b_val.as_f64(), b_val.as_i32());
+                assert_eq!(a_val == b_val, expected, "comparing `{}` and `{}` after reading them", a, b);
+                assert_eq!(a_val.clone() == b_val.clone(), expected, "comparing clones of `{}` and `{}`", a, b);
+            }
+        }
+        let from_json = DynVal::try_from(serde_json::json!({"a": [1, 2]})).unwrap();
+        assert_eq!(from_json, DynVal::from(r#"{"a":[1,2]}"#));
+        assert_eq!(DynVal::from(&serde_json::json!(2.5)), DynVal::from("2.50"));
+    }
+
+    #[test]
+    fn test_cached_conversions() {
+        let value = DynVal::from("3000000000");
+        assert_eq!(value.as_i64().unwrap(), 3_000_000_000);
+        assert_eq!(value.as_i32().unwrap_err().to_string(), "Failed to turn `3000000000` into a value of type i32");
+        assert!(value.as_i32().unwrap_err().source.is_some());
+        let value = DynVal::from("[1, 2]");
+        assert!(value.2.get().is_none());
+        assert_eq!(value.as_json_ref().unwrap().as_ref(), &serde_json::json!([1, 2]));
+        assert!(Arc::ptr_eq(value.2.get().unwrap(), value.clone().at(Span(1, 2, 0)).2.get().unwrap()));
+        assert!(DynVal::from("abc").as_json_value().unwrap_err().source.is_some());
+    }
+
+    #[test]
+    fn test_changed_text() {
+        let mut value = DynVal::from("1");
+        assert_eq!(value.as_i64().unwrap(), 1);
+        let unchanged = value.clone();
+        value.0 = "[2]".to_string();
+        assert!(value.as_i64().is_err());
+        assert_eq!(value.as_json_value().unwrap(), serde_json::json!([2]));
+        assert_eq!(unchanged.as_i64().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_serialization() {
+        let value = DynVal::from("1.50").at(Span(1, 2, 3));
+        let _ = value.as_f64();
+        let serialized = serde_json::to_string(&value).unwrap();
+        assert_eq!(serialized, r#"["1.50",[1,2,3]]"#);
+        let deserialized: DynVal = serde_json::from_str(&serialized).unwrap();
+        assert_eq!((deserialized.as_str(), deserialized.span()), ("1.50", Span(1, 2, 3)));
+        assert_eq!(deserialized.as_f64().ok(), Some(1.5));
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        for value in &["hi", "1", "1.50", "-2.5", "true", "null", "\"quoted\"", "[1,\"a\",[true]]", r#"{"a":{"b":null}}"#, ""] {
+            let value = DynVal::from(*value);
+            assert_eq!(DynVal::from(&value.to_json_value()).0, value.0);
+            if let Ok(json) = value.as_json_value() {
+                if json.is_array() || json.is_object() {
+                    assert_eq!(DynVal::try_from(json.clone()).unwrap().as_json_value().unwrap(), json);
+                }
+            }
+        }
+        assert_eq!(DynVal::from(r#"{ "a": [1, 2] }"#).to_json_value(), serde_json::json!({"a": [1, 2]}));
+    }
 }
@@ -1,7 +1,4 @@
-use crate::{
-    dynval,
-    parser::lexer::{self, LexicalError},
-};
+use crate::{dynval, parser::lexer};
 use eww_shared_util::{Span, Spanned};
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -51,7 +48,7 @@ fn get_parse_error_span(file_id: usize, err: &lalrpop_util::ParseError<usize, le
         lalrpop_util::ParseError::UnrecognizedEOF { location, expected: _ } => Span(*location, *location, file_id),
         lalrpop_util::ParseError::UnrecognizedToken { token, expected: _ } => Span(token.0, token.2, file_id),
         lalrpop_util::ParseError::ExtraToken { token } => Span(token.0, token.2, file_id),
-        lalrpop_util::ParseError::User { error: LexicalError(span) } => *span,
+        lalrpop_util::ParseError::User { error } => error.span(),
     }
 }
 
@@ -3,9 +3,10 @@ use itertools::Itertools;
 use crate::{
     ast::{BinOp, SimplExpr, UnaryOp},
     dynval::{ConversionError, DynVal},
+    functions::FunctionRegistry,
 };
 use eww_shared_util::{Span, Spanned, VarName};
-use std::{collections::HashMap, convert::TryFrom};
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, convert::TryFrom, time::Duration};
 
 #[derive(Debug, thiserror::Error)]
 pub enum EvalError {
@@ -25,7 +26,28 @@ pub enum EvalError {
     WrongArgCount(String),
 
     #[error("Unknown function {0}")]
-    UnknownFunction(String),
+    UnknownFunction(String, Vec<String>),
+
+    #[error("Lambdas can only be passed as the last argument to functions that take one, like map, filter, sort_by or sum")]
+    UnexpectedLambda,
+
+    #[error("Invalid time format: {0}")]
+    InvalidTimeFormat(String),
+
+    #[error("Unknown format spec `{0}`, expected something like `.2`, `>5`, `<5`, `^5`, `bytes` or `percent`")]
+    UnknownFormatSpec(String),
+
+    #[error("Unknown unit {0}, expected one of B, kB, MB, GB, TB, PB, EB, KiB, MiB, GiB, TiB, PiB or EiB")]
+    UnknownUnit(String),
+
+    #[error("Unknown timezone {0}")]
+    UnknownTimezone(String),
+
+    #[error("Division by zero")]
+    DivisionByZero,
+
+    #[error("The result of this integer operation does not fit into a 64 bit integer")]
+    IntegerOverflow,
 
     #[error("Unable to index into value {0}")]
     CannotIndex(String),
@@ -63,31 +85,46 @@ impl Spanned for EvalError {
 impl SimplExpr {
     /// map over all of the variable references, replacing them with whatever expression the provided function returns.
     /// Returns [Err] when the provided function fails with an [Err]
+    /// Variables bound by lambdas are not passed to the provided function.
     pub fn try_map_var_refs<E, F: Fn(Span, VarName) -> Result<SimplExpr, E> + Copy>(self, f: F) -> Result<Self, E> {
+        self.try_map_free_var_refs(f, &[])
+    }
+
+    /// map over all variable references that don't refer to any of the given bound variables.
+    fn try_map_free_var_refs<E, F: Fn(Span, VarName) -> Result<SimplExpr, E> + Copy>(
+        self,
+        f: F,
+        bound: &[VarName],
+    ) -> Result<Self, E> {
         use SimplExpr::*;
+        let map = |x: SimplExpr| x.try_map_free_var_refs(f, bound);
         Ok(match self {
-            BinOp(span, box a, op, box b) => BinOp(span, box a.try_map_var_refs(f)?, op, box b.try_map_var_refs(f)?),
-            Concat(span, elems) => Concat(span, elems.into_iter().map(|x| x.try_map_var_refs(f)).collect::<Result<_, _>>()?),
-            UnaryOp(span, op, box a) => UnaryOp(span, op, box a.try_map_var_refs(f)?),
-            IfElse(span, box a, box b, box c) => {
-                IfElse(span, box a.try_map_var_refs(f)?, box b.try_map_var_refs(f)?, box c.try_map_var_refs(f)?)
+            BinOp(span, box a, op, box b) => BinOp(span, box map(a)?, op, box map(b)?),
+            Concat(span, elems) => Concat(span, elems.into_iter().map(map).collect::<Result<_, _>>()?),
+            UnaryOp(span, op, box a) => UnaryOp(span, op, box map(a)?),
+            Formatted(span, box a, spec) => Formatted(span, box map(a)?, spec),
+            IfElse(span, box a, box b, box c) => IfElse(span, box map(a)?, box map(b)?, box map(c)?),
+            JsonAccess(span, box a, box b) => JsonAccess(span, box map(a)?, box map(b)?),
+            JsonSafeAccess(span, box a, box b) => JsonSafeAccess(span, box map(a)?, box map(b)?),
+            FunctionCall(span, name, args) => FunctionCall(span, name, args.into_iter().map(map).collect::<Result<_, _>>()?),
+            Lambda(span, param, box body) => {
+                let mut bound = bound.to_vec();
+                bound.push(param.clone());
+                Lambda(span, param, box body.try_map_free_var_refs(f, &bound)?)
             }
-            JsonAccess(span, box a, box b) => JsonAccess(span, box a.try_map_var_refs(f)?, box b.try_map_var_refs(f)?),
-            FunctionCall(span, name, args) => {
-                FunctionCall(span, name, args.into_iter().map(|x| x.try_map_var_refs(f)).collect::<Result<_, _>>()?)
+            Let(span, name, box value, box body) => {
+                let value = map(value)?;
+                let mut bound = bound.to_vec();
+                bound.push(name.clone());
+                Let(span, name, box value, box body.try_map_free_var_refs(f, &bound)?)
             }
+            VarRef(span, name) if bound.contains(&name) => VarRef(span, name),
             VarRef(span, name) => f(span, name)?,
-            JsonArray(span, values) => {
-                JsonArray(span, values.into_iter().map(|x| x.try_map_var_refs(f)).collect::<Result<_, _>>()?)
+            JsonArray(span, values) => JsonArray(span, values.into_iter().map(map).collect::<Result<_, _>>()?),
+            JsonObject(span, entries) => {
+                JsonObject(span, entries.into_iter().map(|(k, v)| Ok((map(k)?, map(v)?))).collect::<Result<_, _>>()?)
             }
-            JsonObject(span, entries) => JsonObject(
-                span,
-                entries
-                    .into_iter()
-                    .map(|(k, v)| Ok((k.try_map_var_refs(f)?, v.try_map_var_refs(f)?)))
-                    .collect::<Result<_, _>>()?,
-            ),
-            x @ Literal(..) => x,
+            x @ (Literal(..) | Value(..)) => x,
         })
     }
 
@@ -106,7 +143,7 @@ impl SimplExpr {
     pub fn resolve_refs(self, variables: &HashMap<VarName, DynVal>) -> Result<Self, EvalError> {
         use SimplExpr::*;
         self.try_map_var_refs(|span, name| match variables.get(&name) {
-            Some(value) => Ok(Literal(value.clone())),
+            Some(value) => Ok(Value(value.clone())),
             None => {
                 let similar_ish =
                     variables.keys().filter(|key| levenshtein::levenshtein(&key.0, &name.0) < 3).cloned().collect_vec();
@@ -118,15 +155,15 @@ impl SimplExpr {
     pub fn var_refs(&self) -> Vec<(Span, &VarName)> {
         use SimplExpr::*;
         match self {
-            Literal(..) => Vec::new(),
+            Literal(..) | Value(..) => Vec::new(),
             VarRef(span, name) => vec![(*span, name)],
             Concat(_, elems) => elems.iter().flat_map(|x| x.var_refs().into_iter()).collect(),
-            BinOp(_, box a, _, box b) | JsonAccess(_, box a, box b) => {
+            BinOp(_, box a, _, box b) | JsonAccess(_, box a, box b) | JsonSafeAccess(_, box a, box b) => {
                 let mut refs = a.var_refs();
                 refs.extend(b.var_refs().iter());
                 refs
             }
-            UnaryOp(_, _, box x) => x.var_refs(),
+            UnaryOp(_, _, box x) | Formatted(_, box x, _) => x.var_refs(),
             IfElse(_, box a, box b, box c) => {
                 let mut refs = a.var_refs();
                 refs.extend(b.var_refs().iter());
@@ -134,6 +171,12 @@ impl SimplExpr {
                 refs
             }
             FunctionCall(_, _, args) => args.iter().flat_map(|a| a.var_refs()).collect(),
+            Lambda(_, param, box body) => body.var_refs().into_iter().filter(|(_, name)| *name != param).collect(),
+            Let(_, name, box value, box body) => {
+                let mut refs = value.var_refs();
+                refs.extend(body.var_refs().into_iter().filter(|(_, var_ref)| *var_ref != name));
+                refs
+            }
             JsonArray(_, values) => values.iter().flat_map(|v| v.var_refs()).collect(),
             JsonObject(_, entries) => entries.iter().flat_map(|(k, v)| k.var_refs().into_iter().chain(v.var_refs())).collect(),
         }
@@ -149,128 +192,631 @@ impl SimplExpr {
         }
     }
 
+    /// Evaluate the expression, using the builtin functions.
     pub fn eval(&self, values: &HashMap<VarName, DynVal>) -> Result<DynVal, EvalError> {
+        self.eval_with(values, FunctionRegistry::builtins())
+    }
+
+    /// Evaluate the expression, calling functions from the given registry.
+    pub fn eval_with(&self, values: &HashMap<VarName, DynVal>, functions: &FunctionRegistry) -> Result<DynVal, EvalError> {
+        self.eval_in(&mut Scope { values, locals: Vec::new() }, functions)
+    }
+
+    fn eval_in(&self, scope: &mut Scope, functions: &FunctionRegistry) -> Result<DynVal, EvalError> {
         let span = self.span();
         let value = match self {
-            SimplExpr::Literal(x) => Ok(x.clone()),
+            SimplExpr::Literal(x) | SimplExpr::Value(x) => Ok(x.clone()),
             SimplExpr::Concat(span, elems) => {
                 let mut output = String::new();
                 for elem in elems {
-                    let result = elem.eval(values)?;
+                    let result = elem.eval_in(scope, functions)?;
                     output.push_str(&result.0);
                 }
-                Ok(DynVal(output, *span))
-            }
-            SimplExpr::VarRef(span, ref name) => {
-                let similar_ish =
-                    values.keys().filter(|keys| levenshtein::levenshtein(&keys.0, &name.0) < 3).cloned().collect_vec();
-                Ok(values
-                    .get(name)
-                    .cloned()
-                    .ok_or_else(|| EvalError::UnknownVariable(name.clone(), similar_ish).at(*span))?
-                    .at(*span))
+                Ok(DynVal::from_string(output).at(*span))
             }
+            SimplExpr::VarRef(span, ref name) => match scope.get(name) {
+                Some(value) => Ok(value.clone().at(*span)),
+                None => Err(unknown_variable(name, scope.names()).at(*span)),
+            },
+            SimplExpr::BinOp(span, a, BinOp::NullCoalesce, b) => match a.eval_coalesced(scope, functions)? {
+                Some(a) => Ok(a.at(*span)),
+                None => Ok(b.eval_in(scope, functions)?.at(*span)),
+            },
             SimplExpr::BinOp(span, a, op, b) => {
-                let a = a.eval(values)?;
-                let b = b.eval(values)?;
-                let dynval = match op {
-                    BinOp::Equals => DynVal::from(a == b),
-                    BinOp::NotEquals => DynVal::from(a != b),
-                    BinOp::And => DynVal::from(a.as_bool()? && b.as_bool()?),
-                    BinOp::Or => DynVal::from(a.as_bool()? || b.as_bool()?),
-                    BinOp::Plus => match (a.as_f64(), b.as_f64()) {
-                        (Ok(a), Ok(b)) => DynVal::from(a + b),
-                        _ => DynVal::from(format!("{}{}", a.as_string()?, b.as_string()?)),
-                    },
-                    BinOp::Minus => DynVal::from(a.as_f64()? - b.as_f64()?),
-                    BinOp::Times => DynVal::from(a.as_f64()? * b.as_f64()?),
-                    BinOp::Div => DynVal::from(a.as_f64()? / b.as_f64()?),
-                    BinOp::Mod => DynVal::from(a.as_f64()? % b.as_f64()?),
-                    BinOp::GT => DynVal::from(a.as_f64()? > b.as_f64()?),
-                    BinOp::LT => DynVal::from(a.as_f64()? < b.as_f64()?),
-                    #[allow(clippy::useless_conversion)]
-                    BinOp::Elvis => DynVal::from(if a.0.is_empty() { b } else { a }),
-                    BinOp::RegexMatch => {
-                        let regex = regex::Regex::new(&b.as_string()?)?;
-                        DynVal::from(regex.is_match(&a.as_string()?))
-                    }
-                };
-                Ok(dynval.at(*span))
-            }
-            SimplExpr::UnaryOp(span, op, a) => {
-                let a = a.eval(values)?;
-                Ok(match op {
-                    UnaryOp::Not => DynVal::from(!a.as_bool()?).at(*span),
-                })
+                let a = a.eval_in(scope, functions)?;
+                let b = b.eval_in(scope, functions)?;
+                Ok(eval_binop(op, a, b)?.at(*span))
             }
+            SimplExpr::UnaryOp(span, op, a) => Ok(eval_unaryop(op, a.eval_in(scope, functions)?)?.at(*span)),
             SimplExpr::IfElse(_, cond, yes, no) => {
-                if cond.eval(values)?.as_bool()? {
-                    yes.eval(values)
+                if cond.eval_in(scope, functions)?.as_bool()? {
+                    yes.eval_in(scope, functions)
                 } else {
-                    no.eval(values)
+                    no.eval_in(scope, functions)
                 }
             }
             SimplExpr::JsonAccess(span, val, index) => {
-                let val = val.eval(values)?;
-                let index = index.eval(values)?;
-                match val.as_json_value()? {
-                    serde_json::Value::Array(val) => {
-                        let index = index.as_i32()?;
-                        let indexed_value = val.get(index as usize).unwrap_or(&serde_json::Value::Null);
-                        Ok(DynVal::from(indexed_value).at(*span))
-                    }
-                    serde_json::Value::Object(val) => {
-                        let indexed_value = val
-                            .get(&index.as_string()?)
-                            .or_else(|| val.get(&index.as_i32().ok()?.to_string()))
-                            .unwrap_or(&serde_json::Value::Null);
-                        Ok(DynVal::from(indexed_value).at(*span))
-                    }
-                    _ => Err(EvalError::CannotIndex(format!("{}", val)).at(*span)),
+                let val = val.eval_in(scope, functions)?;
+                let index = index.eval_in(scope, functions)?;
+                json_access(&val, &index).map(|x| x.at(*span)).map_err(|e| e.at(*span))
+            }
+            SimplExpr::JsonSafeAccess(span, val, index) => {
+                let val = val.eval_in(scope, functions)?;
+                let index = index.eval_in(scope, functions)?;
+                Ok(json_safe_access(&val, &index).unwrap_or_else(json_null).at(*span))
+            }
+            SimplExpr::FunctionCall(span, function_name, args) => match args.as_slice() {
+                [collection, SimplExpr::Lambda(_, param, body)] => {
+                    let collection = collection.eval_in(scope, functions)?;
+                    let mut apply = |item| scope.with_local(param, item, |scope| body.eval_in(scope, functions));
+                    functions.call_lambda(function_name, &collection, &mut apply).map(|x| x.at(*span)).map_err(|e| e.at(*span))
                 }
+                _ => {
+                    let args = args.iter().map(|a| a.eval_in(scope, functions)).collect::<Result<Vec<_>, EvalError>>()?;
+                    functions.call(function_name, &args).map(|x| x.at(*span)).map_err(|e| e.at(*span))
+                }
+            },
+            SimplExpr::Lambda(span, ..) => Err(EvalError::UnexpectedLambda.at(*span)),
+            SimplExpr::Formatted(span, value, spec) => {
+                let value = value.eval_in(scope, functions)?;
+                Ok(DynVal::from(spec.apply(&value).map_err(|e| e.at(*span))?).at(*span))
             }
-            SimplExpr::FunctionCall(span, function_name, args) => {
-                let args = args.into_iter().map(|a| a.eval(values)).collect::<Result<_, EvalError>>()?;
-                call_expr_function(&function_name, args).map(|x| x.at(*span)).map_err(|e| e.at(*span))
+            SimplExpr::Let(_, name, value, body) => {
+                let value = value.eval_in(scope, functions)?;
+                scope.with_local(name, value, |scope| body.eval_in(scope, functions))
             }
             SimplExpr::JsonArray(span, entries) => {
                 let entries = entries
-                    .into_iter()
-                    .map(|v| Ok(serde_json::Value::String(v.eval(values)?.as_string()?)))
-                    .collect::<Result<_, EvalError>>()?;
-                Ok(DynVal::try_from(serde_json::Value::Array(entries))?.at(*span))
+                    .iter()
+                    .map(|v| Ok(json_element(v.is_string(), &v.eval_in(scope, functions)?)))
+                    .collect::<Result<Vec<_>, EvalError>>()?;
+                Ok(json_array(entries)?.at(*span))
             }
             SimplExpr::JsonObject(span, entries) => {
                 let entries = entries
-                    .into_iter()
-                    .map(|(k, v)| Ok((k.eval(values)?.as_string()?, serde_json::Value::String(v.eval(values)?.as_string()?))))
-                    .collect::<Result<_, EvalError>>()?;
-                Ok(DynVal::try_from(serde_json::Value::Object(entries))?.at(*span))
+                    .iter()
+                    .map(|(k, v)| Ok((k.eval_in(scope, functions)?, json_element(v.is_string(), &v.eval_in(scope, functions)?))))
+                    .collect::<Result<Vec<_>, EvalError>>()?;
+                Ok(json_object(entries)?.at(*span))
             }
         };
         Ok(value?.at(span))
     }
-}
 
-fn call_expr_function(name: &str, args: Vec<DynVal>) -> Result<DynVal, EvalError> {
-    match name {
-        "round" => match args.as_slice() {
-            [num, digits] => {
-                let num = num.as_f64()?;
-                let digits = digits.as_i32()?;
-                Ok(DynVal::from(format!("{:.1$}", num, digits as usize)))
+    /// Evaluate the left side of a `??`, resulting in `None` if the value is absent.
+    ///
+    /// Only json accesses count as absent, if they can't index into the value or the indexed value is `null`.
+    /// Thus, `.` and `[]` accesses along the left side behave like `?.`.
+    /// A `??` is absent if both of its sides are, such that `a.b ?? c.d ?? e` falls back to `e` if neither access succeeds.
+    /// Any other value, like a variable or a string that contains `null`, is never absent.
+    fn eval_coalesced(&self, scope: &mut Scope, functions: &FunctionRegistry) -> Result<Option<DynVal>, EvalError> {
+        match self {
+            SimplExpr::JsonAccess(span, val, index) | SimplExpr::JsonSafeAccess(span, val, index) => {
+                let val = val.eval_coalesced(scope, functions)?;
+                let index = index.eval_in(scope, functions)?;
+                Ok(val.and_then(|val| json_safe_access(&val, &index)).map(|x| x.at(*span)))
             }
-            _ => Err(EvalError::WrongArgCount(name.to_string())),
+            SimplExpr::BinOp(span, a, BinOp::NullCoalesce, b) => match a.eval_coalesced(scope, functions)? {
+                Some(a) => Ok(Some(a.at(*span))),
+                None => Ok(b.eval_coalesced(scope, functions)?.map(|x| x.at(*span))),
+            },
+            _ => self.eval_in(scope, functions).map(Some),
+        }
+    }
+
+    /// Evaluate the left side of a `??` without any variables, resulting in `None` if the value is absent.
+    /// See [SimplExpr::eval_coalesced].
+    pub(crate) fn eval_coalesced_no_vars(&self, functions: &FunctionRegistry) -> Result<Option<DynVal>, EvalError> {
+        self.eval_coalesced(&mut Scope { values: &HashMap::new(), locals: Vec::new() }, functions)
+    }
+}
+
+/// The variables an expression is evaluated with: the given values, overlaid by the variables bound within the expression.
+/// Bindings of `let` and lambdas are added and removed as they come in and out of scope, such that the values never need to be copied.
+struct Scope<'a> {
+    values: &'a HashMap<VarName, DynVal>,
+    /// Variables bound within the expression, with the innermost binding last
+    locals: Vec<(VarName, DynVal)>,
+}
+
+impl Scope<'_> {
+    fn get(&self, name: &VarName) -> Option<&DynVal> {
+        match self.locals.iter().rev().find(|(local, _)| local == name) {
+            Some((_, value)) => Some(value),
+            None => self.values.get(name),
+        }
+    }
+
+    fn names(&self) -> impl Iterator<Item = &VarName> {
+        self.values.keys().chain(self.locals.iter().map(|(name, _)| name))
+    }
+
+    /// Run the given function with the variable bound to the value.
+    fn with_local<T>(&mut self, name: &VarName, value: DynVal, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.locals.push((name.clone(), value));
+        let result = f(self);
+        self.locals.pop();
+        result
+    }
+}
+
+pub(crate) fn unknown_variable<'a>(name: &VarName, known: impl Iterator<Item = &'a VarName>) -> EvalError {
+    let similar_ish = known.filter(|key| levenshtein::levenshtein(&key.0, &name.0) < 3).cloned().collect_vec();
+    EvalError::UnknownVariable(name.clone(), similar_ish)
+}
+
+/// Apply a binary operator. The null-coalescing operator is not handled here, as it needs to be evaluated lazily.
+pub(crate) fn eval_binop(op: &BinOp, a: DynVal, b: DynVal) -> Result<DynVal, EvalError> {
+    Ok(match op {
+        BinOp::Equals => DynVal::from(a == b),
+        BinOp::NotEquals => DynVal::from(a != b),
+        BinOp::And => DynVal::from(a.as_bool()? && b.as_bool()?),
+        BinOp::Or => DynVal::from(a.as_bool()? || b.as_bool()?),
+        BinOp::Plus => match (a.as_f64(), b.as_f64()) {
+            (Ok(_), Ok(_)) => arithmetic(&a, &b, |a, b| checked(a.checked_add(b)), |a, b| a + b)?,
+            _ => DynVal::from(format!("{}{}", a.as_string()?, b.as_string()?)),
         },
-        "replace" => match args.as_slice() {
-            [string, pattern, replacement] => {
-                let string = string.as_string()?;
-                let pattern = regex::Regex::new(&pattern.as_string()?)?;
-                let replacement = replacement.as_string()?;
-                Ok(DynVal::from(pattern.replace_all(&string, replacement.replace("$", "$$").replace("\\", "$")).into_owned()))
-            }
-            _ => Err(EvalError::WrongArgCount(name.to_string())),
+        BinOp::Minus => arithmetic(&a, &b, |a, b| checked(a.checked_sub(b)), |a, b| a - b)?,
+        BinOp::Times => arithmetic(&a, &b, |a, b| checked(a.checked_mul(b)), |a, b| a * b)?,
+        BinOp::Div => DynVal::from(a.as_f64()? / b.as_f64()?),
+        BinOp::IntDiv => arithmetic(&a, &b, |a, b| checked(floor_div(a, nonzero(b)?)), |a, b| (a / b).floor())?,
+        BinOp::Mod => arithmetic(&a, &b, |a, b| checked(a.checked_rem(nonzero(b)?)), |a, b| a % b)?,
+        BinOp::Pow => pow(&a, &b)?,
+        BinOp::GT => DynVal::from(compare(&a, &b)? == Ordering::Greater),
+        BinOp::LT => DynVal::from(compare(&a, &b)? == Ordering::Less),
+        BinOp::GE => DynVal::from(compare(&a, &b)? != Ordering::Less),
+        BinOp::LE => DynVal::from(compare(&a, &b)? != Ordering::Greater),
+        #[allow(clippy::useless_conversion)]
+        BinOp::Elvis => DynVal::from(if a.0.is_empty() { b } else { a }),
+        BinOp::NullCoalesce => unreachable!("null-coalescing is evaluated lazily"),
+        BinOp::RegexMatch => DynVal::from(cached_regex(&b.as_string()?)?.is_match(&a.as_string()?)),
+    })
+}
+
+pub(crate) fn eval_unaryop(op: &UnaryOp, a: DynVal) -> Result<DynVal, EvalError> {
+    Ok(match op {
+        UnaryOp::Not => DynVal::from(!a.as_bool()?),
+        UnaryOp::Negative => match a.as_i64() {
+            Ok(x) => DynVal::from(checked(x.checked_neg())?),
+            Err(_) => DynVal::from(-a.as_f64()?),
         },
-        _ => Err(EvalError::UnknownFunction(name.to_string())),
+    })
+}
+
+pub(crate) fn json_access(val: &DynVal, index: &DynVal) -> Result<DynVal, EvalError> {
+    match &*val.as_json_ref()? {
+        serde_json::Value::Array(val) => {
+            let index = index.as_i32()?;
+            let indexed_value = val.get(index as usize).unwrap_or(&serde_json::Value::Null);
+            Ok(DynVal::from(indexed_value))
+        }
+        serde_json::Value::Object(val) => {
+            let indexed_value = val
+                .get(&index.as_string()?)
+                .or_else(|| val.get(&index.as_i32().ok()?.to_string()))
+                .unwrap_or(&serde_json::Value::Null);
+            Ok(DynVal::from(indexed_value))
+        }
+        _ => Err(EvalError::CannotIndex(format!("{}", val))),
+    }
+}
+
+/// Index into a json value like [json_access], resulting in `None` instead of failing, or if the indexed value is `null`.
+pub(crate) fn json_safe_access(val: &DynVal, index: &DynVal) -> Option<DynVal> {
+    let json = val.as_json_ref();
+    let indexed_value = match json.as_deref() {
+        Ok(serde_json::Value::Array(val)) => index.as_i32().ok().and_then(|index| val.get(index as usize)),
+        Ok(serde_json::Value::Object(val)) => val.get(&index.0).or_else(|| val.get(&index.as_i32().ok()?.to_string())),
+        _ => None,
+    };
+    indexed_value.filter(|value| !value.is_null()).map(DynVal::from)
+}
+
+/// The value `?.` results in if it can't index into a value.
+pub(crate) fn json_null() -> DynVal {
+    DynVal::from(&serde_json::Value::Null)
+}
+
+/// Turn the value of an element of a json array or object literal into json, depending on the expression it resulted from.
+/// String literals and interpolations always result in json strings, such that `'1.50'` and `"${1 + 1}"` stay strings.
+/// The json type of any other value is given by its text: numbers, booleans, `null` and json arrays or objects keep their type,
+/// and anything else is a json string.
+pub(crate) fn json_element(is_string: bool, value: &DynVal) -> serde_json::Value {
+    use serde_json::Value;
+    if is_string {
+        return Value::String(value.0.clone());
+    }
+    if let Ok(int) = value.as_i64() {
+        return Value::from(int);
+    }
+    if let Some(number) = value.as_f64().ok().and_then(serde_json::Number::from_f64) {
+        return Value::Number(number);
+    }
+    match value.as_json_ref().as_deref() {
+        Ok(json @ (Value::Array(_) | Value::Object(_) | Value::Bool(_) | Value::Null)) => json.clone(),
+        _ => Value::String(value.0.clone()),
+    }
+}
+
+pub(crate) fn json_array(entries: Vec<serde_json::Value>) -> Result<DynVal, EvalError> {
+    Ok(DynVal::try_from(serde_json::Value::Array(entries))?)
+}
+
+pub(crate) fn json_object(entries: Vec<(DynVal, serde_json::Value)>) -> Result<DynVal, EvalError> {
+    let entries = entries.into_iter().map(|(k, v)| Ok((k.as_string()?, v))).collect::<Result<_, EvalError>>()?;
+    Ok(DynVal::try_from(serde_json::Value::Object(entries))?)
+}
+
+/// Compile a regex, reusing previously compiled regexes, as the same patterns tend to be used over and over again.
+pub(crate) fn cached_regex(pattern: &str) -> Result<regex::Regex, regex::Error> {
+    thread_local! {
+        static REGEX_CACHE: RefCell<HashMap<String, regex::Regex>> = RefCell::new(HashMap::new());
+    }
+    REGEX_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if let Some(regex) = cache.get(pattern) {
+            return Ok(regex.clone());
+        }
+        // patterns built from changing values would otherwise make the cache grow forever
+        if cache.len() >= 256 {
+            cache.clear();
+        }
+        let regex = regex::Regex::new(pattern)?;
+        cache.insert(pattern.to_string(), regex.clone());
+        Ok(regex)
+    })
+}
+
+/// Format a duration in a short, human readable way, like `1h 5m 20s`.
+/// Units that are zero are left out, and durations shorter than a second are given in milliseconds.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs == 0 {
+        return format!("{}ms", duration.as_millis());
+    }
+    let units = [(secs / 86400, "d"), (secs / 3600 % 24, "h"), (secs / 60 % 60, "m"), (secs % 60, "s")];
+    units.iter().filter(|(amount, _)| *amount > 0).map(|(amount, unit)| format!("{}{}", amount, unit)).join(" ")
+}
+
+/// Apply an arithmetic operation, using integer arithmetic if both operands are integers.
+/// This keeps integer results formatted as integers, and avoids losing precision on large numbers.
+/// Integer results that don't fit into an i64 are an error, rather than silently losing precision as a float.
+fn arithmetic(
+    a: &DynVal,
+    b: &DynVal,
+    int_op: fn(i64, i64) -> Result<i64, EvalError>,
+    float_op: fn(f64, f64) -> f64,
+) -> Result<DynVal, EvalError> {
+    if let (Ok(a), Ok(b)) = (a.as_i64(), b.as_i64()) {
+        return Ok(DynVal::from(int_op(a, b)?));
+    }
+    Ok(DynVal::from(float_op(a.as_f64()?, b.as_f64()?)))
+}
+
+/// Turn the result of a checked integer operation into an error if it overflowed.
+fn checked(result: Option<i64>) -> Result<i64, EvalError> {
+    result.ok_or(EvalError::IntegerOverflow)
+}
+
+/// Check the divisor of an integer division, which is undefined for zero.
+fn nonzero(divisor: i64) -> Result<i64, EvalError> {
+    if divisor == 0 {
+        Err(EvalError::DivisionByZero)
+    } else {
+        Ok(divisor)
+    }
+}
+
+/// Integer division, rounding towards negative infinity.
+fn floor_div(a: i64, b: i64) -> Option<i64> {
+    let quotient = a.checked_div(b)?;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        Some(quotient - 1)
+    } else {
+        Some(quotient)
+    }
+}
+
+pub(crate) fn pow(base: &DynVal, exponent: &DynVal) -> Result<DynVal, EvalError> {
+    // negative exponents result in fractions, which need floating point arithmetic
+    if matches!(exponent.as_i64(), Ok(x) if x < 0) {
+        return Ok(DynVal::from(base.as_f64()?.powf(exponent.as_f64()?)));
+    }
+    arithmetic(base, exponent, |a, b| checked(u32::try_from(b).ok().and_then(|b| a.checked_pow(b))), f64::powf)
+}
+
+/// Compare two values numerically if both of them are numbers, and lexicographically otherwise.
+pub(crate) fn compare(a: &DynVal, b: &DynVal) -> Result<Ordering, EvalError> {
+    match (a.as_f64(), b.as_f64()) {
+        (Ok(_), Ok(_)) => compare_numbers(a, b),
+        _ => Ok(a.0.cmp(&b.0)),
+    }
+}
+
+pub(crate) fn compare_numbers(a: &DynVal, b: &DynVal) -> Result<Ordering, EvalError> {
+    match (a.as_i64(), b.as_i64()) {
+        (Ok(a), Ok(b)) => Ok(a.cmp(&b)),
+        _ => Ok(a.as_f64()?.partial_cmp(&b.as_f64()?).unwrap_or(Ordering::Equal)),
+    }
+}
+
+pub(crate) fn sum(values: impl Iterator<Item = DynVal>) -> Result<DynVal, EvalError> {
+    values.fold(Ok(DynVal::from(0)), |total, value| arithmetic(&total?, &value, |a, b| checked(a.checked_add(b)), |a, b| a + b))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dynval::DynVal;
+    use eww_shared_util::VarName;
+    use std::collections::HashMap;
+
+    fn eval(text: &str) -> Result<String, String> {
+        let expr = crate::parser::parse_string(0, 0, text).map_err(|e| e.to_string())?;
+        expr.eval(&HashMap::new()).map(|x| x.0).map_err(|e| e.to_string())
+    }
+
+    macro_rules! test_eval {
+        ($($text:literal),* $(,)?) => {{
+            $(
+                ::insta::assert_debug_snapshot!(eval($text));
+            )*
+        }}
+    }
+
+    #[test]
+    fn test_string_functions() {
+        test_eval!(
+            "substring('hello world', 6, 5)",
+            "substring('hello', 3, 10)",
+            "substring('hello', 1)",
+            "strlength('hëllo')",
+            "split('a,b,c', ',')",
+            "join(split('a,b,c', ','), ' - ')",
+            "join('[1, true, \"x\"]', '')",
+            "join('not an array', '')",
+            "trim('  hi  ')",
+            "upper('hi') + lower('HO')",
+            "search('a1b22c333', '[0-9]+')",
+            "captures('foo=1 bar=2', '([a-z]+)=([0-9])')",
+            "captures('ab', 'a(x)?b')",
+            "search('abc', '(')",
+            "pad_left('7', 3)",
+            "pad_left(7, 3, '0')",
+            "pad_left('hello', 3, '0')",
+            "pad_left('7', 3, '00')",
+            "pad_left('7', 'three')",
+            "truncate('hello world', 5)",
+            "truncate('hello world', 5, '...')",
+            "truncate('hi', 5, '...')",
+            "truncate('hello world', 5, '')",
+            "truncate('hello', 2, '...')",
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        test_eval!(
+            "1 + 2",
+            "1.5 + 2",
+            "9007199254740993 + 1",
+            "9223372036854775807 + 1",
+            "3 / 2",
+            "4 / 2",
+            "7 // 2",
+            "'-7' // 2",
+            "7.5 // 2",
+            "7 % 3",
+            "2 ** 10",
+            "2 ** 3 ** 2",
+            "2 ** '-1'",
+            "2 * 3 ** 2",
+            "1 // 0",
+            "1 % 0",
+            "1.5 // 0",
+            "-9223372036854775807 - 2",
+            "2 ** 64",
+            "-(-9223372036854775807 - 1)",
+        );
+    }
+
+    #[test]
+    fn test_comparisons() {
+        test_eval!(
+            "1 < 2",
+            "2 > 10",
+            "10 >= 10",
+            "1.5 <= 1",
+            "9007199254740993 > 9007199254740992",
+            "'apple' < 'banana'",
+            "'b' >= 'abc'",
+            "'10' < 'a'",
+            "-3 < -2",
+            "-(2 + 3) * 2",
+            "-'1.5'",
+            "- 2 ** 2",
+            "-'foo'",
+        );
+    }
+
+    #[test]
+    fn test_time_functions() {
+        test_eval!(
+            "formattime(0, '%Y-%m-%d %H:%M:%S', 'UTC')",
+            "formattime(1629900000, '%H:%M', 'Europe/Berlin')",
+            "formattime(1629900000, '%a, %d. %B', 'America/New_York')",
+            "formattime(0, '%H:%M', 'Mars/Olympus_Mons')",
+            "formattime(0, '%Q', 'UTC')",
+            "formattime('yesterday', '%H:%M', 'UTC')",
+            "formattime(0)",
+            "formatduration(0)",
+            "formatduration(1500)",
+            "formatduration(90061000)",
+            "formatduration(3600000)",
+            "formatduration(-61000)",
+        );
+    }
+
+    #[test]
+    fn test_unit_formatting() {
+        test_eval!(
+            "formatduration(1500.6)",
+            "formatduration('1m30s')",
+            "formatduration('1.5h')",
+            "formatduration('soon')",
+            "formatbytes(1536)",
+            "formatbytes(1536, 3)",
+            "formatbytes(1536, 2, false)",
+            "formatbytes(1000)",
+            "formatbytes(5368709120, 0) + '/s'",
+            "convertbytes(1536, 'KiB')",
+            "convertbytes(2500000, 'MB')",
+            "convertbytes(1, 'parsecs')",
+        );
+    }
+
+    #[test]
+    fn test_json_functions() {
+        test_eval!(
+            r#"map('[{"usage": 1}, {"usage": 2.5}]', x => x.usage)"#,
+            r#"map('["a", "b"]', x => upper(x) + "!")"#,
+            r#"map('[[1, 2], [3]]', x => map(x, y => y * 10))"#,
+            r#"filter('[1, 5, 2, 8]', x => x > 2)"#,
+            r#"filter('[{"name": "a", "up": true}, {"name": "b", "up": false}]', x => x.up)"#,
+            r#"sort_by('[{"n": "b", "v": 10}, {"n": "a", "v": 9}, {"n": "c", "v": 100}]', x => x.v)"#,
+            r#"sort_by('["pear", "apple", "fig"]', x => x)"#,
+            r#"sum('[1, 2, 3]')"#,
+            r#"sum('[]')"#,
+            r#"sum('[{"v": 1.5}, {"v": 2}]', x => x.v)"#,
+            r#"length('[1, 2, 3]') + length('{"a": 1}')"#,
+            r#"length('hello')"#,
+            r#"keys('{"a": 1, "b": {"c": 2}}')"#,
+            r#"values('{"a": 1, "b": {"c": 2}}')"#,
+            r#"map('not an array', x => x)"#,
+            r#"round(1, x => x)"#,
+            r#"strlength(x => x)"#,
+            r#"mpa('[1]', x => x)"#,
+        );
+    }
+
+    #[test]
+    fn test_lambda_bound_variables() {
+        let expr = crate::parser::parse_string(0, 0, "map(cores, x => x.usage * scale) + x").unwrap();
+        let refs = expr.var_refs().into_iter().map(|(_, name)| name.0.as_str()).collect::<Vec<_>>();
+        assert_eq!(refs, vec!["cores", "scale", "x"]);
+
+        let variables: HashMap<VarName, DynVal> = vec![("cores", r#"[{"usage": 1}, {"usage": 2}]"#), ("scale", "3"), ("x", "!")]
+            .into_iter()
+            .map(|(name, value)| (VarName::from(name), DynVal::from(value)))
+            .collect();
+        let resolved = expr.clone().resolve_refs(&variables).unwrap();
+        assert_eq!(resolved.eval(&HashMap::new()).unwrap().0, "[3,6]!");
+        assert_eq!(expr.eval(&variables).unwrap().0, "[3,6]!");
+    }
+
+    #[test]
+    fn test_json_literals() {
+        test_eval!(
+            r#"[1, true, "a", 1.50, 'null']"#,
+            r#"[[1, 2], {"a": [false]}, []]"#,
+            r#"{"a": 1, "b": {"c": "d"}, 5: 2 + 3}"#,
+            r#"["${1 + 1}", '"quoted"']"#,
+            r#"{"nested": '{ "a": 1 }'}.nested.a"#,
+            r#"[1, 2, 3][1] + 1"#,
+            r#"[1 + 1, -0.5, {"a": 1}?.b, upper('x'), 'true', "${true}"]"#,
+        );
+    }
+
+    #[test]
+    fn test_null_safe_access() {
+        test_eval!(
+            r#"{"a": {"b": 1}}?.a?.b"#,
+            r#"{"a": 1}?.b?.c"#,
+            r#"{"a": 1}.b ?? "default""#,
+            r#"{"a": 1}?.b?.c ?? "default""#,
+            r#"'hello'?.foo ?? 'not json'"#,
+            r#"12?.[0] ?? 'not indexable'"#,
+            r#"[1, 2]?.[1]"#,
+            r#"[1, 2]?.[5] ?? 'out of bounds'"#,
+            r#"[1, 2]?.['a'] ?? 'not a number'"#,
+            r#"'' ?? 'empty'"#,
+            r#"0 ?? 'zero is not absent'"#,
+            r#"1 ?? unknown_variable"#,
+            r#"{"a": 1}.b.c"#,
+            r#"{"a": 1}.b.c ?? "d""#,
+            r#"'hello'.foo ?? 'not json'"#,
+            r#"[1, 2][5].a ?? 'out of bounds'"#,
+            r#"{"a": {"b": 1}}.a.b ?? 'absent'"#,
+            r#"unknown_variable.a ?? 'still an error'"#,
+            r#"'null' ?? 'a string is never absent'"#,
+            r#"'{"a": null}'.a ?? 'null is absent'"#,
+            r#"{"a": "null"}.a ?? 'a string is never absent'"#,
+            r#"{"a": 1}.b ?? [1][5] ?? 'neither access succeeds'"#,
+            r#"let b = {"a": 1}?.b in b ?? 'only accesses are absent'"#,
+        );
+    }
+
+    #[test]
+    fn test_let() {
+        test_eval!(
+            "let x = 2 in x * x",
+            "let x = 2 in let y = x + 1 in x * y",
+            "let x = 1 in let x = x + 1 in x",
+            "(let x = 2 in x) + 1",
+            r#"let bat = {"capacity": 42} in bat.capacity > 20 ? "${bat.capacity}%" : "low""#,
+            "let index = 1 in [10, 20][index]",
+            "let x = y in x",
+        );
+    }
+
+    #[test]
+    fn test_let_scoping() {
+        let expr = crate::parser::parse_string(0, 0, "(let x = a + 1 in x * b) + x").unwrap();
+        let refs = expr.var_refs().into_iter().map(|(_, name)| name.0.as_str()).collect::<Vec<_>>();
+        assert_eq!(refs, vec!["a", "b", "x"]);
+
+        let variables: HashMap<VarName, DynVal> = vec![("a", "1"), ("b", "3"), ("x", "10")]
+            .into_iter()
+            .map(|(name, value)| (VarName::from(name), DynVal::from(value)))
+            .collect();
+        assert_eq!(expr.clone().resolve_refs(&variables).unwrap().eval(&HashMap::new()).unwrap().0, "16");
+        assert_eq!(expr.eval(&variables).unwrap().0, "16");
+    }
+
+    #[test]
+    fn test_math_functions() {
+        test_eval!(
+            "min(3, 1, 2)",
+            "max(3, 1.5, 12)",
+            "min()",
+            "max(1, 'a')",
+            "floor(2.7) + ceil(2.1)",
+            "floor('-2.5')",
+            "abs('-12')",
+            "abs('-1.5')",
+            "pow(3, 4)",
+            "pow(4, 0.5)",
+            "log(100, 10)",
+            "round(log(2), 4)",
+            "clamp(120, 0, 100)",
+            "clamp('-5', 0, 100)",
+            "clamp(42, 0, 100)",
+            "clamp(1, 2)",
+        );
+    }
+
+    #[test]
+    fn test_format_specifiers() {
+        test_eval!(
+            r#""${3.14159:.2}""#,
+            r#""[${42:>5}]""#,
+            r#""[${'ab':>5}] [${'ab':5}] [${'ab':-^6}] [${7:0>3}] [${7:<3}]""#,
+            r#""${'truncated':.5}""#,
+            r#""${1536:bytes} ${512:bytes} ${3221225472:.2bytes}""#,
+            r#""${42.567:percent} ${42.567:.1percent}""#,
+            r#""${true ? 1 : 2:.1}""#,
+            r#""${'a':bytes}""#,
+            r#""${1:%}""#,
+            r#""${1:.x}""#,
+        );
     }
 }
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+use crate::{dynval::DynVal, eval::EvalError};
+
+/// Formatting of a value within a string interpolation, as given in `"${value:spec}"`.
+///
+/// The format of a spec is `[[fill]align][width][.precision][kind]`, i.e. `>5`, `.2`, `0>3` or `.1bytes`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FormatSpec {
+    pub fill: char,
+    pub align: Option<Alignment>,
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+    pub kind: FormatKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Alignment {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FormatKind {
+    Default,
+    /// A number of bytes, shown in binary units, like `1.5 KiB`
+    Bytes,
+    /// A percentage in the range of 0 to 100, shown with a `%` sign
+    Percent,
+}
+
+impl FromStr for FormatSpec {
+    type Err = EvalError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let unknown = || EvalError::UnknownFormatSpec(spec.to_string());
+        let mut rest = spec;
+        let mut fill = ' ';
+        let mut align = None;
+
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next().and_then(parse_alignment)) {
+            (Some(fill_char), Some(alignment)) => {
+                fill = fill_char;
+                align = Some(alignment);
+                rest = &rest[fill_char.len_utf8() + 1..];
+            }
+            (Some(first), _) if parse_alignment(first).is_some() => {
+                align = parse_alignment(first);
+                rest = &rest[1..];
+            }
+            _ => {}
+        }
+
+        let (width, remaining) = take_number(rest);
+        let width = width.map(|x| x.parse().map_err(|_| unknown())).transpose()?;
+        rest = remaining;
+
+        let mut precision = None;
+        if let Some(remaining) = rest.strip_prefix('.') {
+            let (digits, remaining) = take_number(remaining);
+            precision = Some(digits.ok_or_else(unknown)?.parse().map_err(|_| unknown())?);
+            rest = remaining;
+        }
+
+        let kind = match rest {
+            "" => FormatKind::Default,
+            "bytes" => FormatKind::Bytes,
+            "percent" => FormatKind::Percent,
+            _ => return Err(unknown()),
+        };
+        Ok(FormatSpec { fill, align, width, precision, kind })
+    }
+}
+
+impl fmt::Display for FormatSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(align) = self.align {
+            if self.fill != ' ' {
+                write!(f, "{}", self.fill)?;
+            }
+            let align = match align {
+                Alignment::Left => '<',
+                Alignment::Right => '>',
+                Alignment::Center => '^',
+            };
+            write!(f, "{}", align)?;
+        }
+        if let Some(width) = self.width {
+            write!(f, "{}", width)?;
+        }
+        if let Some(precision) = self.precision {
+            write!(f, ".{}", precision)?;
+        }
+        match self.kind {
+            FormatKind::Default => Ok(()),
+            FormatKind::Bytes => write!(f, "bytes"),
+            FormatKind::Percent => write!(f, "percent"),
+        }
+    }
+}
+
+impl FormatSpec {
+    /// Format the given value according to this spec.
+    pub fn apply(&self, value: &DynVal) -> Result<String, EvalError> {
+        let number = value.as_f64();
+        let is_number = number.is_ok();
+        let text = match self.kind {
+            FormatKind::Bytes => format_bytes(number?, self.precision.unwrap_or(1), true),
+            FormatKind::Percent => format!("{:.*}%", self.precision.unwrap_or(0), number?),
+            FormatKind::Default => match (self.precision, number) {
+                (Some(precision), Ok(number)) => format!("{:.*}", precision, number),
+                (Some(precision), Err(_)) => value.0.chars().take(precision).collect(),
+                (None, _) => value.0.clone(),
+            },
+        };
+
+        let padding = self.width.unwrap_or_default().saturating_sub(text.chars().count());
+        // like in rust, numbers are aligned to the right by default, and everything else to the left
+        let align = self.align.unwrap_or(if is_number { Alignment::Right } else { Alignment::Left });
+        let (left, right) = match align {
+            Alignment::Left => (0, padding),
+            Alignment::Right => (padding, 0),
+            Alignment::Center => (padding / 2, padding - padding / 2),
+        };
+        let fill = |n| std::iter::repeat(self.fill).take(n);
+        Ok(fill(left).chain(text.chars()).chain(fill(right)).collect())
+    }
+}
+
+fn parse_alignment(c: char) -> Option<Alignment> {
+    match c {
+        '<' => Some(Alignment::Left),
+        '>' => Some(Alignment::Right),
+        '^' => Some(Alignment::Center),
+        _ => None,
+    }
+}
+
+/// Split off the leading digits of a string, if there are any.
+fn take_number(s: &str) -> (Option<&str>, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or_else(|| s.len());
+    if end == 0 {
+        (None, s)
+    } else {
+        (Some(&s[..end]), &s[end..])
+    }
+}
+
+const IEC_UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+const SI_UNITS: [&str; 7] = ["B", "kB", "MB", "GB", "TB", "PB", "EB"];
+
+/// Format a number of bytes in a human readable way, like `1.5 KiB`,
+/// using binary (IEC) units if `binary` is set, and decimal (SI) units otherwise.
+pub fn format_bytes(bytes: f64, precision: usize, binary: bool) -> String {
+    let (base, units) = if binary { (1024.0, IEC_UNITS) } else { (1000.0, SI_UNITS) };
+    let mut value = bytes;
+    let mut unit = 0;
+    while value.abs() >= base && unit < units.len() - 1 {
+        value /= base;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", value, units[0])
+    } else {
+        format!("{:.*} {}", precision, value, units[unit])
+    }
+}
+
+/// Get the number of bytes in the given SI (`kB`, `MB`, ...) or IEC (`KiB`, `MiB`, ...) unit.
+/// Units are matched case-insensitively, so `kb` and `KB` are accepted as well.
+pub fn byte_unit_size(unit: &str) -> Option<f64> {
+    let find_power = |units: [&str; 7]| units.iter().position(|x| x.eq_ignore_ascii_case(unit));
+    if let Some(power) = find_power(IEC_UNITS) {
+        Some(1024f64.powi(power as i32))
+    } else {
+        find_power(SI_UNITS).map(|power| 1000f64.powi(power as i32))
+    }
+}
@@ -0,0 +1,248 @@
+use std::{cmp::Ordering, convert::TryFrom, time::Duration};
+
+use itertools::Itertools;
+
+use super::{FunctionRegistry, FunctionSignature, LambdaSignature};
+use crate::{
+    dynval::{ConversionError, DynVal},
+    eval::{cached_regex, compare, compare_numbers, format_duration, pow, sum, EvalError},
+    format_spec::{byte_unit_size, format_bytes},
+};
+
+/// Register the functions that are built into simplexpr.
+/// The arguments are checked against the signature before a function is called,
+/// so the implementations can index into them and rely on them being of the right type.
+pub(super) fn register_builtins(registry: &mut FunctionRegistry) {
+    use super::ValueType::{Any, Bool, Int, Json, Number, String};
+    registry
+        .register("round", FunctionSignature::new(&[Number, Int], Number), |args| {
+            let num = args[0].as_f64()?;
+            let digits = args[1].as_i32()?.max(0) as usize;
+            Ok(DynVal::from(format!("{:.1$}", num, digits)))
+        })
+        .register("replace", FunctionSignature::new(&[String, String, String], String), |args| {
+            let string = args[0].as_string()?;
+            let pattern = cached_regex(&args[1].as_string()?)?;
+            let replacement = args[2].as_string()?;
+            Ok(DynVal::from(pattern.replace_all(&string, replacement.replace("$", "$$").replace("\\", "$")).into_owned()))
+        })
+        .register("substring", FunctionSignature::new(&[String, Int, Int], String), |args| {
+            let start = args[1].as_i32()?.max(0) as usize;
+            let length = args[2].as_i32()?.max(0) as usize;
+            Ok(DynVal::from_string(args[0].as_string()?.chars().skip(start).take(length).collect()))
+        })
+        .register("strlength", FunctionSignature::new(&[String], Int), |args| {
+            Ok(DynVal::from(args[0].as_string()?.chars().count() as i32))
+        })
+        .register("split", FunctionSignature::new(&[String, String], Json), |args| {
+            let string = args[0].as_string()?;
+            let separator = args[1].as_string()?;
+            json_string_array(string.split(separator.as_str()).map(str::to_string))
+        })
+        .register("join", FunctionSignature::new(&[Json, String], String), |args| {
+            let separator = args[1].as_string()?;
+            let joined = args[0]
+                .as_json_array()?
+                .into_iter()
+                .map(|value| match value {
+                    serde_json::Value::String(x) => x,
+                    other => other.to_string(),
+                })
+                .join(&separator);
+            Ok(DynVal::from(joined))
+        })
+        .register("trim", FunctionSignature::new(&[String], String), |args| Ok(DynVal::from(args[0].as_string()?.trim())))
+        .register("upper", FunctionSignature::new(&[String], String), |args| {
+            Ok(DynVal::from(args[0].as_string()?.to_uppercase()))
+        })
+        .register("lower", FunctionSignature::new(&[String], String), |args| {
+            Ok(DynVal::from(args[0].as_string()?.to_lowercase()))
+        })
+        .register("search", FunctionSignature::new(&[String, String], Json), |args| {
+            let string = args[0].as_string()?;
+            let pattern = cached_regex(&args[1].as_string()?)?;
+            json_string_array(pattern.find_iter(&string).map(|x| x.as_str().to_string()))
+        })
+        .register("captures", FunctionSignature::new(&[String, String], Json), |args| {
+            let string = args[0].as_string()?;
+            let pattern = cached_regex(&args[1].as_string()?)?;
+            let captures = pattern
+                .captures_iter(&string)
+                .map(|captures| {
+                    let groups = captures.iter().map(|group| group.map(|x| x.as_str()).unwrap_or_default());
+                    serde_json::Value::Array(groups.map(|x| serde_json::Value::String(x.to_string())).collect())
+                })
+                .collect();
+            Ok(DynVal::try_from(serde_json::Value::Array(captures))?)
+        })
+        .register("pad_left", FunctionSignature::new(&[String, Int, String], String).optional(1), |args| match args.get(2) {
+            None => pad_left(&args[0], &args[1], ' '),
+            Some(fill) => {
+                let mut chars = fill.0.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(fill), None) => pad_left(&args[0], &args[1], fill),
+                    _ => Err(ConversionError { value: fill.clone(), target_type: "char", source: None }.into()),
+                }
+            }
+        })
+        .register("truncate", FunctionSignature::new(&[String, Int, String], String).optional(1), |args| {
+            let suffix = args.get(2).map(|x| x.0.as_str()).unwrap_or("…");
+            truncate(&args[0], &args[1], suffix)
+        })
+        .register("min", FunctionSignature::new(&[Number], Number).variadic(), |args| extremum(args, Ordering::Less))
+        .register("max", FunctionSignature::new(&[Number], Number).variadic(), |args| extremum(args, Ordering::Greater))
+        .register("floor", FunctionSignature::new(&[Number], Number), |args| {
+            let num = &args[0];
+            Ok(num.as_i64().map(DynVal::from).or_else(|_| num.as_f64().map(|x| DynVal::from(x.floor())))?)
+        })
+        .register("ceil", FunctionSignature::new(&[Number], Number), |args| {
+            let num = &args[0];
+            Ok(num.as_i64().map(DynVal::from).or_else(|_| num.as_f64().map(|x| DynVal::from(x.ceil())))?)
+        })
+        .register("abs", FunctionSignature::new(&[Number], Number), |args| {
+            match args[0].as_i64().ok().and_then(i64::checked_abs) {
+                Some(x) => Ok(DynVal::from(x)),
+                None => Ok(DynVal::from(args[0].as_f64()?.abs())),
+            }
+        })
+        .register("pow", FunctionSignature::new(&[Number, Number], Number), |args| pow(&args[0], &args[1]))
+        .register("log", FunctionSignature::new(&[Number, Number], Number).optional(1), |args| {
+            let num = args[0].as_f64()?;
+            match args.get(1) {
+                Some(base) => Ok(DynVal::from(num.log(base.as_f64()?))),
+                None => Ok(DynVal::from(num.ln())),
+            }
+        })
+        .register("clamp", FunctionSignature::new(&[Number, Number, Number], Number), |args| {
+            let (num, min, max) = (&args[0], &args[1], &args[2]);
+            if compare_numbers(num, min)? == Ordering::Less {
+                Ok(min.clone())
+            } else if compare_numbers(num, max)? == Ordering::Greater {
+                Ok(max.clone())
+            } else {
+                Ok(num.clone())
+            }
+        })
+        .register("sum", FunctionSignature::new(&[Json], Number), |args| sum(args[0].as_json_array()?.iter().map(DynVal::from)))
+        .register("length", FunctionSignature::new(&[Json], Int), |args| match args[0].as_json_value()? {
+            serde_json::Value::Array(x) => Ok(DynVal::from(x.len() as i64)),
+            serde_json::Value::Object(x) => Ok(DynVal::from(x.len() as i64)),
+            _ => Err(ConversionError { value: args[0].clone(), target_type: "json-array", source: None }.into()),
+        })
+        .register("keys", FunctionSignature::new(&[Json], Json), |args| {
+            json_string_array(args[0].as_json_object()?.into_iter().map(|(key, _)| key))
+        })
+        .register("values", FunctionSignature::new(&[Json], Json), |args| {
+            let values = args[0].as_json_object()?.into_iter().map(|(_, value)| value).collect();
+            Ok(DynVal::try_from(serde_json::Value::Array(values))?)
+        })
+        .register("formattime", FunctionSignature::new(&[Int, String, String], String).optional(1), |args| {
+            format_time(&args[0], &args[1], args.get(2))
+        })
+        // numbers are taken as milliseconds, anything else needs to be a duration like `1m30s`
+        .register("formatduration", FunctionSignature::new(&[Any], String), |args| {
+            let (sign, duration) = match args[0].as_f64() {
+                Ok(millis) => (if millis < 0.0 { "-" } else { "" }, Duration::from_millis(millis.abs().round() as u64)),
+                Err(_) => ("", args[0].as_duration()?),
+            };
+            Ok(DynVal::from(format!("{}{}", sign, format_duration(duration))))
+        })
+        .register("formatbytes", FunctionSignature::new(&[Number, Int, Bool], String).optional(2), |args| {
+            let precision = args.get(1).map(DynVal::as_i32).transpose()?.unwrap_or(1);
+            let binary = args.get(2).map(DynVal::as_bool).transpose()?.unwrap_or(true);
+            Ok(DynVal::from(format_bytes(args[0].as_f64()?, precision.max(0) as usize, binary)))
+        })
+        .register("convertbytes", FunctionSignature::new(&[Number, String], Number), |args| {
+            let unit = &args[1];
+            let unit_size = byte_unit_size(&unit.0).ok_or_else(|| EvalError::UnknownUnit(unit.0.clone()))?;
+            Ok(DynVal::from(args[0].as_f64()? / unit_size))
+        });
+
+    // functions taking a json array and a lambda, which gets called with the elements of the array
+    registry
+        .register_lambda("map", LambdaSignature { body: Any, returns: Json }, |items, apply| {
+            let mapped =
+                items.iter().map(|item| Ok(apply(DynVal::from(item))?.to_json_value())).collect::<Result<_, EvalError>>()?;
+            Ok(DynVal::try_from(serde_json::Value::Array(mapped))?)
+        })
+        .register_lambda("filter", LambdaSignature { body: Bool, returns: Json }, |items, apply| {
+            let mut filtered = Vec::new();
+            for item in items {
+                if apply(DynVal::from(&item))?.as_bool()? {
+                    filtered.push(item);
+                }
+            }
+            Ok(DynVal::try_from(serde_json::Value::Array(filtered))?)
+        })
+        .register_lambda("sort_by", LambdaSignature { body: Any, returns: Json }, |items, apply| {
+            let mut keyed =
+                items.into_iter().map(|item| Ok((apply(DynVal::from(&item))?, item))).collect::<Result<Vec<_>, EvalError>>()?;
+            // comparing two values can't fail, as values that aren't numbers are compared lexicographically
+            keyed.sort_by(|(a, _), (b, _)| compare(a, b).unwrap_or(Ordering::Equal));
+            let sorted = keyed.into_iter().map(|(_, item)| item).collect();
+            Ok(DynVal::try_from(serde_json::Value::Array(sorted))?)
+        })
+        .register_lambda("sum", LambdaSignature { body: Number, returns: Number }, |items, apply| {
+            sum(items.iter().map(|item| apply(DynVal::from(item))).collect::<Result<Vec<_>, _>>()?.into_iter())
+        });
+}
+
+/// Find the smallest or largest of the given numbers, depending on the wanted ordering.
+fn extremum(args: &[DynVal], wanted: Ordering) -> Result<DynVal, EvalError> {
+    let mut result = &args[0];
+    for arg in &args[1..] {
+        if compare_numbers(arg, result)? == wanted {
+            result = arg;
+        }
+    }
+    Ok(result.clone())
+}
+
+/// Format a unix timestamp (in seconds) using a strftime-style format string,
+/// in the given timezone, or the local timezone if none is given.
+fn format_time(timestamp: &DynVal, format: &DynVal, timezone: Option<&DynVal>) -> Result<DynVal, EvalError> {
+    use chrono::{format::Item, Local, TimeZone, Utc};
+    let format = format.as_string()?;
+    let items = chrono::format::StrftimeItems::new(&format).collect_vec();
+    // chrono panics when displaying a format that contains invalid items
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        return Err(EvalError::InvalidTimeFormat(format));
+    }
+    let datetime = Utc.timestamp_opt(timestamp.as_i64()?, 0).single().ok_or_else(|| ConversionError {
+        value: timestamp.clone(),
+        target_type: "timestamp",
+        source: None,
+    })?;
+    let formatted = match timezone {
+        Some(timezone) => {
+            let timezone = timezone.as_string()?;
+            let timezone: chrono_tz::Tz = timezone.parse().map_err(|_| EvalError::UnknownTimezone(timezone))?;
+            datetime.with_timezone(&timezone).format_with_items(items.iter()).to_string()
+        }
+        None => datetime.with_timezone(&Local).format_with_items(items.iter()).to_string(),
+    };
+    Ok(DynVal::from(formatted))
+}
+
+fn json_string_array(values: impl Iterator<Item = String>) -> Result<DynVal, EvalError> {
+    Ok(DynVal::try_from(serde_json::Value::Array(values.map(serde_json::Value::String).collect()))?)
+}
+
+fn pad_left(string: &DynVal, width: &DynVal, fill: char) -> Result<DynVal, EvalError> {
+    let string = string.as_string()?;
+    let padding = (width.as_i32()?.max(0) as usize).saturating_sub(string.chars().count());
+    Ok(DynVal::from(std::iter::repeat(fill).take(padding).chain(string.chars()).collect::<String>()))
+}
+
+/// Cut the string down to the given amount of characters, ending it with the suffix if anything was cut off.
+/// The suffix counts towards the length, such that the result is never longer than requested.
+fn truncate(string: &DynVal, length: &DynVal, suffix: &str) -> Result<DynVal, EvalError> {
+    let string = string.as_string()?;
+    let length = length.as_i32()?.max(0) as usize;
+    if string.chars().count() > length {
+        let kept = length.saturating_sub(suffix.chars().count());
+        Ok(DynVal::from(string.chars().take(kept).chain(suffix.chars()).take(length).collect::<String>()))
+    } else {
+        Ok(DynVal::from(string))
+    }
+}
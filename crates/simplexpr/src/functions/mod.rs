@@ -0,0 +1,282 @@
+mod builtins;
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use once_cell::sync::Lazy;
+
+use crate::{
+    dynval::{ConversionError, DynVal},
+    eval::EvalError,
+};
+
+/// The type of value a function expects as an argument, or returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum ValueType {
+    Any,
+    String,
+    Number,
+    Int,
+    Bool,
+    Json,
+    Duration,
+}
+
+impl ValueType {
+    /// Check that the given value can be read as this type.
+    pub fn check(self, value: &DynVal) -> Result<(), ConversionError> {
+        match self {
+            ValueType::Any | ValueType::String => Ok(()),
+            ValueType::Number => value.as_f64().map(|_| ()),
+            ValueType::Int => value.as_i64().map(|_| ()),
+            ValueType::Bool => value.as_bool().map(|_| ()),
+            ValueType::Json => value.as_json_value().map(|_| ()),
+            ValueType::Duration => value.as_duration().map(|_| ()),
+        }
+    }
+}
+
+/// The arguments a function accepts, and the type of value it returns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSignature {
+    pub args: Vec<ValueType>,
+    /// How many of the arguments have to be given. The remaining ones are optional.
+    pub required_args: usize,
+    /// Whether any amount of additional arguments of the same type as the last argument may be given
+    pub variadic: bool,
+    pub returns: ValueType,
+}
+
+impl FunctionSignature {
+    /// A function that requires all of the given arguments
+    pub fn new(args: &[ValueType], returns: ValueType) -> Self {
+        FunctionSignature { args: args.to_vec(), required_args: args.len(), variadic: false, returns }
+    }
+
+    /// Make the last `count` arguments optional.
+    pub fn optional(mut self, count: usize) -> Self {
+        self.required_args = self.args.len().saturating_sub(count);
+        self
+    }
+
+    /// Allow the last argument to be repeated any number of times.
+    pub fn variadic(mut self) -> Self {
+        self.variadic = true;
+        self
+    }
+
+    pub fn accepts_arg_count(&self, count: usize) -> bool {
+        count >= self.required_args && (self.variadic || count <= self.args.len())
+    }
+
+    /// The expected type of the argument at the given position
+    pub fn arg_type(&self, index: usize) -> Option<ValueType> {
+        match self.args.get(index) {
+            Some(arg) => Some(*arg),
+            None if self.variadic => self.args.last().copied(),
+            None => None,
+        }
+    }
+}
+
+type FunctionImpl = dyn Fn(&[DynVal]) -> Result<DynVal, EvalError> + Send + Sync;
+
+pub struct Function {
+    pub signature: FunctionSignature,
+    implementation: Box<FunctionImpl>,
+}
+
+impl std::fmt::Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Function").field("signature", &self.signature).finish()
+    }
+}
+
+/// What a [LambdaFunction] expects the lambda passed to it to return, and the type of value the function itself returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LambdaSignature {
+    pub body: ValueType,
+    pub returns: ValueType,
+}
+
+type LambdaFunctionImpl = dyn Fn(Vec<serde_json::Value>, &mut dyn FnMut(DynVal) -> Result<DynVal, EvalError>) -> Result<DynVal, EvalError>
+    + Send
+    + Sync;
+
+/// A function that takes a json array and a lambda, like `map(items, x => x.name)`.
+/// The implementation gets the elements of the array, and a function that calls the lambda with a value.
+pub struct LambdaFunction {
+    pub signature: LambdaSignature,
+    implementation: Box<LambdaFunctionImpl>,
+}
+
+impl std::fmt::Debug for LambdaFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LambdaFunction").field("signature", &self.signature).finish()
+    }
+}
+
+static BUILTINS: Lazy<FunctionRegistry> = Lazy::new(FunctionRegistry::with_builtins);
+
+/// The functions that can be called from within an expression.
+///
+/// Functions are registered together with their [FunctionSignature],
+/// which is checked before a function is called, such that implementations can rely on getting arguments of the right type.
+#[derive(Debug, Default)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, Function>,
+    /// Functions taking a lambda, which are kept separately, as a function may also be registered without taking a lambda
+    lambda_functions: HashMap<String, LambdaFunction>,
+}
+
+impl FunctionRegistry {
+    /// A registry without any functions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry containing all the functions that are built into simplexpr, which can be extended by custom functions
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        builtins::register_builtins(&mut registry);
+        registry
+    }
+
+    /// The shared registry of builtin functions, as used by [crate::SimplExpr::eval]
+    pub fn builtins() -> &'static Self {
+        &BUILTINS
+    }
+
+    /// Register a function, replacing any previously registered function of the same name.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        signature: FunctionSignature,
+        implementation: impl Fn(&[DynVal]) -> Result<DynVal, EvalError> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.functions.insert(name.into(), Function { signature, implementation: Box::new(implementation) });
+        self
+    }
+
+    /// Register a function that takes a json array and a lambda, replacing any previously registered one of the same name.
+    pub fn register_lambda(
+        &mut self,
+        name: impl Into<String>,
+        signature: LambdaSignature,
+        implementation: impl Fn(Vec<serde_json::Value>, &mut dyn FnMut(DynVal) -> Result<DynVal, EvalError>) -> Result<DynVal, EvalError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> &mut Self {
+        self.lambda_functions.insert(name.into(), LambdaFunction { signature, implementation: Box::new(implementation) });
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    pub fn get_lambda(&self, name: &str) -> Option<&LambdaFunction> {
+        self.lambda_functions.get(name)
+    }
+
+    /// The names of all registered functions, including the ones taking a lambda
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().chain(self.lambda_functions.keys()).map(String::as_str).unique()
+    }
+
+    fn unknown_function(&self, name: &str) -> EvalError {
+        let similar_ish = self.names().filter(|x| levenshtein::levenshtein(x, name) < 3).sorted().map(String::from);
+        EvalError::UnknownFunction(name.to_string(), similar_ish.collect())
+    }
+
+    /// Call the function with the given name, after checking the arguments against its signature.
+    /// Fails with an [EvalError::UnknownFunction] listing similarly named functions if no such function exists.
+    pub fn call(&self, name: &str, args: &[DynVal]) -> Result<DynVal, EvalError> {
+        let function = self.get(name).ok_or_else(|| self.unknown_function(name))?;
+        if !function.signature.accepts_arg_count(args.len()) {
+            return Err(EvalError::WrongArgCount(name.to_string()));
+        }
+        for (index, arg) in args.iter().enumerate() {
+            if let Some(arg_type) = function.signature.arg_type(index) {
+                arg_type.check(arg)?;
+            }
+        }
+        (function.implementation)(args)
+    }
+
+    /// Call the function with the given name that takes a json array and a lambda, which is applied by calling `apply`.
+    /// Fails with an [EvalError::UnexpectedLambda] if the function exists, but doesn't take a lambda.
+    pub fn call_lambda(
+        &self,
+        name: &str,
+        array: &DynVal,
+        apply: &mut dyn FnMut(DynVal) -> Result<DynVal, EvalError>,
+    ) -> Result<DynVal, EvalError> {
+        let function = match self.get_lambda(name) {
+            Some(function) => function,
+            None if self.functions.contains_key(name) => return Err(EvalError::UnexpectedLambda),
+            None => return Err(self.unknown_function(name)),
+        };
+        (function.implementation)(array.as_json_array()?, apply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimplExpr;
+
+    #[test]
+    fn test_custom_functions() {
+        let mut registry = FunctionRegistry::with_builtins();
+        registry.register("double", FunctionSignature::new(&[ValueType::Number], ValueType::Number), |args| {
+            Ok(DynVal::from(args[0].as_f64()? * 2.0))
+        });
+        registry.register_lambda(
+            "count_where",
+            LambdaSignature { body: ValueType::Bool, returns: ValueType::Int },
+            |items, apply| {
+                let mut count = 0;
+                for item in items {
+                    if apply(DynVal::from(&item))?.as_bool()? {
+                        count += 1;
+                    }
+                }
+                Ok(DynVal::from(count))
+            },
+        );
+        let eval = |text: &str| {
+            let expr: SimplExpr = crate::parser::parse_string(0, 0, text).unwrap();
+            expr.eval_with(&HashMap::new(), &registry).map(|x| x.0).map_err(|e| e.to_string())
+        };
+        assert_eq!(eval("double(2.5) + strlength('abc')"), Ok("8".to_string()));
+        assert_eq!(eval("double('a')"), Err("Failed to turn `a` into a value of type f64".to_string()));
+        assert_eq!(eval("double(1, 2)"), Err("Incorrect number of arguments given to function: double".to_string()));
+        assert_eq!(eval("count_where('[1, 5, 7]', x => x > double(1))"), Ok("2".to_string()));
+        assert!(eval("double('[1]', x => x)").unwrap_err().starts_with("Lambdas can only be passed"));
+        assert!(matches!(
+            registry.call_lambda("mpa", &DynVal::from("[]"), &mut Ok),
+            Err(EvalError::UnknownFunction(name, similar)) if name == "mpa" && similar.contains(&"map".to_string())
+        ));
+        assert!(matches!(
+            registry.call("doubel", &[]),
+            Err(EvalError::UnknownFunction(name, similar)) if name == "doubel" && similar == vec!["double".to_string()]
+        ));
+    }
+
+    #[test]
+    fn test_signature_arg_count() {
+        use ValueType::*;
+        let signature = FunctionSignature::new(&[String, Int, String], String).optional(1);
+        assert!(!signature.accepts_arg_count(1));
+        assert!(signature.accepts_arg_count(2));
+        assert!(signature.accepts_arg_count(3));
+        assert!(!signature.accepts_arg_count(4));
+        let signature = FunctionSignature::new(&[Number], Number).variadic();
+        assert!(!signature.accepts_arg_count(0));
+        assert!(signature.accepts_arg_count(5));
+        assert_eq!(signature.arg_type(4), Some(Number));
+    }
+}
@@ -7,10 +7,15 @@
 #![feature(never_type)]
 
 pub mod ast;
+pub mod compiled;
 pub mod dynval;
 pub mod error;
 pub mod eval;
+pub mod format_spec;
+pub mod functions;
+pub mod optimize;
 pub mod parser;
+pub mod type_check;
 
 pub use ast::SimplExpr;
 
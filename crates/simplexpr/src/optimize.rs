@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use eww_shared_util::{Span, Spanned};
+
+use crate::{
+    ast::{BinOp, SimplExpr},
+    eval::EvalError,
+    functions::FunctionRegistry,
+};
+
+impl SimplExpr {
+    /// Replace all sub-expressions that don't reference any variables with their value, using the builtin functions.
+    /// See [SimplExpr::fold_constants_with].
+    pub fn fold_constants(self) -> Result<Self, EvalError> {
+        self.fold_constants_with(FunctionRegistry::builtins())
+    }
+
+    /// Replace all sub-expressions that don't reference any variables with their value.
+    ///
+    /// Conditionals whose condition is constant are replaced by the branch that is taken,
+    /// and `?:` and `??` are simplified if their left side is constant, without folding the branches that are never used.
+    /// Fails if any of the constant sub-expressions that are always evaluated fails to evaluate, as the expression would then
+    /// fail whenever it is used. Sub-expressions that are only evaluated conditionally, like the branches of a conditional
+    /// whose condition is not constant, or the body of a lambda, are left unfolded if they fail instead.
+    pub fn fold_constants_with(self, functions: &FunctionRegistry) -> Result<Self, EvalError> {
+        use SimplExpr::*;
+        let fold = |x: SimplExpr| x.fold_constants_with(functions);
+        let fold_box = |x: Box<SimplExpr>| Ok::<_, EvalError>(Box::new(fold(*x)?));
+        let fold_conditional = |x: Box<SimplExpr>| Box::new(x.clone().fold_constants_with(functions).unwrap_or(*x));
+        let folded = match self {
+            IfElse(span, cond, yes, no) => match fold(*cond)? {
+                Literal(cond) | Value(cond) => {
+                    let branch = if cond.as_bool().map_err(|e| EvalError::from(e).at(span))? { yes } else { no };
+                    return fold(*branch);
+                }
+                cond => IfElse(span, Box::new(cond), fold_conditional(yes), fold_conditional(no)),
+            },
+            BinOp(span, a, self::BinOp::Elvis, b) => match fold(*a)? {
+                Literal(a) | Value(a) => return if a.0.is_empty() { fold(*b) } else { Ok(Value(a.at(span))) },
+                a => BinOp(span, Box::new(a), self::BinOp::Elvis, fold_conditional(b)),
+            },
+            BinOp(span, a, self::BinOp::NullCoalesce, b) => {
+                let a = coalesced_access(*a);
+                if a.var_refs().is_empty() {
+                    let a_span = a.span();
+                    return match a.eval_coalesced_no_vars(functions).map_err(|err| spanned(err, a_span))? {
+                        Some(a) => Ok(Value(a.at(span))),
+                        None => fold(*b),
+                    };
+                }
+                BinOp(span, Box::new(fold(a)?), self::BinOp::NullCoalesce, fold_conditional(b))
+            }
+            BinOp(span, a, op, b) => BinOp(span, fold_box(a)?, op, fold_box(b)?),
+            UnaryOp(span, op, a) => UnaryOp(span, op, fold_box(a)?),
+            Concat(span, elems) => Concat(span, elems.into_iter().map(fold).collect::<Result<_, _>>()?),
+            JsonAccess(span, a, b) => JsonAccess(span, fold_box(a)?, fold_box(b)?),
+            JsonSafeAccess(span, a, b) => JsonSafeAccess(span, fold_box(a)?, fold_box(b)?),
+            FunctionCall(span, name, args) => FunctionCall(span, name, args.into_iter().map(fold).collect::<Result<_, _>>()?),
+            // a lambda can't be evaluated on its own, so only its body is simplified
+            Lambda(span, param, body) => return Ok(Lambda(span, param, fold_conditional(body))),
+            Let(span, name, value, body) => Let(span, name, fold_box(value)?, fold_box(body)?),
+            Formatted(span, value, spec) => Formatted(span, fold_box(value)?, spec),
+            JsonArray(span, values) => JsonArray(span, values.into_iter().map(fold).collect::<Result<_, _>>()?),
+            JsonObject(span, entries) => {
+                JsonObject(span, entries.into_iter().map(|(k, v)| Ok((fold(k)?, fold(v)?))).collect::<Result<_, EvalError>>()?)
+            }
+            x @ (Literal(_) | Value(_) | VarRef(..)) => return Ok(x),
+        };
+
+        if folded.var_refs().is_empty() {
+            let span = folded.span();
+            let value = folded.eval_with(&HashMap::new(), functions).map_err(|err| spanned(err, span))?;
+            // the results of string interpolations stay strings when used in a json literal
+            Ok(if folded.is_string() { Literal(value.at(span)) } else { Value(value.at(span)) })
+        } else {
+            Ok(folded)
+        }
+    }
+}
+
+/// Give an error the span of the expression that failed to evaluate, unless it already has a more specific one.
+fn spanned(err: EvalError, span: Span) -> EvalError {
+    match err {
+        err @ EvalError::Spanned(..) => err,
+        err => err.at(span),
+    }
+}
+
+/// On the left side of a `??`, failing to index into a value counts as the value being absent,
+/// so `.` and `[]` accesses along it are turned into `?.`, which results in `null` instead of failing.
+/// Both sides of a `??` on the left side are treated the same way, as it is absent if both of them are.
+fn coalesced_access(expr: SimplExpr) -> SimplExpr {
+    match expr {
+        SimplExpr::JsonAccess(span, a, b) | SimplExpr::JsonSafeAccess(span, a, b) => {
+            SimplExpr::JsonSafeAccess(span, Box::new(coalesced_access(*a)), b)
+        }
+        SimplExpr::BinOp(span, a, BinOp::NullCoalesce, b) => {
+            SimplExpr::BinOp(span, Box::new(coalesced_access(*a)), BinOp::NullCoalesce, Box::new(coalesced_access(*b)))
+        }
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn fold(text: &str) -> Result<String, String> {
+        let expr = crate::parser::parse_string(0, 0, text).map_err(|e| e.to_string())?;
+        expr.fold_constants().map(|x| x.to_string()).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        assert_eq!(fold("1 + 2 * 3"), Ok(r#""7""#.to_string()));
+        assert_eq!(fold("a + 2 * 3"), Ok(r#"(a + "6")"#.to_string()));
+        assert_eq!(fold("\"${round(2.345, 1)} ${a}\""), Ok(r#""2.3 ${a}""#.to_string()));
+        assert_eq!(fold("true ? a : b"), Ok("a".to_string()));
+        assert_eq!(fold("1 > 2 ? a : b + 'x'"), Ok(r#"(b + "x")"#.to_string()));
+        assert_eq!(fold("'' ?: a"), Ok("a".to_string()));
+        assert_eq!(fold("'x' ?? a"), Ok(r#""x""#.to_string()));
+        assert_eq!(fold("'x'.a.b ?? a"), Ok("a".to_string()));
+        assert_eq!(fold("'null' ?? a"), Ok(r#""null""#.to_string()));
+        assert_eq!(fold("'x'.a ?? '{}'.b ?? a"), Ok("a".to_string()));
+        assert_eq!(fold("map(a, x => x * (2 + 2))"), Ok(r#"map(a, x => (x * "4"))"#.to_string()));
+        assert_eq!(fold("let x = 2 in x * 3"), Ok(r#""6""#.to_string()));
+        assert_eq!(fold("let x = a in x * (1 + 1)"), Ok(r#"(let x = a in (x * "2"))"#.to_string()));
+        assert_eq!(fold("upper('abc') + a.b[1 + 1]"), Ok(r#"("ABC" + a["b"]["2"])"#.to_string()));
+        // branches that are never taken are not evaluated
+        assert_eq!(fold("false ? 1 / 'x' : 2"), Ok(r#""2""#.to_string()));
+        // branches that may be taken are left unfolded if they fail, as they might never be evaluated
+        assert_eq!(fold("a ? 1 / 'x' : 2 + 2"), Ok(r#"(a ? ("1" / "x") : "4")"#.to_string()));
+        assert_eq!(fold("a ?? 1 / 'x'"), Ok(r#"(a ?? ("1" / "x"))"#.to_string()));
+        assert_eq!(fold("map(a, x => x + 1 / 'x')"), Ok(r#"map(a, x => (x + ("1" / "x")))"#.to_string()));
+        assert_eq!(fold("(a ? 1 : 2) + 1 / 'x'"), Err("Failed to turn `x` into a value of type f64".to_string()));
+        assert_eq!(fold("a + round(1)"), Err("Incorrect number of arguments given to function: round".to_string()));
+    }
+
+    #[test]
+    fn test_fold_json_literal() {
+        use crate::dynval::DynVal;
+        use eww_shared_util::VarName;
+        let expr = crate::parser::parse_string(0, 0, r#"[a, 'null', 1 + 1, "${1 + 1}", {"b": '1.50'}]"#).unwrap();
+        let variables = std::iter::once((VarName::from("a"), DynVal::from("1"))).collect();
+        let folded = expr.clone().fold_constants().unwrap();
+        assert_eq!(folded.eval(&variables).unwrap().0, r#"[1,"null",2,"2",{"b":"1.50"}]"#);
+        assert_eq!(folded.eval(&variables).unwrap().0, expr.eval(&variables).unwrap().0);
+    }
+
+    #[test]
+    fn test_fold_error_span() {
+        use eww_shared_util::{Span, Spanned};
+        let expr = crate::parser::parse_string(0, 0, "a + \"${'x':bytes}\"").unwrap();
+        assert_eq!(expr.fold_constants().unwrap_err().span(), Span(7, 16, 0));
+    }
+}
@@ -1,6 +1,6 @@
-use eww_shared_util::Span;
+use eww_shared_util::{Span, Spanned};
 
-use crate::{dynval::DynVal, SimplExpr};
+use crate::{dynval::DynVal, format_spec::FormatSpec, SimplExpr};
 
 use super::lexer::{LexicalError, Sp, StrLitSegment, Token};
 
@@ -19,27 +19,36 @@ pub fn parse_stringlit(
         let (lo, seg, hi) = segs.remove(0);
         let span = Span(lo, hi, file_id);
         match seg {
-            StrLitSegment::Literal(lit) => Ok(SimplExpr::Literal(DynVal(lit, span))),
+            StrLitSegment::Literal(lit) => Ok(SimplExpr::Literal(DynVal::from_string(lit).at(span))),
             StrLitSegment::Interp(toks) => {
                 let token_stream = toks.into_iter().map(|x| Ok(x));
                 parser.parse(file_id, token_stream)
             }
+            StrLitSegment::FormatSpec(_) => Err(lalrpop_util::ParseError::User { error: LexicalError::InvalidToken(span) }),
         }
     } else {
-        let elems = segs
-            .into_iter()
-            .filter_map(|(lo, segment, hi)| {
-                let span = Span(lo, hi, file_id);
-                match segment {
-                    StrLitSegment::Literal(lit) if lit.is_empty() => None,
-                    StrLitSegment::Literal(lit) => Some(Ok(SimplExpr::Literal(DynVal(lit, span)))),
-                    StrLitSegment::Interp(toks) => {
-                        let token_stream = toks.into_iter().map(|x| Ok(x));
-                        Some(parser.parse(file_id, token_stream))
+        let mut elems = Vec::new();
+        for (lo, segment, hi) in segs {
+            let span = Span(lo, hi, file_id);
+            match segment {
+                StrLitSegment::Literal(lit) if lit.is_empty() => {}
+                StrLitSegment::Literal(lit) => elems.push(SimplExpr::Literal(DynVal::from_string(lit).at(span))),
+                StrLitSegment::Interp(toks) => {
+                    let token_stream = toks.into_iter().map(|x| Ok(x));
+                    elems.push(parser.parse(file_id, token_stream)?);
+                }
+                // a format spec always directly follows the interpolation it belongs to
+                StrLitSegment::FormatSpec(spec) => {
+                    let spec = spec
+                        .parse::<FormatSpec>()
+                        .map_err(|_| lalrpop_util::ParseError::User { error: LexicalError::UnknownFormatSpec(span, spec) })?;
+                    if let Some(value) = elems.pop() {
+                        let span = value.span().to(span);
+                        elems.push(SimplExpr::Formatted(span, b(value), spec));
                     }
                 }
-            })
-            .collect::<Result<Vec<SimplExpr>, _>>()?;
+            }
+        }
         Ok(SimplExpr::Concat(span, elems))
     }
 }
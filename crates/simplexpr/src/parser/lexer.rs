@@ -10,6 +10,8 @@ pub type Sp<T> = (usize, T, usize);
 pub enum StrLitSegment {
     Literal(String),
     Interp(Vec<Sp<Token>>),
+    /// The format spec of the preceding interpolation, as in `${value:spec}`
+    FormatSpec(String),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, strum::Display, strum::EnumString)]
@@ -18,15 +20,23 @@ pub enum Token {
     Minus,
     Times,
     Div,
+    IntDiv,
     Mod,
+    Pow,
     Equals,
     NotEquals,
     And,
     Or,
     GT,
     LT,
+    GE,
+    LE,
     Elvis,
+    NullCoalesce,
+    SafeAccess,
     RegexMatch,
+    Arrow,
+    Assign,
 
     Not,
 
@@ -42,6 +52,8 @@ pub enum Token {
     Dot,
     True,
     False,
+    Let,
+    In,
 
     Ident(String),
     NumLit(String),
@@ -73,17 +85,25 @@ pub static STR_INTERPOLATION_END: &str = "}";
 regex_rules! {
     escape(r"+")     => |_| Token::Plus,
     escape(r"-")     => |_| Token::Minus,
+    escape(r"**")    => |_| Token::Pow,
     escape(r"*")     => |_| Token::Times,
+    escape(r"//")    => |_| Token::IntDiv,
     escape(r"/")     => |_| Token::Div,
     escape(r"%")     => |_| Token::Mod,
     escape(r"==")    => |_| Token::Equals,
     escape(r"!=")    => |_| Token::NotEquals,
     escape(r"&&")    => |_| Token::And,
     escape(r"||")    => |_| Token::Or,
+    escape(r">=")    => |_| Token::GE,
+    escape(r"<=")    => |_| Token::LE,
     escape(r">")     => |_| Token::GT,
     escape(r"<")     => |_| Token::LT,
     escape(r"?:")    => |_| Token::Elvis,
+    escape(r"??")    => |_| Token::NullCoalesce,
+    escape(r"?.")    => |_| Token::SafeAccess,
     escape(r"=~")    => |_| Token::RegexMatch,
+    escape(r"=>")    => |_| Token::Arrow,
+    escape(r"=")     => |_| Token::Assign,
 
     escape(r"!" )    => |_| Token::Not,
 
@@ -99,6 +119,8 @@ regex_rules! {
     escape(r".")     => |_| Token::Dot,
     escape(r"true")  => |_| Token::True,
     escape(r"false") => |_| Token::False,
+    r"let\b"        => |_| Token::Let,
+    r"in\b"         => |_| Token::In,
 
     r"[ \n\n\f]+" => |_| Token::Skip,
     r";.*"=> |_| Token::Comment,
@@ -152,7 +174,11 @@ impl<'s> Lexer<'s> {
                     Some(x) => x,
                     None => {
                         self.failed = true;
-                        return Some(Err(LexicalError(Span(self.pos + self.offset, self.pos + self.offset, self.file_id))));
+                        return Some(Err(LexicalError::InvalidToken(Span(
+                            self.pos + self.offset,
+                            self.pos + self.offset,
+                            self.file_id,
+                        ))));
                     }
                 };
 
@@ -226,6 +252,7 @@ impl<'s> Lexer<'s> {
             } else {
                 let segment_start = self.pos;
                 let mut toks = Vec::new();
+                let mut format_spec = None;
                 let mut curly_nesting = 0;
 
                 'inner: while let Some(tok) = self.next_token() {
@@ -245,18 +272,48 @@ impl<'s> Lexer<'s> {
 
                     if curly_nesting < 0 {
                         break 'inner;
+                    } else if tok.1 == Token::Colon && is_format_spec_colon(&toks) {
+                        // the format spec is not made up of tokens, so it is read as raw text instead
+                        let spec_start = self.pos;
+                        self.advance_until_one_of(&["}"])?;
+                        let spec = self.source[spec_start..self.pos - 1].to_string();
+                        format_spec = Some((tok.0, (spec_start + self.offset, spec, self.pos + self.offset - 1)));
+                        break 'inner;
                     } else {
                         toks.push(tok);
                     }
                 }
 
-                elements.push((segment_start + self.offset, StrLitSegment::Interp(toks), self.pos + self.offset - 1));
+                match format_spec {
+                    Some((interp_end, (lo, spec, hi))) => {
+                        elements.push((segment_start + self.offset, StrLitSegment::Interp(toks), interp_end));
+                        elements.push((lo, StrLitSegment::FormatSpec(spec), hi));
+                    }
+                    None => elements.push((segment_start + self.offset, StrLitSegment::Interp(toks), self.pos + self.offset - 1)),
+                }
                 in_string_lit = true;
             }
         }
     }
 }
 
+/// Check if a colon following the given tokens of an interpolation starts a format spec, as in `${value:spec}`,
+/// rather than belonging to a ternary or being nested within brackets.
+fn is_format_spec_colon(toks: &[Sp<Token>]) -> bool {
+    let mut nesting = 0;
+    let mut open_ternaries = 0;
+    for (_, tok, _) in toks {
+        match tok {
+            Token::LPren | Token::LBrack | Token::LCurl => nesting += 1,
+            Token::RPren | Token::RBrack | Token::RCurl => nesting -= 1,
+            Token::Question if nesting == 0 => open_ternaries += 1,
+            Token::Colon if nesting == 0 => open_ternaries -= 1,
+            _ => {}
+        }
+    }
+    nesting == 0 && open_ternaries == 0
+}
+
 impl<'s> Iterator for Lexer<'s> {
     type Item = Result<Sp<Token>, LexicalError>;
 
@@ -265,18 +322,28 @@ impl<'s> Iterator for Lexer<'s> {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub struct LexicalError(pub Span);
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum LexicalError {
+    InvalidToken(Span),
+    /// The format spec of a string interpolation, as in `${value:spec}`, could not be parsed
+    UnknownFormatSpec(Span, String),
+}
 
 impl Spanned for LexicalError {
     fn span(&self) -> Span {
-        self.0
+        match self {
+            LexicalError::InvalidToken(span) => *span,
+            LexicalError::UnknownFormatSpec(span, _) => *span,
+        }
     }
 }
 
 impl std::fmt::Display for LexicalError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Lexical error at {}", self.0)
+        match self {
+            LexicalError::InvalidToken(span) => write!(f, "Lexical error at {}", span),
+            LexicalError::UnknownFormatSpec(_, spec) => write!(f, "{}", crate::eval::EvalError::UnknownFormatSpec(spec.clone())),
+        }
     }
 }
 
@@ -311,5 +378,6 @@ mod test {
         weird_nesting => v!(r#"
             "${ {"hi": "ho"}.hi }".hi
         "#),
+        format_spec           => v!(r#" "${x:>5} ${ a ? b : c } ${ {"a": 1}.a :.2}" "#),
     }
 }
@@ -44,6 +44,24 @@ mod tests {
             "foo.bar[2 + 2] * asdf[foo.bar]",
             r#"[1, 2, 3 + 4, "bla", [blub, blo]]"#,
             r#"{ "key": "value", 5: 1+2, true: false }"#,
+            "2 ** 3 ** 2",
+            "! a ** b",
+            "7 // 2 * 3 ** 2 + 1",
+            "1 < 2 == 3 > 2",
+            "a >= b && c <= d",
+            "-a + -2 * b",
+            "- 2 ** 2",
+            "a - -b",
+            "!a <= -b",
+            "map(foo, x => x.bar * 2)",
+            "filter(a, x => any(x, y => y > x))",
+            "a?.b?.c ?? d",
+            "a?.[0]?.b.c ?? 1 + 2",
+            "let x = a.b in x + 1",
+            "let x = 1 in let y = 2 in x > y ? x : y",
+            "letter + index",
+            r#""${cpu:>5}% ${ a ? b : c } ${ {"a": 1}.a :.2}""#,
+            r#""${cpu:>5x}""#,
         );
     }
 }
@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+
+use eww_shared_util::{Span, Spanned, VarName};
+
+use crate::{
+    ast::{BinOp, SimplExpr, UnaryOp},
+    dynval::DynVal,
+    format_spec::FormatKind,
+    functions::{FunctionRegistry, ValueType},
+};
+
+/// A place where a value is used as a type it most likely can't be converted to, found by [SimplExpr::check_type].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Expected a value of type `{expected}`, but got a value of type `{found}`")]
+pub struct TypeMismatch {
+    pub span: Span,
+    pub expected: ValueType,
+    pub found: ValueType,
+}
+
+impl Spanned for TypeMismatch {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl SimplExpr {
+    /// Statically check that this expression results in a value of the expected type,
+    /// given the types of the variables it references, and that all of its sub-expressions are used as the types they need to be.
+    ///
+    /// Variables of unknown type, as well as text produced by string operations, are assumed to possibly be of any type,
+    /// so only mismatches that are very likely to fail at runtime are reported.
+    pub fn check_type(
+        &self,
+        expected: ValueType,
+        vars: &HashMap<VarName, ValueType>,
+        functions: &FunctionRegistry,
+    ) -> Vec<TypeMismatch> {
+        let mut checker = TypeChecker { vars, locals: Vec::new(), functions, mismatches: Vec::new() };
+        checker.expect(self, expected);
+        checker.mismatches
+    }
+}
+
+struct TypeChecker<'a> {
+    vars: &'a HashMap<VarName, ValueType>,
+    /// Variables bound within the expression by `let` or lambdas, with the innermost binding last
+    locals: Vec<(VarName, ValueType)>,
+    functions: &'a FunctionRegistry,
+    mismatches: Vec<TypeMismatch>,
+}
+
+impl TypeChecker<'_> {
+    /// Record a mismatch if the given expression can't result in a value of the expected type, returning the inferred type.
+    fn expect(&mut self, expr: &SimplExpr, expected: ValueType) -> ValueType {
+        match expr {
+            // literals are known exactly, so they are checked by value rather than by type
+            SimplExpr::Literal(value) | SimplExpr::Value(value) => {
+                let found = literal_type(value);
+                if expected.check(value).is_err() {
+                    self.mismatch(expr.span(), expected, found);
+                }
+                found
+            }
+            SimplExpr::IfElse(_, cond, yes, no) => {
+                self.expect(cond, ValueType::Bool);
+                let yes = self.expect(yes, expected);
+                let no = self.expect(no, expected);
+                join(yes, no)
+            }
+            _ => {
+                let found = self.infer(expr);
+                if !is_compatible(found, expected) {
+                    self.mismatch(expr.span(), expected, found);
+                }
+                found
+            }
+        }
+    }
+
+    fn mismatch(&mut self, span: Span, expected: ValueType, found: ValueType) {
+        self.mismatches.push(TypeMismatch { span, expected, found });
+    }
+
+    fn var_type(&self, name: &VarName) -> ValueType {
+        match self.locals.iter().rev().find(|(local, _)| local == name) {
+            Some((_, value_type)) => *value_type,
+            None => self.vars.get(name).copied().unwrap_or(ValueType::Any),
+        }
+    }
+
+    /// Infer the type of the value the given expression results in, checking its sub-expressions along the way.
+    fn infer(&mut self, expr: &SimplExpr) -> ValueType {
+        use ValueType::*;
+        match expr {
+            SimplExpr::Literal(value) | SimplExpr::Value(value) => literal_type(value),
+            SimplExpr::VarRef(_, name) => self.var_type(name),
+            SimplExpr::Concat(_, elems) => {
+                for elem in elems {
+                    self.infer(elem);
+                }
+                String
+            }
+            SimplExpr::BinOp(_, a, op, b) => self.infer_binop(a, op, b),
+            SimplExpr::UnaryOp(_, UnaryOp::Not, a) => {
+                self.expect(a, Bool);
+                Bool
+            }
+            SimplExpr::UnaryOp(_, UnaryOp::Negative, a) => numeric_result(self.expect(a, Number), Int),
+            SimplExpr::IfElse(_, cond, yes, no) => {
+                self.expect(cond, Bool);
+                let yes = self.infer(yes);
+                let no = self.infer(no);
+                join(yes, no)
+            }
+            SimplExpr::JsonAccess(_, value, index) | SimplExpr::JsonSafeAccess(_, value, index) => {
+                self.expect(value, Json);
+                self.infer(index);
+                Any
+            }
+            SimplExpr::FunctionCall(_, name, args) => match args.as_slice() {
+                [collection, SimplExpr::Lambda(_, param, body)] => {
+                    // unknown functions are reported when evaluating
+                    let signature = self.functions.get_lambda(name).map(|function| function.signature);
+                    self.expect(collection, Json);
+                    self.locals.push((param.clone(), Any));
+                    match signature {
+                        Some(signature) => self.expect(body, signature.body),
+                        None => self.infer(body),
+                    };
+                    self.locals.pop();
+                    signature.map(|signature| signature.returns).unwrap_or(Any)
+                }
+                _ => self.infer_function_call(name, args),
+            },
+            SimplExpr::Lambda(_, param, body) => {
+                self.locals.push((param.clone(), Any));
+                self.infer(body);
+                self.locals.pop();
+                Any
+            }
+            SimplExpr::Let(_, name, value, body) => {
+                let value_type = self.infer(value);
+                self.locals.push((name.clone(), value_type));
+                let body_type = self.infer(body);
+                self.locals.pop();
+                body_type
+            }
+            SimplExpr::Formatted(_, value, spec) => {
+                match spec.kind {
+                    FormatKind::Bytes | FormatKind::Percent => self.expect(value, Number),
+                    _ => self.infer(value),
+                };
+                String
+            }
+            SimplExpr::JsonArray(_, values) => {
+                for value in values {
+                    self.infer(value);
+                }
+                Json
+            }
+            SimplExpr::JsonObject(_, entries) => {
+                for (key, value) in entries {
+                    self.infer(key);
+                    self.infer(value);
+                }
+                Json
+            }
+        }
+    }
+
+    fn infer_binop(&mut self, a: &SimplExpr, op: &BinOp, b: &SimplExpr) -> ValueType {
+        use ValueType::*;
+        match op {
+            BinOp::Equals | BinOp::NotEquals | BinOp::GT | BinOp::LT | BinOp::GE | BinOp::LE => {
+                self.infer(a);
+                self.infer(b);
+                Bool
+            }
+            BinOp::And | BinOp::Or => {
+                self.expect(a, Bool);
+                self.expect(b, Bool);
+                Bool
+            }
+            // adding anything that isn't a number concatenates the values as strings
+            BinOp::Plus => match (self.infer(a), self.infer(b)) {
+                (a @ (Int | Number), b @ (Int | Number)) => numeric_result(a, b),
+                (Bool | Json | Duration, _) | (_, Bool | Json | Duration) => String,
+                _ => Any,
+            },
+            BinOp::Div => {
+                self.expect(a, Number);
+                self.expect(b, Number);
+                Number
+            }
+            BinOp::Minus | BinOp::Times | BinOp::IntDiv | BinOp::Mod | BinOp::Pow => {
+                let (a, b) = (self.expect(a, Number), self.expect(b, Number));
+                numeric_result(a, b)
+            }
+            BinOp::Elvis | BinOp::NullCoalesce => {
+                let (a, b) = (self.infer(a), self.infer(b));
+                join(a, b)
+            }
+            BinOp::RegexMatch => {
+                self.infer(a);
+                self.infer(b);
+                Bool
+            }
+        }
+    }
+
+    fn infer_function_call(&mut self, name: &str, args: &[SimplExpr]) -> ValueType {
+        // unknown functions and wrong argument counts are reported when evaluating
+        let signature = match self.functions.get(name) {
+            Some(function) if function.signature.accepts_arg_count(args.len()) => function.signature.clone(),
+            _ => {
+                for arg in args {
+                    self.infer(arg);
+                }
+                return ValueType::Any;
+            }
+        };
+        for (index, arg) in args.iter().enumerate() {
+            match signature.arg_type(index) {
+                Some(arg_type) => self.expect(arg, arg_type),
+                None => self.infer(arg),
+            };
+        }
+        signature.returns
+    }
+}
+
+/// The most specific type the given value can be read as
+fn literal_type(value: &DynVal) -> ValueType {
+    if value.as_i64().is_ok() {
+        ValueType::Int
+    } else if value.as_f64().is_ok() {
+        ValueType::Number
+    } else if value.as_bool().is_ok() {
+        ValueType::Bool
+    } else if value.as_duration().is_ok() {
+        ValueType::Duration
+    } else if value.0.starts_with(&['[', '{'][..]) && value.as_json_value().is_ok() {
+        ValueType::Json
+    } else {
+        ValueType::String
+    }
+}
+
+/// The type of the result of an arithmetic operation on values of the given types.
+/// Operations on integers stay integers, as long as they don't overflow.
+fn numeric_result(a: ValueType, b: ValueType) -> ValueType {
+    match (a, b) {
+        (ValueType::Int, ValueType::Int) => ValueType::Int,
+        _ => ValueType::Number,
+    }
+}
+
+/// The type of a value that may be of either of the given types
+fn join(a: ValueType, b: ValueType) -> ValueType {
+    match (a, b) {
+        (a, b) if a == b => a,
+        (ValueType::Int | ValueType::Number, ValueType::Int | ValueType::Number) => ValueType::Number,
+        _ => ValueType::Any,
+    }
+}
+
+/// Check if a value of the found type can possibly be used as the expected type.
+/// Strings may contain anything, so they are assumed to be convertible to any type.
+fn is_compatible(found: ValueType, expected: ValueType) -> bool {
+    use ValueType::*;
+    match (found, expected) {
+        (Any | String, _) | (_, Any | String) => true,
+        (found, expected) if found == expected => true,
+        (Int, Number) | (Number, Int) => true,
+        // numbers and booleans are valid json as well
+        (Int | Number | Bool, Json) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(text: &str, expected: ValueType) -> Vec<(ValueType, ValueType)> {
+        let vars = vars();
+        let expr = crate::parser::parse_string(0, 0, text).unwrap();
+        expr.check_type(expected, &vars, FunctionRegistry::builtins()).into_iter().map(|x| (x.expected, x.found)).collect()
+    }
+
+    fn vars() -> HashMap<VarName, ValueType> {
+        vec![("count", ValueType::Int), ("flag", ValueType::Bool), ("obj", ValueType::Json), ("text", ValueType::String)]
+            .into_iter()
+            .map(|(name, value_type)| (VarName::from(name), value_type))
+            .collect()
+    }
+
+    #[test]
+    fn test_check_type() {
+        use ValueType::*;
+        assert_eq!(check("count * 2 + 1", Number), vec![]);
+        assert_eq!(check("flag && count > 2", Bool), vec![]);
+        assert_eq!(check("'yes'", Bool), vec![(Bool, String)]);
+        assert_eq!(check("flag ? 'yes' : true", Bool), vec![(Bool, String)]);
+        assert_eq!(check("obj * 2", Number), vec![(Number, Json)]);
+        assert_eq!(check("-flag", Any), vec![(Number, Bool)]);
+        assert_eq!(check("count ? 1 : 2", Any), vec![(Bool, Int)]);
+        assert_eq!(check("strlength(text)", Bool), vec![(Bool, Int)]);
+        assert_eq!(check("round(flag, 2)", Any), vec![(Number, Bool)]);
+        assert_eq!(check("'${obj:.1bytes}'", Any), vec![(Number, Json)]);
+        assert_eq!(check("let x = obj in x - 1", Any), vec![(Number, Json)]);
+        assert_eq!(check("filter(obj, x => x.count)", Json), vec![]);
+        assert_eq!(check("map(obj, x => x * 2)", Number), vec![(Number, Json)]);
+        assert_eq!(check("sum(obj, x => flag)", Any), vec![(Number, Bool)]);
+        // text and values of unknown type could be anything
+        assert_eq!(check("text * unknown", Bool), vec![(Bool, Number)]);
+        assert_eq!(check("upper(text) + obj.x", Bool), vec![]);
+        assert_eq!(check("'1' + 2", Number), vec![]);
+    }
+}
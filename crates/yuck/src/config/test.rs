@@ -1,9 +1,31 @@
 use crate::{
-    config::config::Config,
+    config::{config::Config, widget_definition::ArgType},
     parser::{self, ast::Ast, from_ast::FromAst, lexer::Lexer},
 };
 
-use super::file_provider::YuckFiles;
+use super::{
+    file_provider::YuckFiles,
+    validate::{KnownTypes, ValidationError},
+};
+use simplexpr::type_check::TypeMismatch;
+
+fn load_config(input: &str) -> Config {
+    let mut files = YuckFiles::new();
+    let (_, asts) = files.load_str("config.yuck".to_string(), input.to_string()).unwrap();
+    Config::generate(&mut files, asts).unwrap()
+}
+
+fn validate_str(input: &str) -> Result<Vec<TypeMismatch>, ValidationError> {
+    super::validate::validate(&load_config(input), &KnownTypes::default())
+}
+
+fn validation_error(input: &str) -> ValidationError {
+    validate_str(input).expect_err("expected the config to fail validation")
+}
+
+fn is_unknown_variable(error: &ValidationError, expected: &str) -> bool {
+    matches!(error, ValidationError::UnknownVariable { name, .. } if name.0 == expected)
+}
 
 #[test]
 fn test_config() {
@@ -28,3 +50,173 @@ fn test_config() {
         insta::assert_ron_snapshot!(config.unwrap());
     });
 }
+
+#[test]
+fn test_loop_validation() {
+    assert!(validate_str(
+        r#"
+        (defvar items "[1, 2]")
+        (defwidget foo [] (box (for item in items (label :text item))))
+    "#
+    )
+    .is_ok());
+
+    let error = validation_error(
+        r#"
+        (defvar items "[1, 2]")
+        (defwidget foo [] (box (for item in items (label :text other))))
+    "#,
+    );
+    assert!(is_unknown_variable(&error, "other"), "{:?}", error);
+
+    let error = validation_error(
+        r#"
+        (defwidget foo [] (box (for item in items (label :text item))))
+    "#,
+    );
+    assert!(is_unknown_variable(&error, "items"), "{:?}", error);
+}
+
+#[test]
+fn test_let_validation() {
+    assert!(validate_str(r#"(defvar bat "{}") (defwidget foo [] (label :text {let cap = bat.capacity in "${cap}%"}))"#).is_ok());
+
+    let error = validation_error(r#"(defwidget foo [] (label :text {let cap = bat.capacity in "${cap}%"}))"#);
+    assert!(is_unknown_variable(&error, "bat"), "{:?}", error);
+
+    let error = validation_error(r#"(defvar bat "{}") (defwidget foo [] (label :text {(let cap = bat in cap) + cap}))"#);
+    assert!(is_unknown_variable(&error, "cap"), "{:?}", error);
+}
+
+#[test]
+fn test_children_validation() {
+    assert!(validate_str(
+        r#"
+        (defwidget card [] (box :class "card" (children)))
+        (defwidget foo [] (card (label :text "hi")))
+    "#
+    )
+    .is_ok());
+
+    assert!(validate_str(
+        r#"
+        (defwidget card [] (box :class "card" (children :nth 0)))
+        (defwidget foo [] (card "hi"))
+    "#
+    )
+    .is_ok());
+
+    let error = validation_error(
+        r#"
+        (defwidget card [] (box :class "card"))
+        (defwidget foo [] (card (label :text "hi")))
+    "#,
+    );
+    assert!(matches!(&error, ValidationError::UnexpectedChildren { widget_name, .. } if widget_name == "card"), "{:?}", error);
+
+    let error = validation_error(
+        r#"
+        (defwindow bar :geometry (geometry :width "12%" :height "20px") (children))
+    "#,
+    );
+    assert!(matches!(error, ValidationError::ChildrenOutsideDefinition(_)), "{:?}", error);
+}
+
+#[test]
+fn test_typed_args() {
+    let config = load_config(r#"(defwidget foo [name ?(size "12") count:number ?flag:bool] (label :text name))"#);
+    let args = &config.widget_definitions["foo"].expected_args;
+    let summary = args
+        .iter()
+        .map(|arg| (arg.name.to_string(), arg.optional, arg.default.as_ref().map(|x| x.to_string()), arg.arg_type))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            ("name".to_string(), false, None, None),
+            ("size".to_string(), true, Some("\"12\"".to_string()), None),
+            ("count".to_string(), false, None, Some(ArgType::Number)),
+            ("flag".to_string(), true, None, Some(ArgType::Bool)),
+        ]
+    );
+
+    assert!(validate_str(r#"(defwidget foo [count:number] "${count}") (defwidget bar [] (foo :count 12))"#).is_ok());
+
+    let is_count_mismatch = |error: &ValidationError, widget: &str| {
+        matches!(error, ValidationError::ArgTypeMismatch { widget_name, arg_name, expected: ArgType::Number, .. }
+            if widget_name == widget && arg_name.0 == "count")
+    };
+    let error = validation_error(r#"(defwidget foo [count:number] "${count}") (defwidget bar [] (foo :count "abc"))"#);
+    assert!(is_count_mismatch(&error, "foo"), "{:?}", error);
+    let error = validation_error(r#"(defwidget foo [?(count:number "abc")] "${count}")"#);
+    assert!(is_count_mismatch(&error, "foo"), "{:?}", error);
+    let error = validation_error(r#"(defwidget foo [?(count:number unknown)] "${count}")"#);
+    assert!(is_unknown_variable(&error, "unknown"), "{:?}", error);
+}
+
+#[test]
+fn test_window_args() {
+    let config = load_config(r#"(defwindow bar [monitor ?(name "main")] :stacking "fg" (label :text "${name} on ${monitor}"))"#);
+    let args = &config.window_definitions["bar"].expected_args;
+    assert_eq!(
+        args.iter().map(|arg| (arg.name.to_string(), arg.optional)).collect::<Vec<_>>(),
+        vec![("monitor".to_string(), false), ("name".to_string(), true)]
+    );
+    assert!(super::validate::validate(&config, &KnownTypes::default()).is_ok());
+
+    let error = validation_error(r#"(defwindow bar [monitor] (label :text name))"#);
+    assert!(is_unknown_variable(&error, "name"), "{:?}", error);
+
+    // values passed via `--arg` aren't part of the config, so the error points at the declaration of the argument
+    use crate::format_diagnostic::ToDiagnostic;
+    use eww_shared_util::Spanned;
+    let config = load_config(r#"(defwindow bar [monitor:number] (label :text monitor))"#);
+    let arg = &config.window_definitions["bar"].expected_args[0];
+    let error = super::validate::validate_arg_type("bar", arg, &"main".into(), None).unwrap_err();
+    assert_eq!(error.span(), arg.span);
+    assert_eq!(error.to_diagnostic().notes, vec!["The value was passed via `--arg monitor=main`".to_string()]);
+}
+
+#[test]
+fn test_type_check() {
+    use eww_shared_util::{AttrName, VarName};
+    use simplexpr::functions::ValueType;
+
+    let known_types = KnownTypes {
+        globals: maplit::hashmap! { VarName::from("EWW_RAM") => ValueType::Json },
+        widget_attrs: maplit::hashmap! {
+            "progress".to_string() => maplit::hashmap! { AttrName::from("value") => ValueType::Number },
+        },
+    };
+    let type_mismatches = |input: &str| {
+        super::validate::validate(&load_config(input), &known_types)
+            .map(|mismatches| mismatches.into_iter().map(|x| (x.expected, x.found)).collect::<Vec<_>>())
+    };
+
+    assert_eq!(type_mismatches(r#"(defwidget foo [] (progress :value {EWW_RAM.used_mem / 2}))"#).unwrap(), vec![]);
+    assert_eq!(
+        type_mismatches(r#"(defwidget foo [] (progress :value {EWW_RAM * 2}))"#).unwrap(),
+        vec![(ValueType::Number, ValueType::Json)]
+    );
+    assert_eq!(
+        type_mismatches(r#"(defwidget foo [] (progress :value {EWW_RAM ? 1 : 2}))"#).unwrap(),
+        vec![(ValueType::Bool, ValueType::Json)]
+    );
+    assert_eq!(
+        type_mismatches(r#"(defvar shown:bool false) (defwidget foo [] (progress :value shown))"#).unwrap(),
+        vec![(ValueType::Number, ValueType::Bool)]
+    );
+    assert_eq!(
+        type_mismatches(r#"(defwidget foo [count:number] (label :text count)) (defwidget bar [flag:bool] (foo :count flag))"#)
+            .unwrap(),
+        vec![(ValueType::Number, ValueType::Bool)]
+    );
+    assert_eq!(
+        type_mismatches(r#"(defwidget foo [items:duration] (box (for item in items (label :text item))))"#).unwrap(),
+        vec![(ValueType::Json, ValueType::Duration)]
+    );
+    assert!(matches!(
+        type_mismatches(r#"(defvar shown:bool "yes")"#),
+        Err(ValidationError::VarTypeMismatch { expected: ArgType::Bool, .. })
+    ));
+}
@@ -1,13 +1,23 @@
 use std::collections::{HashMap, HashSet};
 
-use simplexpr::SimplExpr;
+use simplexpr::{
+    dynval::DynVal,
+    functions::{FunctionRegistry, ValueType},
+    type_check::TypeMismatch,
+    SimplExpr,
+};
 
 use crate::{
     error::AstResult,
     parser::{ast::Ast, ast_iterator::AstIterator, from_ast::FromAst},
 };
 
-use super::{widget_definition::WidgetDefinition, widget_use::WidgetUse, Config};
+use super::{
+    widget_definition::{ArgType, AttrSpec, WidgetDefinition},
+    widget_use::{BasicWidgetUse, WidgetUse},
+    window_definition::WindowDefinition,
+    Config,
+};
 use eww_shared_util::{AttrName, Span, Spanned, VarName};
 
 #[derive(Debug, thiserror::Error)]
@@ -25,6 +35,26 @@ pub enum ValidationError {
         /// True if the error occurred inside a widget definition, false if it occurred in a window definition
         in_definition: bool,
     },
+
+    #[error("`children` can only be used inside of a widget definition")]
+    ChildrenOutsideDefinition(Span),
+
+    #[error("Widget `{widget_name}` does not accept any children")]
+    UnexpectedChildren { widget_name: String, def_span: Span, children_span: Span },
+
+    #[error("Argument `{arg_name}` of widget `{widget_name}` must be of type `{expected}`, but got `{value}`")]
+    ArgTypeMismatch {
+        widget_name: String,
+        arg_name: AttrName,
+        expected: ArgType,
+        value: DynVal,
+        /// Where the value is given in the config, or `None` if it was given from outside of it, like via `--arg`
+        value_span: Option<Span>,
+        arg_span: Span,
+    },
+
+    #[error("Initial value of variable `{name}` must be of type `{expected}`, but got `{value}`")]
+    VarTypeMismatch { name: VarName, expected: ArgType, value: DynVal, span: Span },
 }
 
 impl Spanned for ValidationError {
@@ -33,35 +63,87 @@ impl Spanned for ValidationError {
             ValidationError::UnknownWidget(span, _) => *span,
             ValidationError::MissingAttr { use_span, .. } => *use_span,
             ValidationError::UnknownVariable { span, .. } => *span,
+            ValidationError::ChildrenOutsideDefinition(span) => *span,
+            ValidationError::UnexpectedChildren { children_span, .. } => *children_span,
+            ValidationError::ArgTypeMismatch { value_span, arg_span, .. } => value_span.unwrap_or(*arg_span),
+            ValidationError::VarTypeMismatch { span, .. } => *span,
         }
     }
 }
 
-pub fn validate(config: &Config, additional_globals: Vec<VarName>) -> Result<(), ValidationError> {
+/// Types of values that are provided from outside of the config, used when checking expressions for likely type mismatches
+#[derive(Debug, Clone, Default)]
+pub struct KnownTypes {
+    /// Variables that are available in addition to the ones defined in the config, such as the magic variables
+    pub globals: HashMap<VarName, ValueType>,
+    /// The types of the attributes of builtin widgets, by widget name
+    pub widget_attrs: HashMap<String, HashMap<AttrName, ValueType>>,
+}
+
+/// Validate the config, returning an error for anything that would definitely fail,
+/// and a list of likely type mismatches, which may be reported as warnings.
+pub fn validate(config: &Config, known_types: &KnownTypes) -> Result<Vec<TypeMismatch>, ValidationError> {
     let var_names = std::iter::empty()
-        .chain(additional_globals.iter().cloned())
+        .chain(known_types.globals.keys().cloned())
         .chain(config.script_vars.keys().cloned())
         .chain(config.var_definitions.keys().cloned())
         .collect();
+    for var in config.var_definitions.values() {
+        match var.value_type {
+            Some(expected) if expected.check(&var.initial_value).is_err() => {
+                return Err(ValidationError::VarTypeMismatch {
+                    name: var.name.clone(),
+                    expected,
+                    value: var.initial_value.clone(),
+                    span: var.span,
+                })
+            }
+            _ => {}
+        }
+    }
     for window in config.window_definitions.values() {
-        validate_variables_in_widget_use(&config.widget_definitions, &var_names, &window.widget, false)?;
+        validate_window_definition(&config.widget_definitions, &var_names, window)?;
     }
     for def in config.widget_definitions.values() {
         validate_widget_definition(&config.widget_definitions, &var_names, &def)?;
     }
-    Ok(())
+    Ok(check_types(config, known_types))
 }
 
-pub fn validate_widget_definition(
-    other_defs: &HashMap<String, WidgetDefinition>,
+pub fn validate_window_definition(
+    defs: &HashMap<String, WidgetDefinition>,
     globals: &HashSet<VarName>,
-    def: &WidgetDefinition,
+    window: &WindowDefinition,
 ) -> Result<(), ValidationError> {
+    let variables_in_scope = validate_arg_list(globals, &window.name, &window.expected_args)?;
+    validate_variables_in_widget_use(defs, &variables_in_scope, &window.widget, false)
+}
+
+/// Validate the default values of the given arguments,
+/// returning the variables that are in scope for the widget tree they are declared for.
+fn validate_arg_list(globals: &HashSet<VarName>, name: &str, args: &[AttrSpec]) -> Result<HashSet<VarName>, ValidationError> {
     let mut variables_in_scope = globals.clone();
-    for arg in def.expected_args.iter() {
+    for arg in args.iter() {
         variables_in_scope.insert(VarName(arg.name.to_string()));
     }
 
+    // default values may only refer to global variables
+    for arg in args.iter() {
+        if let Some(default) = &arg.default {
+            validate_variables_in_expr(globals, default, true)?;
+            validate_constant_arg_type(name, arg, default)?;
+        }
+    }
+    Ok(variables_in_scope)
+}
+
+pub fn validate_widget_definition(
+    other_defs: &HashMap<String, WidgetDefinition>,
+    globals: &HashSet<VarName>,
+    def: &WidgetDefinition,
+) -> Result<(), ValidationError> {
+    let variables_in_scope = validate_arg_list(globals, &def.name, &def.expected_args)?;
+
     validate_variables_in_widget_use(other_defs, &variables_in_scope, &def.widget, true)
 }
 
@@ -70,6 +152,85 @@ pub fn validate_variables_in_widget_use(
     variables: &HashSet<VarName>,
     widget: &WidgetUse,
     is_in_definition: bool,
+) -> Result<(), ValidationError> {
+    match widget {
+        WidgetUse::Basic(widget) => validate_basic_widget_use(defs, variables, widget, is_in_definition),
+        WidgetUse::Loop(widget) => {
+            validate_variables_in_expr(variables, &widget.elements_expr, is_in_definition)?;
+            let mut body_variables = variables.clone();
+            body_variables.insert(widget.element_name.clone());
+            validate_variables_in_widget_use(defs, &body_variables, &widget.body, is_in_definition)
+        }
+        WidgetUse::Children(widget) => {
+            if !is_in_definition {
+                return Err(ValidationError::ChildrenOutsideDefinition(widget.span));
+            }
+            if let Some(nth_expr) = &widget.nth_expr {
+                validate_variables_in_expr(variables, nth_expr, is_in_definition)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Check that the given value matches the declared type of an argument, if any.
+/// Values that aren't given in the config, like the arguments passed to a window via `--arg`, don't have a `value_span`.
+pub fn validate_arg_type(
+    widget_name: &str,
+    arg: &AttrSpec,
+    value: &DynVal,
+    value_span: Option<Span>,
+) -> Result<(), ValidationError> {
+    match arg.arg_type {
+        Some(arg_type) if arg_type.check(value).is_err() => Err(ValidationError::ArgTypeMismatch {
+            widget_name: widget_name.to_string(),
+            arg_name: arg.name.clone(),
+            expected: arg_type,
+            value: value.clone(),
+            value_span,
+            arg_span: arg.span,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Check the type of a value given to an argument, if that value is known at config load time
+fn validate_constant_arg_type(widget_name: &str, arg: &AttrSpec, expr: &SimplExpr) -> Result<(), ValidationError> {
+    if arg.arg_type.is_none() || !expr.var_refs().is_empty() {
+        return Ok(());
+    }
+    match expr.eval_no_vars() {
+        Ok(value) => validate_arg_type(widget_name, arg, &value, Some(expr.span())),
+        Err(_) => Ok(()),
+    }
+}
+
+fn validate_variables_in_expr(
+    variables: &HashSet<VarName>,
+    expr: &SimplExpr,
+    is_in_definition: bool,
+) -> Result<(), ValidationError> {
+    let unknown_var = expr.var_refs().into_iter().find(|(_, var_ref)| !variables.contains(*var_ref));
+    if let Some((span, var)) = unknown_var {
+        return Err(ValidationError::UnknownVariable { span, name: var.clone(), in_definition: is_in_definition });
+    }
+    Ok(())
+}
+
+/// Check if the given widget contains a `(children)` placeholder anywhere in its tree
+fn contains_children_placeholder(widget: &WidgetUse) -> bool {
+    match widget {
+        WidgetUse::Basic(widget) => widget.children.iter().any(contains_children_placeholder),
+        WidgetUse::Loop(widget) => contains_children_placeholder(&widget.body),
+        WidgetUse::Children(_) => true,
+    }
+}
+
+fn validate_basic_widget_use(
+    defs: &HashMap<String, WidgetDefinition>,
+    variables: &HashSet<VarName>,
+    widget: &BasicWidgetUse,
+    is_in_definition: bool,
 ) -> Result<(), ValidationError> {
     let matching_definition = defs.get(&widget.name);
     if let Some(matching_def) = matching_definition {
@@ -85,6 +246,18 @@ pub fn validate_variables_in_widget_use(
                 use_span: widget.attrs.span,
             });
         }
+        for expected in matching_def.expected_args.iter() {
+            if let Some(expr) = widget.attrs.attrs.get(&expected.name).and_then(|attr| attr.value.as_simplexpr().ok()) {
+                validate_constant_arg_type(&widget.name, expected, &expr)?;
+            }
+        }
+        if !widget.children.is_empty() && !contains_children_placeholder(&matching_def.widget) {
+            return Err(ValidationError::UnexpectedChildren {
+                widget_name: widget.name.clone(),
+                def_span: matching_def.span,
+                children_span: widget.children_span(),
+            });
+        }
     }
 
     let values = widget.attrs.attrs.values();
@@ -106,3 +279,88 @@ pub fn validate_variables_in_widget_use(
 
     Ok(())
 }
+
+/// Check all expressions in the config for values that are used as a type they most likely can't be converted to.
+fn check_types(config: &Config, known_types: &KnownTypes) -> Vec<TypeMismatch> {
+    let mut globals = known_types.globals.clone();
+    globals.extend(config.script_vars.keys().map(|name| (name.clone(), ValueType::Any)));
+    globals.extend(
+        config.var_definitions.values().map(|var| (var.name.clone(), var.value_type.map_or(ValueType::Any, ValueType::from))),
+    );
+
+    let mut checker = TypeChecker { defs: &config.widget_definitions, known_types, mismatches: Vec::new() };
+    for window in config.window_definitions.values() {
+        let variables = checker.check_arg_list(&globals, &window.expected_args);
+        checker.check_widget_use(&variables, &window.widget);
+    }
+    for def in config.widget_definitions.values() {
+        let variables = checker.check_arg_list(&globals, &def.expected_args);
+        checker.check_widget_use(&variables, &def.widget);
+    }
+    // definitions are stored in hashmaps, so sort the results to report them in a consistent order
+    checker.mismatches.sort_by_key(|mismatch| (mismatch.span.2, mismatch.span.0, mismatch.span.1));
+    checker.mismatches
+}
+
+struct TypeChecker<'a> {
+    defs: &'a HashMap<String, WidgetDefinition>,
+    known_types: &'a KnownTypes,
+    mismatches: Vec<TypeMismatch>,
+}
+
+impl TypeChecker<'_> {
+    fn check_expr(&mut self, variables: &HashMap<VarName, ValueType>, expr: &SimplExpr, expected: ValueType) {
+        self.mismatches.extend(expr.check_type(expected, variables, FunctionRegistry::builtins()));
+    }
+
+    /// Check the default values of the given arguments, returning the types of the variables in scope for the widget tree they are declared for.
+    fn check_arg_list(&mut self, globals: &HashMap<VarName, ValueType>, args: &[AttrSpec]) -> HashMap<VarName, ValueType> {
+        let mut variables = globals.clone();
+        for arg in args {
+            let arg_type = arg.arg_type.map_or(ValueType::Any, ValueType::from);
+            if let Some(default) = &arg.default {
+                self.check_expr(globals, default, arg_type);
+            }
+            variables.insert(VarName(arg.name.to_string()), arg_type);
+        }
+        variables
+    }
+
+    fn check_widget_use(&mut self, variables: &HashMap<VarName, ValueType>, widget: &WidgetUse) {
+        match widget {
+            WidgetUse::Basic(widget) => {
+                for (attr_name, attr) in widget.attrs.attrs.iter() {
+                    if let Ok(expr) = attr.value.as_simplexpr() {
+                        let expected = self.attr_type(&widget.name, attr_name);
+                        self.check_expr(variables, &expr, expected);
+                    }
+                }
+                for child in widget.children.iter() {
+                    self.check_widget_use(variables, child);
+                }
+            }
+            WidgetUse::Loop(widget) => {
+                self.check_expr(variables, &widget.elements_expr, ValueType::Json);
+                let mut body_variables = variables.clone();
+                body_variables.insert(widget.element_name.clone(), ValueType::Any);
+                self.check_widget_use(&body_variables, &widget.body);
+            }
+            WidgetUse::Children(widget) => {
+                if let Some(nth_expr) = &widget.nth_expr {
+                    self.check_expr(variables, nth_expr, ValueType::Int);
+                }
+            }
+        }
+    }
+
+    /// The type an attribute of the given widget expects, taken from the widget definition or the known builtin widgets
+    fn attr_type(&self, widget_name: &str, attr_name: &AttrName) -> ValueType {
+        let declared_type = match self.defs.get(widget_name) {
+            Some(def) => {
+                def.expected_args.iter().find(|arg| &arg.name == attr_name).and_then(|arg| arg.arg_type.map(ValueType::from))
+            }
+            None => self.known_types.widget_attrs.get(widget_name).and_then(|attrs| attrs.get(attr_name)).copied(),
+        };
+        declared_type.unwrap_or(ValueType::Any)
+    }
+}
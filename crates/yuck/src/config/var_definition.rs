@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use simplexpr::{dynval::DynVal, SimplExpr};
 
+use super::widget_definition::{parse_name_and_type, ArgType};
 use crate::{
     error::{AstResult, AstResultExt},
     parser::{
@@ -16,6 +17,8 @@ use eww_shared_util::{AttrName, Span, VarName};
 pub struct VarDefinition {
     pub name: VarName,
     pub initial_value: DynVal,
+    /// The type declared via `(defvar name:type ...)`, if any
+    pub value_type: Option<ArgType>,
     pub span: Span,
 }
 
@@ -24,10 +27,11 @@ impl FromAstElementContent for VarDefinition {
 
     fn from_tail<I: Iterator<Item = Ast>>(span: Span, mut iter: AstIterator<I>) -> AstResult<Self> {
         let result: AstResult<_> = try {
-            let (_, name) = iter.expect_symbol()?;
+            let (name_span, symbol) = iter.expect_symbol()?;
+            let (name, value_type) = parse_name_and_type(name_span, &symbol)?;
             let (_, initial_value) = iter.expect_literal()?;
             iter.expect_done()?;
-            Self { name: VarName(name), initial_value, span }
+            Self { name: VarName(name.to_string()), initial_value, value_type, span }
         };
         result.note(r#"Expected format: `(defvar name "initial-value")`"#)
     }
@@ -1,9 +1,14 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, str::FromStr};
 
-use simplexpr::SimplExpr;
+use simplexpr::{
+    dynval::{ConversionError, DynVal},
+    functions::ValueType,
+    SimplExpr,
+};
 
 use crate::{
-    error::{AstError::WrongExprType, AstResult, AstResultExt, FormFormatError},
+    enum_parse,
+    error::{AstError, AstError::WrongExprType, AstResult, AstResultExt, FormFormatError},
     parser::{
         ast::Ast,
         ast_iterator::AstIterator,
@@ -12,22 +17,119 @@ use crate::{
 };
 use eww_shared_util::{AttrName, Span, Spanned, VarName};
 
-use super::widget_use::WidgetUse;
+use super::{widget_use::WidgetUse, window_definition::EnumParseError};
+
+/// The type of an argument, as declared via `name:type` in an argument list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display, serde::Serialize)]
+pub enum ArgType {
+    #[display(fmt = "string")]
+    String,
+    #[display(fmt = "number")]
+    Number,
+    #[display(fmt = "bool")]
+    Bool,
+    #[display(fmt = "json")]
+    Json,
+    #[display(fmt = "duration")]
+    Duration,
+}
+
+impl FromStr for ArgType {
+    type Err = EnumParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        enum_parse! { "ArgType", s,
+            "string" => ArgType::String,
+            "number" => ArgType::Number,
+            "bool" => ArgType::Bool,
+            "json" => ArgType::Json,
+            "duration" => ArgType::Duration,
+        }
+    }
+}
+
+impl ArgType {
+    /// Check that the given value can be used as a value of this type
+    pub fn check(&self, value: &DynVal) -> Result<(), ConversionError> {
+        match self {
+            ArgType::String => Ok(()),
+            ArgType::Number => value.as_f64().map(|_| ()),
+            ArgType::Bool => value.as_bool().map(|_| ()),
+            ArgType::Json => value.as_json_value().map(|_| ()),
+            ArgType::Duration => value.as_duration().map(|_| ()),
+        }
+    }
+}
+
+impl From<ArgType> for ValueType {
+    fn from(arg_type: ArgType) -> Self {
+        match arg_type {
+            ArgType::String => ValueType::String,
+            ArgType::Number => ValueType::Number,
+            ArgType::Bool => ValueType::Bool,
+            ArgType::Json => ValueType::Json,
+            ArgType::Duration => ValueType::Duration,
+        }
+    }
+}
+
+/// Parse a `name` or `name:type` symbol into the name and the optional type
+pub(super) fn parse_name_and_type(span: Span, symbol: &str) -> AstResult<(&str, Option<ArgType>)> {
+    match symbol.split_once(':') {
+        Some((name, arg_type)) => {
+            let arg_type = arg_type.parse().map_err(|e| AstError::Other(span, Box::new(e)))?;
+            Ok((name, Some(arg_type)))
+        }
+        None => Ok((symbol, None)),
+    }
+}
 
 #[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
 pub struct AttrSpec {
     pub name: AttrName,
     pub optional: bool,
+    /// The value used when the argument is not provided. Only present for optional arguments.
+    pub default: Option<SimplExpr>,
+    pub arg_type: Option<ArgType>,
     pub span: Span,
 }
 
+impl AttrSpec {
+    /// Parse the `(name default)` list of an optional argument with a default value, as in `?(name default)`
+    fn from_default_list(span: Span, list: Ast) -> AstResult<Self> {
+        let mut iter = list.try_ast_iter()?;
+        let (name_span, symbol) = iter.expect_symbol()?;
+        let (name, arg_type) = parse_name_and_type(name_span, &symbol)?;
+        let default = iter.expect_any().and_then(SimplExpr::from_ast)?;
+        iter.expect_done()?;
+        Ok(Self { name: AttrName(name.to_string()), optional: true, default: Some(default), arg_type, span })
+    }
+}
+
 impl FromAst for AttrSpec {
     fn from_ast(e: Ast) -> AstResult<Self> {
         let span = e.span();
         let symbol = e.as_symbol()?;
-        let (name, optional) = if let Some(name) = symbol.strip_prefix('?') { (name.to_string(), true) } else { (symbol, false) };
-        Ok(Self { name: AttrName(name), optional, span })
+        let (symbol, optional) = if let Some(name) = symbol.strip_prefix('?') { (name, true) } else { (symbol.as_str(), false) };
+        let (name, arg_type) = parse_name_and_type(span, symbol)?;
+        Ok(Self { name: AttrName(name.to_string()), optional, default: None, arg_type, span })
+    }
+}
+
+/// Parse the argument list of a widget definition, such as `[name ?(size "12") count:number]`
+pub fn parse_arg_list(args: Vec<Ast>) -> AstResult<Vec<AttrSpec>> {
+    let mut specs = Vec::new();
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.next() {
+        match arg {
+            Ast::Symbol(span, symbol) if symbol == "?" && matches!(args.peek(), Some(Ast::List(..))) => {
+                let list = args.next().unwrap();
+                specs.push(AttrSpec::from_default_list(span.to(list.span()), list)?);
+            }
+            arg => specs.push(AttrSpec::from_ast(arg)?),
+        }
     }
+    Ok(specs)
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
@@ -48,7 +150,7 @@ impl FromAstElementContent for WidgetDefinition {
             .expect_array()
             .wrong_expr_type_to(|_, _| Some(FormFormatError::WidgetDefArglistMissing(name_span.point_span_at_end())))
             .note(EXPECTED_WIDGET_DEF_FORMAT)?;
-        let expected_args = expected_args.into_iter().map(AttrSpec::from_ast).collect::<AstResult<_>>()?;
+        let expected_args = parse_arg_list(expected_args)?;
         let widget = iter.expect_any().note(EXPECTED_WIDGET_DEF_FORMAT).and_then(WidgetUse::from_ast)?;
         iter.expect_done().map_err(|e| FormFormatError::WidgetDefMultipleChildren(e.span()))?;
         Ok(Self { name, expected_args, widget, span, args_span })
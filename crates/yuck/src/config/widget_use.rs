@@ -4,15 +4,22 @@ use simplexpr::SimplExpr;
 
 use crate::{
     config::attributes::AttrEntry,
-    error::{AstError, AstResult},
+    error::{AstError, AstResult, AstResultExt, FormFormatError},
     parser::{ast::Ast, ast_iterator::AstIterator, from_ast::FromAst},
 };
-use eww_shared_util::{AttrName, Span, VarName};
+use eww_shared_util::{AttrName, Span, Spanned, VarName};
 
 use super::attributes::Attributes;
 
 #[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
-pub struct WidgetUse {
+pub enum WidgetUse {
+    Basic(BasicWidgetUse),
+    Loop(LoopWidgetUse),
+    Children(ChildrenWidgetUse),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+pub struct BasicWidgetUse {
     pub name: String,
     pub attrs: Attributes,
     pub children: Vec<WidgetUse>,
@@ -20,12 +27,41 @@ pub struct WidgetUse {
     pub name_span: Span,
 }
 
-impl WidgetUse {
+/// A `(for element in elements (widget ...))` form, which renders its body once for every element of a json array.
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+pub struct LoopWidgetUse {
+    pub element_name: VarName,
+    pub elements_expr: SimplExpr,
+    pub elements_expr_span: Span,
+    pub body: Box<WidgetUse>,
+    pub span: Span,
+}
+
+/// A `(children)` placeholder within a widget definition,
+/// which is replaced by the children that are given to the defined widget.
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+pub struct ChildrenWidgetUse {
+    pub span: Span,
+    /// Selects a single child by its index, instead of inserting all of them
+    pub nth_expr: Option<SimplExpr>,
+}
+
+impl BasicWidgetUse {
     pub fn children_span(&self) -> Span {
         if self.children.is_empty() {
             self.span.point_span_at_end().shifted(-1)
         } else {
-            self.children.first().unwrap().span.to(self.children.last().unwrap().span)
+            self.children.first().unwrap().span().to(self.children.last().unwrap().span())
+        }
+    }
+}
+
+impl Spanned for WidgetUse {
+    fn span(&self) -> Span {
+        match self {
+            WidgetUse::Basic(w) => w.span,
+            WidgetUse::Loop(w) => w.span,
+            WidgetUse::Children(w) => w.span,
         }
     }
 }
@@ -34,19 +70,61 @@ impl FromAst for WidgetUse {
     fn from_ast(e: Ast) -> AstResult<Self> {
         let span = e.span();
         if let Ok(value) = e.clone().as_simplexpr() {
-            Ok(label_from_simplexpr(value, span))
+            Ok(WidgetUse::Basic(label_from_simplexpr(value, span)))
         } else {
             let mut iter = e.try_ast_iter()?;
             let (name_span, name) = iter.expect_symbol()?;
-            let attrs = iter.expect_key_values()?;
-            let children = iter.map(WidgetUse::from_ast).collect::<AstResult<Vec<_>>>()?;
-            Ok(Self { name, attrs, children, span, name_span })
+            match name.as_ref() {
+                "for" => Ok(WidgetUse::Loop(LoopWidgetUse::from_iter(span, iter)?)),
+                "children" => Ok(WidgetUse::Children(ChildrenWidgetUse::from_iter(span, iter)?)),
+                _ => Ok(WidgetUse::Basic(BasicWidgetUse::from_iter(span, name, name_span, iter)?)),
+            }
         }
     }
 }
 
-fn label_from_simplexpr(value: SimplExpr, span: Span) -> WidgetUse {
-    WidgetUse {
+impl BasicWidgetUse {
+    fn from_iter<I: Iterator<Item = Ast>>(
+        span: Span,
+        name: String,
+        name_span: Span,
+        mut iter: AstIterator<I>,
+    ) -> AstResult<Self> {
+        let attrs = iter.expect_key_values()?;
+        let children = iter.map(WidgetUse::from_ast).collect::<AstResult<Vec<_>>>()?;
+        Ok(Self { name, attrs, children, span, name_span })
+    }
+}
+
+impl LoopWidgetUse {
+    fn from_iter<I: Iterator<Item = Ast>>(span: Span, mut iter: AstIterator<I>) -> AstResult<Self> {
+        let (_, element_name) = iter.expect_symbol().note(EXPECTED_LOOP_FORMAT)?;
+        let (in_span, in_string) = iter.expect_symbol().note(EXPECTED_LOOP_FORMAT)?;
+        if in_string != "in" {
+            return Err(FormFormatError::ExpectedInInForLoop(in_span, in_string).into());
+        }
+        let elements_value = iter.expect_any().note(EXPECTED_LOOP_FORMAT)?;
+        let elements_expr_span = elements_value.span();
+        let elements_expr = SimplExpr::from_ast(elements_value)?;
+        let body = iter.expect_any().note(EXPECTED_LOOP_FORMAT).and_then(WidgetUse::from_ast)?;
+        iter.expect_done().note("A for-loop may only contain a single body widget")?;
+        Ok(Self { element_name: VarName(element_name), elements_expr, elements_expr_span, body: Box::new(body), span })
+    }
+}
+
+impl ChildrenWidgetUse {
+    fn from_iter<I: Iterator<Item = Ast>>(span: Span, mut iter: AstIterator<I>) -> AstResult<Self> {
+        let mut attrs = iter.expect_key_values()?;
+        let nth_expr = attrs.ast_optional("nth")?;
+        iter.expect_done().note("`children` cannot be given any children itself")?;
+        Ok(Self { span, nth_expr })
+    }
+}
+
+static EXPECTED_LOOP_FORMAT: &str = r#"Expected format: `(for element in elements (widget ...))`"#;
+
+fn label_from_simplexpr(value: SimplExpr, span: Span) -> BasicWidgetUse {
+    BasicWidgetUse {
         name: "label".to_string(),
         name_span: span.point_span(),
         attrs: Attributes::new(
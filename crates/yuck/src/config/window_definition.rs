@@ -13,11 +13,18 @@ use crate::{
 };
 use eww_shared_util::{AttrName, Span, VarName};
 
-use super::{backend_window_options::BackendWindowOptions, widget_use::WidgetUse, window_geometry::WindowGeometry};
+use super::{
+    backend_window_options::BackendWindowOptions,
+    widget_definition::{parse_arg_list, AttrSpec},
+    widget_use::WidgetUse,
+    window_geometry::WindowGeometry,
+};
 
 #[derive(Debug, Clone, serde::Serialize, PartialEq, Eq)]
 pub struct WindowDefinition {
     pub name: String,
+    /// Arguments that are passed when opening the window, and are available as variables within its widget tree
+    pub expected_args: Vec<AttrSpec>,
     pub geometry: Option<WindowGeometry>,
     pub stacking: WindowStacking,
     pub monitor_number: Option<i32>,
@@ -31,6 +38,11 @@ impl FromAstElementContent for WindowDefinition {
 
     fn from_tail<I: Iterator<Item = Ast>>(span: Span, mut iter: AstIterator<I>) -> AstResult<Self> {
         let (_, name) = iter.expect_symbol()?;
+        // the argument list is optional for windows
+        let expected_args = match iter.expect_array() {
+            Ok((_, args)) => parse_arg_list(args)?,
+            Err(_) => Vec::new(),
+        };
         let mut attrs = iter.expect_key_values()?;
         let monitor_number = attrs.primitive_optional("monitor")?;
         let resizable = attrs.primitive_optional("resizable")?.unwrap_or(true);
@@ -39,7 +51,7 @@ impl FromAstElementContent for WindowDefinition {
         let backend_options = BackendWindowOptions::from_attrs(&mut attrs)?;
         let widget = iter.expect_any().and_then(WidgetUse::from_ast)?;
         iter.expect_done()?;
-        Ok(Self { name, monitor_number, resizable, widget, stacking, geometry, backend_options })
+        Ok(Self { name, expected_args, monitor_number, resizable, widget, stacking, geometry, backend_options })
     }
 }
 
@@ -70,7 +82,7 @@ macro_rules! enum_parse {
         match input.as_str() {
             $( $( $s )|* => Ok($val) ),*,
             _ => Err(EnumParseError {
-                input: input,
+                input,
                 expected: vec![$($($s),*),*],
             })
         }
@@ -170,6 +170,9 @@ pub enum FormFormatError {
 
     #[error("Widget definition has more than one child widget")]
     WidgetDefMultipleChildren(Span),
+
+    #[error("Expected `in` in this position, but got `{1}`")]
+    ExpectedInInForLoop(Span, String),
 }
 
 impl Spanned for FormFormatError {
@@ -177,6 +180,7 @@ impl Spanned for FormFormatError {
         match self {
             FormFormatError::WidgetDefArglistMissing(span) => *span,
             FormFormatError::WidgetDefMultipleChildren(span) => *span,
+            FormFormatError::ExpectedInInForLoop(span, _) => *span,
         }
     }
 }
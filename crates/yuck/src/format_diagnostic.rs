@@ -194,6 +194,42 @@ impl ToDiagnostic for ValidationError {
 
                 diag.with_notes(extra_notes)
             }
+            ValidationError::ChildrenOutsideDefinition(span) => gen_diagnostic! {
+                msg = self,
+                label = span => "Used here",
+                note = "`children` is a placeholder for the children given to a user-defined widget",
+            },
+            ValidationError::UnexpectedChildren { widget_name, def_span, children_span } => Diagnostic::error()
+                .with_message(self.to_string())
+                .with_label(span_to_primary_label(*children_span).with_message("Children given here"))
+                .with_label(span_to_secondary_label(*def_span).with_message("Widget defined here"))
+                .with_notes(vec!["Hint: place a `(children)` placeholder in the definition of the widget".to_string()]),
+            ValidationError::ArgTypeMismatch { expected, value_span: Some(value_span), arg_span, .. } => Diagnostic::error()
+                .with_message(self.to_string())
+                .with_label(
+                    span_to_primary_label(*value_span).with_message(format!("Expected a value of type `{}` here", expected)),
+                )
+                .with_label(span_to_secondary_label(*arg_span).with_message("Type declared here")),
+            ValidationError::ArgTypeMismatch { arg_name, expected, value, value_span: None, arg_span, .. } => gen_diagnostic! {
+                msg = self,
+                label = arg_span => format!("`{}` is declared with type `{}` here", arg_name, expected),
+                note = format!("The value was passed via `--arg {}={}`", arg_name, value),
+            },
+            ValidationError::VarTypeMismatch { expected, span, .. } => gen_diagnostic! {
+                msg = self,
+                label = span => format!("Expected a value of type `{}` here", expected),
+            },
+        }
+    }
+}
+
+impl ToDiagnostic for simplexpr::type_check::TypeMismatch {
+    fn to_diagnostic(&self) -> Diagnostic<usize> {
+        gen_diagnostic! {
+            kind = Severity::Warning,
+            msg = "Value is likely of the wrong type",
+            label = self.span => format!("Expected a value of type `{}` here", self.expected),
+            note = format!("Expected: {}\n     Got: {}", self.expected, self.found),
         }
     }
 }
@@ -238,7 +274,14 @@ impl ToDiagnostic for simplexpr::error::Error {
 
 impl ToDiagnostic for simplexpr::parser::lexer::LexicalError {
     fn to_diagnostic(&self) -> Diagnostic<usize> {
-        generate_lexical_error_diagnostic(self.span())
+        use simplexpr::parser::lexer::LexicalError::*;
+        match self {
+            InvalidToken(span) => generate_lexical_error_diagnostic(*span),
+            UnknownFormatSpec(span, _) => gen_diagnostic! {
+                msg = self,
+                label = span => "Unknown format spec",
+            },
+        }
     }
 }
 
@@ -259,6 +302,16 @@ impl ToDiagnostic for simplexpr::eval::EvalError {
                 notes.push(format!("Hint: If you meant to use the literal value \"{}\", surround the value in quotes", name));
                 gen_diagnostic!(self).with_notes(notes)
             }
+            UnknownFunction(_, similar) => {
+                let notes = match similar.as_slice() {
+                    [] => Vec::new(),
+                    [function] => vec![format!("Did you mean `{}`?", function)],
+                    functions => {
+                        vec![format!("Did you mean one of: {}?", functions.iter().map(|x| format!("`{}`", x)).join(", "))]
+                    }
+                };
+                gen_diagnostic!(self).with_notes(notes)
+            }
             Spanned(span, error) => error.as_ref().to_diagnostic().with_label(span_to_primary_label(*span)),
             _ => gen_diagnostic!(self, self.span()),
         }
@@ -298,6 +351,11 @@ impl ToDiagnostic for FormFormatError {
                         To include multiple elements, wrap these elements in a single container widget such as `box`.\n\
                         This is necessary as eww can't know how you want these elements to be layed out otherwise."
             },
+            FormFormatError::ExpectedInInForLoop(span, got) => gen_diagnostic! {
+                msg = self,
+                label = span => format!("Expected `in` here, got `{}`", got),
+                note = "Expected format: `(for element in elements (widget ...))`",
+            },
         }
     }
 }
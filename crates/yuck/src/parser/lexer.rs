@@ -90,7 +90,7 @@ impl Lexer {
                 self.advance_until_char_boundary();
                 Some(Ok((lo, Token::SimplExpr(vec![(lo, simplexpr::parser::lexer::Token::StringLit(segments), hi)]), hi)))
             }
-            Some(Err(e)) => Some(Err(parse_error::ParseError::LexicalError(e.0))),
+            Some(Err(e)) => Some(Err(parse_error::ParseError::LexicalError(e.span()))),
             None => None,
         }
     }